@@ -0,0 +1,13 @@
+use std::io;
+use std::process;
+
+// The Asa language server. Editors start it and talk to it over stdin and stdout.
+fn main() {
+  match asalang::lsp::serve(io::stdin().lock(), io::stdout()) {
+    Ok(code) => process::exit(code),
+    Err(e) => {
+      eprintln!("error: {}", e);
+      process::exit(1);
+    },
+  }
+}
@@ -0,0 +1,271 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+use crate::interpreter::{Arity, Interpreter};
+use crate::lexer::Span;
+use crate::parser::Node;
+use crate::typecheck::Type;
+
+// A static pass over a parsed program that runs before it is executed. The interpreter only finds an
+// undefined name or a wrong number of arguments when execution happens to reach it; the checker
+// looks at the whole tree up front and reports every problem it can see, each with its location.
+//
+// Names are resolved the way the interpreter resolves them: a function body sees its own parameters
+// and lets, constants and functions are visible everywhere, and the names the embedding host has
+// already defined (built-ins, natives, earlier programs) count as defined. Names qualified with the
+// namespace of an imported module can't be checked without loading it, so they are accepted.
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Severity {
+  Error,
+  Warning,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum FindingKind {
+  UndefinedVariable(String),
+  UndefinedFunction(String),
+  DuplicateFunction(String),
+  ArityMismatch { name: String, expected: Arity, found: usize },
+  // Statements after a return. A return doesn't leave the function: they still run, and the value of
+  // the last statement is what the function returns.
+  CodeAfterReturn,
+  UnusedVariable(String),
+  // Reported by the type checker
+  TypeMismatch { expected: Type, found: Type },
+  InvalidOperand { operator: String, found: Type },
+  BranchMismatch { then: Type, otherwise: Type },
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Finding {
+  pub kind: FindingKind,
+  pub span: Span,
+}
+
+impl Finding {
+  // Errors would make the program fail at run time; warnings point at code that is probably a mistake.
+  pub fn severity(&self) -> Severity {
+    match self.kind {
+      FindingKind::CodeAfterReturn | FindingKind::UnusedVariable(_) => Severity::Warning,
+      _ => Severity::Error,
+    }
+  }
+}
+
+impl fmt::Display for Severity {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Severity::Error => write!(f, "error"),
+      Severity::Warning => write!(f, "warning"),
+    }
+  }
+}
+
+impl fmt::Display for FindingKind {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      FindingKind::UndefinedVariable(name) => write!(f, "undefined variable '{}'", name),
+      FindingKind::UndefinedFunction(name) => write!(f, "undefined function '{}'", name),
+      FindingKind::DuplicateFunction(name) => write!(f, "function '{}' is defined more than once", name),
+      FindingKind::ArityMismatch{name, expected, found} => write!(f, "'{}' expects {} argument(s) but is called with {}", name, expected, found),
+      FindingKind::CodeAfterReturn => write!(f, "code after return still runs, and the last statement's value is returned instead"),
+      FindingKind::UnusedVariable(name) => write!(f, "unused variable '{}'", name),
+      FindingKind::TypeMismatch{expected, found} => write!(f, "mismatched types: expected {}, found {}", expected, found),
+      FindingKind::InvalidOperand{operator, found} => write!(f, "'{}' can't be applied to {}", operator, found),
+      FindingKind::BranchMismatch{then, otherwise} => write!(f, "if branches have different types: {} and {}", then, otherwise),
+    }
+  }
+}
+
+impl fmt::Display for Finding {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}: {}: {}", self.span, self.severity(), self.kind)
+  }
+}
+
+// Checks a program before it is passed to Interpreter::exec. Findings are sorted by location.
+pub fn check(tree: &Node, interpreter: &Interpreter) -> Vec<Finding> {
+  let mut checker = Checker {
+    interpreter,
+    functions: HashMap::new(),
+    constants: Vec::new(),
+    namespaces: Vec::new(),
+    findings: Vec::new(),
+  };
+  checker.declare(tree.children());
+  // Top-level lets are not reported when unused: they stay in the program's frame, where the host
+  // or a REPL line run later can still read them.
+  let mut scope = Scope::new();
+  for node in tree.children() {
+    checker.visit(node, &mut scope);
+  }
+  let mut findings = checker.findings;
+  findings.sort_by_key(|finding| (finding.span.start_line, finding.span.start_col));
+  findings
+}
+
+struct Binding {
+  name: String,
+  span: Span,
+  used: bool,
+}
+
+type Scope = Vec<Binding>;
+
+struct Checker<'a> {
+  interpreter: &'a Interpreter,
+  // Functions and constants defined by the program being checked
+  functions: HashMap<String, Arity>,
+  constants: Vec<String>,
+  // Namespaces of the modules the program imports
+  namespaces: Vec<String>,
+  findings: Vec<Finding>,
+}
+
+impl<'a> Checker<'a> {
+
+  // Collects the top-level definitions first, so that a function can be called before (above) its definition.
+  fn declare(&mut self, children: &[Node]) {
+    for node in children {
+      let definition = match node {
+        Node::Export{children} => &children[0],
+        node => node,
+      };
+      match definition {
+        Node::FunctionDefine{name, children, span} => {
+          let name = name.to_string();
+          // Like the interpreter, calls go to the last definition.
+          if self.functions.insert(name.clone(), Arity::Exact(children[0].children().len())).is_some() {
+            self.report(FindingKind::DuplicateFunction(name), *span);
+          }
+        },
+        Node::ConstDefine{children} => self.constants.push(name_of(&children[0])),
+        Node::Import{path, ..} => {
+          if let Some(stem) = Path::new(path).file_stem() {
+            self.namespaces.push(stem.to_string_lossy().into_owned());
+          }
+        },
+        _ => (),
+      }
+    }
+  }
+
+  fn visit(&mut self, node: &Node, scope: &mut Scope) {
+    match node {
+      Node::Export{children} => self.visit(&children[0], scope),
+      Node::FunctionDefine{children, ..} => self.function(&children[0], &children[1]),
+      Node::ConstDefine{children} => self.visit(&children[1], scope),
+      Node::VariableDefine{children} | Node::IfAssign{children} => {
+        for child in &children[1..] {
+          self.visit(child, scope);
+        }
+        if let Node::Identifier{value, span} = &children[0] {
+          scope.push(Binding{name: value.to_string(), span: *span, used: false});
+        }
+      },
+      // The catch variable is in scope in the catch and finally blocks. Unlike the lets in the blocks,
+      // it is gone after the try.
+      Node::Try{children, ..} => {
+        self.visit(&children[0], scope);
+        let caught = scope.len();
+        if let Node::Identifier{value, span} = &children[1] {
+          scope.push(Binding{name: value.to_string(), span: *span, used: true});
+        }
+        for child in &children[2..] {
+          self.visit(child, scope);
+        }
+        scope.remove(caught);
+      },
+      Node::Identifier{value, span} => self.variable(value.as_str(), *span, scope),
+      // A field of a variable or constant, or else a name from an imported module.
+      Node::FieldAccess{children, name, span} => match &children[0] {
+        Node::Identifier{value, ..} if !self.bound(value.as_str(), scope) && self.imported(value.as_str()) => (),
+        Node::Identifier{value, ..} if !self.bound(value.as_str(), scope) && !self.is_constant(value.as_str()) => {
+          self.report(FindingKind::UndefinedVariable(format!("{}.{}", value, name)), *span);
+        },
+        value => self.visit(value, scope),
+      },
+      Node::FunctionCall{name, children, span} => {
+        let count = children.last().map_or(0, |args| args.children().len());
+        self.call(name.as_str(), count, *span);
+        for child in children {
+          self.visit(child, scope);
+        }
+      },
+      node => {
+        for child in node.children() {
+          self.visit(child, scope);
+        }
+      },
+    }
+  }
+
+  // A function body is checked in a scope of its own that starts with the parameters.
+  fn function(&mut self, args: &Node, stmts: &Node) {
+    let mut scope: Scope = args.children().iter()
+      .filter_map(|arg| match arg.children().first() {
+        Some(Node::Identifier{value, span}) => Some(Binding{name: value.to_string(), span: *span, used: true}),
+        _ => None,
+      })
+      .collect();
+    let mut returned: Option<Span> = None;
+    for stmt in stmts.children() {
+      if let Some(return_span) = returned.take() {
+        self.report(FindingKind::CodeAfterReturn, stmt.span().unwrap_or(return_span));
+      }
+      self.visit(stmt, &mut scope);
+      if let Node::FunctionReturn{span, ..} = stmt {
+        returned = Some(*span);
+      }
+    }
+    for binding in scope.into_iter().filter(|binding| !binding.used) {
+      self.report(FindingKind::UnusedVariable(binding.name), binding.span);
+    }
+  }
+
+  fn variable(&mut self, name: &str, span: Span, scope: &mut Scope) {
+    if let Some(binding) = scope.iter_mut().rev().find(|binding| binding.name == name) {
+      binding.used = true;
+      return;
+    }
+    if !self.is_constant(name) {
+      self.report(FindingKind::UndefinedVariable(name.to_string()), span);
+    }
+  }
+
+  fn bound(&self, name: &str, scope: &Scope) -> bool {
+    scope.iter().any(|binding| binding.name == name)
+  }
+
+  fn is_constant(&self, name: &str) -> bool {
+    self.constants.iter().any(|constant| constant == name) || self.interpreter.has_constant(name)
+  }
+
+  fn call(&mut self, name: &str, count: usize, span: Span) {
+    match self.functions.get(name).copied().or_else(|| self.interpreter.arity(name)) {
+      Some(expected) if !expected.accepts(count) => {
+        self.report(FindingKind::ArityMismatch{name: name.to_string(), expected, found: count}, span);
+      },
+      Some(_) => (),
+      None if name.split_once('.').is_some_and(|(namespace, _)| self.imported(namespace)) => (),
+      None => self.report(FindingKind::UndefinedFunction(name.to_string()), span),
+    }
+  }
+
+  // True for the namespace of an imported module, like util in util.helper.
+  fn imported(&self, namespace: &str) -> bool {
+    self.namespaces.iter().any(|imported| imported == namespace)
+  }
+
+  fn report(&mut self, kind: FindingKind, span: Span) {
+    self.findings.push(Finding{kind, span});
+  }
+}
+
+fn name_of(node: &Node) -> String {
+  match node {
+    Node::Identifier{value, ..} => value.to_string(),
+    _ => unreachable!(),
+  }
+}
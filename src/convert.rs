@@ -0,0 +1,203 @@
+use std::collections::HashMap;
+use crate::interpreter::Value;
+use crate::error::*;
+
+// Conversions between Rust values and Asa values, used when the host passes arguments into Asa
+// functions or reads results back out. Conversions are fallible: an i64 may not fit into an Asa
+// number and a Value may not have the type the host asked for.
+
+pub trait IntoValue {
+  fn into_value(self) -> Result<Value,AsaErrorKind>;
+}
+
+pub trait FromValue: Sized {
+  fn from_value(value: Value) -> Result<Self,AsaErrorKind>;
+}
+
+// A list of arguments for Interpreter::call. Implemented for slices, arrays, vectors and tuples of
+// convertible values, so both `&[Value::Number(1)]` and `(1, "two", true)` work.
+pub trait IntoArgs {
+  fn into_args(self) -> Result<Vec<Value>,AsaErrorKind>;
+}
+
+impl IntoValue for Value {
+  fn into_value(self) -> Result<Value,AsaErrorKind> {
+    Ok(self)
+  }
+}
+
+impl FromValue for Value {
+  fn from_value(value: Value) -> Result<Self,AsaErrorKind> {
+    Ok(value)
+  }
+}
+
+impl IntoValue for i32 {
+  fn into_value(self) -> Result<Value,AsaErrorKind> {
+    Ok(Value::Number(self))
+  }
+}
+
+impl FromValue for i32 {
+  fn from_value(value: Value) -> Result<Self,AsaErrorKind> {
+    match value {
+      Value::Number(n) => Ok(n),
+      other => Err(AsaErrorKind::type_error("from_value", "num", other.type_name())),
+    }
+  }
+}
+
+impl IntoValue for i64 {
+  fn into_value(self) -> Result<Value,AsaErrorKind> {
+    match i32::try_from(self) {
+      Ok(n) => Ok(Value::Number(n)),
+      Err(_) if self > 0 => Err(AsaErrorKind::NumberOverflow),
+      Err(_) => Err(AsaErrorKind::NumberUnderflow),
+    }
+  }
+}
+
+impl FromValue for i64 {
+  fn from_value(value: Value) -> Result<Self,AsaErrorKind> {
+    match value {
+      Value::Number(n) => Ok(n as i64),
+      other => Err(AsaErrorKind::type_error("from_value", "num", other.type_name())),
+    }
+  }
+}
+
+impl IntoValue for f64 {
+  fn into_value(self) -> Result<Value,AsaErrorKind> {
+    Ok(Value::Float(self))
+  }
+}
+
+impl FromValue for f64 {
+  fn from_value(value: Value) -> Result<Self,AsaErrorKind> {
+    match value {
+      Value::Float(n) => Ok(n),
+      Value::Number(n) => Ok(n as f64),
+      other => Err(AsaErrorKind::type_error("from_value", "num or float", other.type_name())),
+    }
+  }
+}
+
+impl IntoValue for bool {
+  fn into_value(self) -> Result<Value,AsaErrorKind> {
+    Ok(Value::Bool(self))
+  }
+}
+
+impl FromValue for bool {
+  fn from_value(value: Value) -> Result<Self,AsaErrorKind> {
+    match value {
+      Value::Bool(b) => Ok(b),
+      other => Err(AsaErrorKind::type_error("from_value", "bool", other.type_name())),
+    }
+  }
+}
+
+impl IntoValue for String {
+  fn into_value(self) -> Result<Value,AsaErrorKind> {
+    Ok(Value::String(self))
+  }
+}
+
+impl IntoValue for &str {
+  fn into_value(self) -> Result<Value,AsaErrorKind> {
+    Ok(Value::String(self.to_string()))
+  }
+}
+
+impl FromValue for String {
+  fn from_value(value: Value) -> Result<Self,AsaErrorKind> {
+    match value {
+      Value::String(s) => Ok(s),
+      other => Err(AsaErrorKind::type_error("from_value", "str", other.type_name())),
+    }
+  }
+}
+
+impl<T: IntoValue> IntoValue for Vec<T> {
+  fn into_value(self) -> Result<Value,AsaErrorKind> {
+    let items = self.into_iter().map(IntoValue::into_value).collect::<Result<Vec<Value>,AsaErrorKind>>()?;
+    Ok(Value::List(items))
+  }
+}
+
+impl<T: FromValue> FromValue for Vec<T> {
+  fn from_value(value: Value) -> Result<Self,AsaErrorKind> {
+    match value {
+      Value::List(items) => items.into_iter().map(T::from_value).collect(),
+      other => Err(AsaErrorKind::type_error("from_value", "list", other.type_name())),
+    }
+  }
+}
+
+impl<T: IntoValue> IntoValue for HashMap<String,T> {
+  fn into_value(self) -> Result<Value,AsaErrorKind> {
+    let mut map = HashMap::with_capacity(self.len());
+    for (key, value) in self {
+      map.insert(key, value.into_value()?);
+    }
+    Ok(Value::Map(map))
+  }
+}
+
+impl<T: FromValue> FromValue for HashMap<String,T> {
+  fn from_value(value: Value) -> Result<Self,AsaErrorKind> {
+    match value {
+      Value::Map(map) => map.into_iter().map(|(key, value)| Ok((key, T::from_value(value)?))).collect(),
+      other => Err(AsaErrorKind::type_error("from_value", "map", other.type_name())),
+    }
+  }
+}
+
+impl IntoArgs for () {
+  fn into_args(self) -> Result<Vec<Value>,AsaErrorKind> {
+    Ok(vec![])
+  }
+}
+
+impl<T: IntoValue + Clone> IntoArgs for &[T] {
+  fn into_args(self) -> Result<Vec<Value>,AsaErrorKind> {
+    self.iter().cloned().map(IntoValue::into_value).collect()
+  }
+}
+
+impl<T: IntoValue + Clone, const N: usize> IntoArgs for &[T; N] {
+  fn into_args(self) -> Result<Vec<Value>,AsaErrorKind> {
+    self.as_slice().into_args()
+  }
+}
+
+impl<T: IntoValue, const N: usize> IntoArgs for [T; N] {
+  fn into_args(self) -> Result<Vec<Value>,AsaErrorKind> {
+    self.into_iter().map(IntoValue::into_value).collect()
+  }
+}
+
+impl<T: IntoValue> IntoArgs for Vec<T> {
+  fn into_args(self) -> Result<Vec<Value>,AsaErrorKind> {
+    self.into_iter().map(IntoValue::into_value).collect()
+  }
+}
+
+macro_rules! tuple_args {
+  ($($name:ident),+) => (
+    impl<$($name: IntoValue),+> IntoArgs for ($($name,)+) {
+      #[allow(non_snake_case)]
+      fn into_args(self) -> Result<Vec<Value>,AsaErrorKind> {
+        let ($($name,)+) = self;
+        Ok(vec![$($name.into_value()?),+])
+      }
+    }
+  )
+}
+
+tuple_args!(A);
+tuple_args!(A, B);
+tuple_args!(A, B, C);
+tuple_args!(A, B, C, D);
+tuple_args!(A, B, C, D, E);
+tuple_args!(A, B, C, D, E, F);
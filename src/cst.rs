@@ -0,0 +1,52 @@
+use std::fmt;
+use crate::lexer::*;
+
+// A lossless view of a source file for tools that rewrite code, like formatters. Every significant
+// token carries the trivia (whitespace, tabs, newlines and comments) that comes before it, and the
+// trivia at the end of the file is attached to the EOF token, so every byte of the source belongs to
+// exactly one token and printing the tree gives back the source unchanged.
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct CstToken {
+  pub leading: Vec<Token>,
+  pub token: Token,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Cst {
+  pub tokens: Vec<CstToken>,
+}
+
+impl Cst {
+  pub fn new(source: &str) -> Cst {
+    let mut tokens = Vec::new();
+    let mut leading = Vec::new();
+    for token in lex_lossless(source) {
+      if token.kind.is_trivia() {
+        leading.push(token);
+      } else {
+        tokens.push(CstToken{leading: std::mem::take(&mut leading), token});
+      }
+    }
+    Cst { tokens }
+  }
+
+  // The comments in the file, in order.
+  pub fn comments(&self) -> impl Iterator<Item = &Token> {
+    self.tokens.iter().flat_map(|token| &token.leading).filter(|token| token.kind == TokenKind::Comment)
+  }
+}
+
+impl CstToken {
+  // The source text of the token's trivia and the token itself.
+  fn bytes(&self) -> impl Iterator<Item = &u8> {
+    self.leading.iter().chain(Some(&self.token)).flat_map(|token| &token.lexeme)
+  }
+}
+
+impl fmt::Display for Cst {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let bytes: Vec<u8> = self.tokens.iter().flat_map(CstToken::bytes).copied().collect();
+    write!(f, "{}", String::from_utf8_lossy(&bytes))
+  }
+}
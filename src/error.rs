@@ -1,13 +1,157 @@
+use std::fmt;
+use crate::interpreter::{Arity, Value};
+use crate::lexer::Span;
+
 #[derive(Debug,PartialEq)]
 pub enum AsaErrorKind {
-  UndefinedFunction,
+  UndefinedFunction(String),
+  ArityMismatch { name: String, expected: Arity, found: usize },
   VariableNotDefined(String),
-  TypeError,
+  // A value of the wrong type given to an operator or function, e.g. true given to - or 5 to upper.
+  // For an argument outside a function's domain, expected and found describe the value instead.
+  TypeError { expected: String, found: String, operator: String },
   DivisionByZero,
   NumberOverflow,
   NumberUnderflow,
   StackError,
-  UndefinedVariable,
+  IndexOutOfBounds,
+  InvalidNumber(String),
+  PermissionDenied(String),
+  IoError(String),
+  ModuleError { chain: Vec<String>, message: String },
+  PrivateAccess { module: String, name: String },
+  ConstReassignment(String),
+  UndefinedVariable(String),
   NoRun,
+  SyntaxError(Vec<SyntaxError>),
   Generic(String),  
+  // A value thrown by a throw statement that no catch handled.
+  Thrown(Value),
+}
+
+impl AsaErrorKind {
+  pub fn type_error(operator: &str, expected: &str, found: &str) -> AsaErrorKind {
+    AsaErrorKind::TypeError{expected: expected.to_string(), found: found.to_string(), operator: operator.to_string()}
+  }
+
+  // A stable code for the kind of error, for documentation and for tools matching on the output.
+  // Codes follow the order of the variants and are never reused, so new variants go at the end.
+  pub fn code(&self) -> &'static str {
+    match self {
+      AsaErrorKind::UndefinedFunction(_) => "E0001",
+      AsaErrorKind::ArityMismatch{..} => "E0002",
+      AsaErrorKind::VariableNotDefined(_) => "E0003",
+      AsaErrorKind::TypeError{..} => "E0004",
+      AsaErrorKind::DivisionByZero => "E0005",
+      AsaErrorKind::NumberOverflow => "E0006",
+      AsaErrorKind::NumberUnderflow => "E0007",
+      AsaErrorKind::StackError => "E0008",
+      AsaErrorKind::IndexOutOfBounds => "E0009",
+      AsaErrorKind::InvalidNumber(_) => "E0010",
+      AsaErrorKind::PermissionDenied(_) => "E0011",
+      AsaErrorKind::IoError(_) => "E0012",
+      AsaErrorKind::ModuleError{..} => "E0013",
+      AsaErrorKind::PrivateAccess{..} => "E0014",
+      AsaErrorKind::ConstReassignment(_) => "E0015",
+      AsaErrorKind::UndefinedVariable(_) => "E0016",
+      AsaErrorKind::NoRun => "E0017",
+      AsaErrorKind::SyntaxError(_) => "E0018",
+      AsaErrorKind::Generic(_) => "E0019",
+      AsaErrorKind::Thrown(_) => "E0020",
+    }
+  }
+
+  // The name of the variant, which is how a catch block tells built-in errors apart.
+  pub fn kind(&self) -> &'static str {
+    match self {
+      AsaErrorKind::UndefinedFunction(_) => "UndefinedFunction",
+      AsaErrorKind::ArityMismatch{..} => "ArityMismatch",
+      AsaErrorKind::VariableNotDefined(_) => "VariableNotDefined",
+      AsaErrorKind::TypeError{..} => "TypeError",
+      AsaErrorKind::DivisionByZero => "DivisionByZero",
+      AsaErrorKind::NumberOverflow => "NumberOverflow",
+      AsaErrorKind::NumberUnderflow => "NumberUnderflow",
+      AsaErrorKind::StackError => "StackError",
+      AsaErrorKind::IndexOutOfBounds => "IndexOutOfBounds",
+      AsaErrorKind::InvalidNumber(_) => "InvalidNumber",
+      AsaErrorKind::PermissionDenied(_) => "PermissionDenied",
+      AsaErrorKind::IoError(_) => "IoError",
+      AsaErrorKind::ModuleError{..} => "ModuleError",
+      AsaErrorKind::PrivateAccess{..} => "PrivateAccess",
+      AsaErrorKind::ConstReassignment(_) => "ConstReassignment",
+      AsaErrorKind::UndefinedVariable(_) => "UndefinedVariable",
+      AsaErrorKind::NoRun => "NoRun",
+      AsaErrorKind::SyntaxError(_) => "SyntaxError",
+      AsaErrorKind::Generic(_) => "Generic",
+      AsaErrorKind::Thrown(_) => "Thrown",
+    }
+  }
+}
+
+// The message of an error, without its code. Syntax errors are listed one per line.
+impl fmt::Display for AsaErrorKind {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      AsaErrorKind::UndefinedFunction(name) => write!(f, "undefined function '{}'", name),
+      AsaErrorKind::ArityMismatch{name, expected, found} => write!(f, "'{}' expects {} argument(s) but is called with {}", name, expected, found),
+      AsaErrorKind::VariableNotDefined(name) => write!(f, "variable '{}' is not defined", name),
+      AsaErrorKind::TypeError{expected, found, operator} => write!(f, "'{}' expects {}, found {}", operator, expected, found),
+      AsaErrorKind::DivisionByZero => write!(f, "division by zero"),
+      AsaErrorKind::NumberOverflow => write!(f, "number overflow"),
+      AsaErrorKind::NumberUnderflow => write!(f, "number underflow"),
+      AsaErrorKind::StackError => write!(f, "call stack too deep"),
+      AsaErrorKind::IndexOutOfBounds => write!(f, "index out of bounds"),
+      AsaErrorKind::InvalidNumber(text) => write!(f, "invalid number '{}'", text),
+      AsaErrorKind::PermissionDenied(path) => write!(f, "permission denied: {}", path),
+      AsaErrorKind::IoError(message) => write!(f, "{}", message),
+      AsaErrorKind::ModuleError{chain, message} => write!(f, "{} (loading {})", message, chain.join(" -> ")),
+      AsaErrorKind::PrivateAccess{module, name} => write!(f, "'{}' is private to module '{}'", name, module),
+      AsaErrorKind::ConstReassignment(name) => write!(f, "cannot rebind constant '{}'", name),
+      AsaErrorKind::UndefinedVariable(name) => write!(f, "undefined variable '{}'", name),
+      AsaErrorKind::NoRun => write!(f, "nothing to run"),
+      AsaErrorKind::SyntaxError(errors) => {
+        write!(f, "syntax error")?;
+        for error in errors {
+          write!(f, "\n  {}", error)?;
+        }
+        Ok(())
+      },
+      AsaErrorKind::Generic(message) => write!(f, "{}", message),
+      AsaErrorKind::Thrown(value) => write!(f, "uncaught {}", value),
+    }
+  }
+}
+
+impl std::error::Error for AsaErrorKind {}
+
+// A part of a source file that couldn't be parsed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyntaxError {
+  pub message: String,
+  pub span: Span,
+}
+
+impl fmt::Display for SyntaxError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{} at {}", self.message, self.span)
+  }
+}
+
+impl std::error::Error for SyntaxError {}
+
+// A call the error unwound through: the function that was running and where it was called from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CallSite {
+  pub function: String,
+  pub span: Span,
+}
+
+impl fmt::Display for CallSite {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    if self.span.start_line == 0 {
+      write!(f, "in {}", self.function)
+    } else {
+      write!(f, "in {}, called at {}", self.function, self.span)
+    }
+  }
 }
@@ -0,0 +1,295 @@
+use std::collections::VecDeque;
+use crate::cst::Cst;
+use crate::error::AsaErrorKind;
+use crate::lexer::*;
+use crate::parser::*;
+
+// The canonical layout of Asa source, used by asac fmt. A program is parsed and printed back from its
+// tree: one statement per line, bodies indented by two spaces, single spaces around operators and
+// after commas, and a blank line around function definitions. If expressions are kept on one line.
+//
+// Comments are not part of the tree, so they are taken from the lossless token stream and placed by
+// line: a comment on a line of its own is printed before the next statement, and a comment after
+// code stays at the end of the statement it follows.
+
+const INDENT: &str = "  ";
+
+// Formats a whole source file. Fails if the file can't be parsed completely.
+pub fn format_source(source: &str) -> Result<String,AsaErrorKind> {
+  let tree = parse_source(source)?;
+  let cst = Cst::new(source);
+  let mut formatter = Formatter {
+    out: String::new(),
+    comments: comments(&cst),
+    cst,
+    indent: 0,
+  };
+  formatter.program(tree.children());
+  Ok(formatter.out)
+}
+
+struct Comment {
+  line: u32,
+  text: String,
+}
+
+// The comments of a file in order, with the line they are on.
+fn comments(cst: &Cst) -> VecDeque<Comment> {
+  cst.comments()
+    .map(|token| Comment{line: token.start_line, text: String::from_utf8_lossy(&token.lexeme).trim_end().to_string()})
+    .collect()
+}
+
+struct Formatter {
+  out: String,
+  // Comments not printed yet
+  comments: VecDeque<Comment>,
+  cst: Cst,
+  indent: usize,
+}
+
+impl Formatter {
+
+  fn program(&mut self, items: &[Node]) {
+    for (i, item) in items.iter().enumerate() {
+      if i > 0 && (is_function(item) || is_function(&items[i - 1])) {
+        self.out.push('\n');
+      }
+      self.statement(item, items.get(i + 1).and_then(line_of));
+    }
+    // Comments after the last statement
+    self.leading(u32::MAX);
+  }
+
+  // Prints a top-level item or a statement of a function body on its own line(s), together with the
+  // comments around it. A comment after code belongs to the last code on its line, so when the next
+  // statement starts on the same line, this one doesn't take it.
+  fn statement(&mut self, node: &Node, next: Option<u32>) {
+    let line = line_of(node);
+    if let Some(line) = line {
+      self.leading(line);
+    }
+    // A statement without a block is printed on one line, so the comments on lines of their own
+    // inside it go before it.
+    if let Some(span) = node.span().filter(|_| !is_function(node) && !matches!(node, Node::Try{..})) {
+      let end = self.statement_end(span);
+      self.hoist(span.start_line, end);
+    }
+    let line = line.filter(|line| Some(*line) != next);
+    self.out.push_str(&INDENT.repeat(self.indent));
+    match node {
+      Node::Export{children} => {
+        self.out.push_str("pub ");
+        self.definition(&children[0], line);
+      },
+      node => self.definition(node, line),
+    }
+  }
+
+  fn definition(&mut self, node: &Node, line: Option<u32>) {
+    match node {
+      Node::FunctionDefine{name, children, span} => {
+        self.out.push_str(&format!("fn {}({})", name, parameters(&children[0])));
+        if let Some(Node::TypeAnnotation{value}) = children.get(2) {
+          self.out.push_str(&format!(" -> {}", value));
+        }
+        let end = self.block_end(*span, 0);
+        self.block(children[1].children(), line, end);
+        self.trailing(Some(end));
+      },
+      // try {..} catch e {..} finally {..}, each block starting on the line the previous one ends on.
+      Node::Try{children, span} => {
+        self.out.push_str("try");
+        let mut line = line;
+        let blocks = [&children[0], &children[2]].into_iter().chain(children.get(3));
+        for (nth, block) in blocks.enumerate() {
+          match nth {
+            1 => self.out.push_str(&format!(" catch {}", expression(&children[1]))),
+            2 => self.out.push_str(" finally"),
+            _ => (),
+          }
+          let end = self.block_end(*span, nth);
+          self.block(block.children(), line, end);
+          line = Some(end);
+        }
+        self.trailing(line);
+      },
+      Node::Import{path, ..} => {
+        self.out.push_str(&format!("import {};", string(path)));
+        self.trailing(line);
+      },
+      node => {
+        self.out.push_str(&expression(node));
+        self.out.push(';');
+        self.trailing(line);
+      },
+    }
+  }
+
+  // Prints the comments on lines before the given one, each on its own line.
+  fn leading(&mut self, line: u32) {
+    while self.comments.front().is_some_and(|comment| comment.line < line) {
+      let comment = self.comments.pop_front().unwrap();
+      self.out.push_str(&INDENT.repeat(self.indent));
+      self.out.push_str(&comment.text);
+      self.out.push('\n');
+    }
+  }
+
+  // Prints the comments on the lines strictly between the given ones, each on its own line.
+  fn hoist(&mut self, start: u32, end: u32) {
+    let (inside, rest): (VecDeque<Comment>, VecDeque<Comment>) = self.comments.drain(..).partition(|comment| comment.line > start && comment.line < end);
+    self.comments = rest;
+    for comment in inside {
+      self.out.push_str(&INDENT.repeat(self.indent));
+      self.out.push_str(&comment.text);
+      self.out.push('\n');
+    }
+  }
+
+  // Ends the current output line, with the comment that followed the code on the given line, if any.
+  fn trailing(&mut self, line: Option<u32>) {
+    if line.is_some() && self.comments.front().is_some_and(|comment| Some(comment.line) == line) {
+      let comment = self.comments.pop_front().unwrap();
+      self.out.push(' ');
+      self.out.push_str(&comment.text);
+    }
+    self.out.push('\n');
+  }
+
+  // Prints " {", the statements of a block indented, and its closing brace on the given line. The
+  // output line is left open after the brace, for whatever follows it on that line.
+  fn block(&mut self, stmts: &[Node], line: Option<u32>, end: u32) {
+    self.out.push_str(" {");
+    self.trailing(line.filter(|line| stmts.first().and_then(line_of).unwrap_or(end) != *line));
+    self.indent += 1;
+    for (i, stmt) in stmts.iter().enumerate() {
+      self.statement(stmt, Some(stmts.get(i + 1).and_then(line_of).unwrap_or(end)));
+    }
+    self.leading(end);
+    self.indent -= 1;
+    self.out.push_str(&INDENT.repeat(self.indent));
+    self.out.push('}');
+  }
+
+  // The line a statement starting at the given location ends on: that of its semicolon, or of its
+  // last token if it has none.
+  fn statement_end(&self, from: Span) -> u32 {
+    let tokens = &self.cst.tokens;
+    let start = tokens.iter().position(|token| token.token.start_line == from.start_line && token.token.start_col == from.start_col).unwrap_or(0);
+    let mut depth = 0;
+    let mut end = from.start_line;
+    for token in &tokens[start..] {
+      match token.token.kind {
+        TokenKind::Semicolon if depth == 0 => return token.token.start_line,
+        TokenKind::RightParen | TokenKind::RightCurly if depth == 0 => return end,
+        TokenKind::RightParen | TokenKind::RightCurly => depth -= 1,
+        TokenKind::LeftParen | TokenKind::LeftCurly => depth += 1,
+        TokenKind::EOF => return end,
+        _ => (),
+      }
+      end = token.token.end_line;
+    }
+    end
+  }
+
+  // The line of the closing brace of the nth block after the given location, e.g. the body of the
+  // function whose name is there.
+  fn block_end(&self, from: Span, nth: usize) -> u32 {
+    let tokens = &self.cst.tokens;
+    let start = tokens.iter().position(|token| token.token.start_line == from.start_line && token.token.start_col == from.start_col).unwrap_or(0);
+    let mut depth = 0;
+    let mut blocks = 0;
+    for token in &tokens[start..] {
+      match token.token.kind {
+        TokenKind::LeftCurly => depth += 1,
+        TokenKind::RightCurly if depth == 1 && blocks == nth => return token.token.start_line,
+        TokenKind::RightCurly => {
+          depth -= 1;
+          if depth == 0 {
+            blocks += 1;
+          }
+        },
+        _ => (),
+      }
+    }
+    u32::MAX
+  }
+}
+
+fn line_of(node: &Node) -> Option<u32> {
+  node.span().map(|span| span.start_line)
+}
+
+fn is_function(node: &Node) -> bool {
+  match node {
+    Node::Export{children} => is_function(&children[0]),
+    Node::FunctionDefine{..} => true,
+    _ => false,
+  }
+}
+
+fn parameters(args: &Node) -> String {
+  let params: Vec<String> = args.children().iter()
+    .map(|param| match param.children() {
+      [name, Node::TypeAnnotation{value}] => format!("{}: {}", expression(name), value),
+      [name] => expression(name),
+      _ => unreachable!(),
+    })
+    .collect();
+  params.join(", ")
+}
+
+// The canonical text of an expression, or of a statement without its semicolon.
+fn expression(node: &Node) -> String {
+  match node {
+    Node::Statement{children} | Node::Expression{children} => expression(&children[0]),
+    Node::VariableDefine{children} => format!("let {} = {}", expression(&children[0]), expression(&children[1])),
+    Node::ConstDefine{children} => format!("const {} = {}", expression(&children[0]), expression(&children[1])),
+    Node::FunctionReturn{children, ..} => format!("return {}", expression(&children[0])),
+    Node::Throw{children, ..} => format!("throw {}", expression(&children[0])),
+    Node::IfAssign{children} => format!("let {} = {}", expression(&children[0]), branches(&children[1], &children[2], &children[3])),
+    Node::IfExpression{children, ..} | Node::MultiLineIf{children} => branches(&children[0], &children[1], &children[2]),
+    Node::IfElse{children} => format!("if {} {} else {}", expression(&children[0]), expression(&children[1]), branches(&children[2], &children[3], &children[4])),
+    Node::ReturnIf{children} | Node::ReturnIfNum{children} | Node::ReturnElse{children} => format!("{{return {};}}", expression(&children[0])),
+    Node::MathExpression{name, children, ..} => {
+      let operator = if name.as_slice() == b"sub" { "-" } else { "+" };
+      format!("{} {} {}", expression(&children[0]), operator, expression(&children[1]))
+    },
+    Node::Condition{children} => format!("{} {} {}", expression(&children[0]), expression(&children[1]), expression(&children[2])),
+    Node::FunctionCall{name, children, ..} => {
+      let args: Vec<String> = children.last().map_or(&[][..], Node::children).iter().map(expression).collect();
+      format!("{}({})", name, args.join(", "))
+    },
+    Node::Identifier{value, ..} => value.to_string(),
+    Node::FieldAccess{children, name, ..} => format!("{}.{}", expression(&children[0]), name),
+    Node::Operator{value, ..} => String::from_utf8_lossy(value).into_owned(),
+    Node::Number{value} => value.to_string(),
+    // Display never uses an exponent, but leaves out the fraction of whole numbers.
+    Node::Float{value} if value.fract() == 0.0 => format!("{}.0", value),
+    Node::Float{value} => value.to_string(),
+    Node::Bool{value} => value.to_string(),
+    Node::String{value} => string(value),
+    _ => unreachable!(),
+  }
+}
+
+fn branches(condition: &Node, then: &Node, otherwise: &Node) -> String {
+  format!("if {} {} else {}", expression(condition), expression(then), expression(otherwise))
+}
+
+// A string literal, with the characters that need it escaped.
+fn string(value: &str) -> String {
+  let mut literal = String::from("\"");
+  for c in value.chars() {
+    match c {
+      '"' => literal.push_str("\\\""),
+      '\\' => literal.push_str("\\\\"),
+      '\n' => literal.push_str("\\n"),
+      '\t' => literal.push_str("\\t"),
+      c => literal.push(c),
+    }
+  }
+  literal.push('"');
+  literal
+}
@@ -1,24 +1,315 @@
-use crate::parser::Node;
+use crate::parser::{syntax_errors, Node};
+use crate::lexer::Span;
+use crate::symbol::Symbol;
+use std::cell::{OnceCell, RefCell};
 use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
 use crate::error::*;
+use crate::convert::*;
+use crate::stdlib;
+use crate::module::*;
+use crate::vm::{self, Chunk};
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum Value {
   String(String),
   Number(i32),
+  Float(f64),
   Bool(bool),
+  List(Vec<Value>),
+  Map(HashMap<String,Value>),
 }
 
-type Frame = HashMap<String, Value>;
-type Arguments = Node;
-type Statements = Node;
+impl fmt::Display for Value {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Value::String(s) => write!(f, "{}", s),
+      Value::Number(n) => write!(f, "{}", n),
+      Value::Float(n) => write!(f, "{:?}", n),
+      Value::Bool(b) => write!(f, "{}", b),
+      Value::List(items) => {
+        write!(f, "[")?;
+        for (i, item) in items.iter().enumerate() {
+          if i > 0 {
+            write!(f, ", ")?;
+          }
+          match item {
+            Value::String(s) => write!(f, "{:?}", s)?,
+            _ => write!(f, "{}", item)?,
+          }
+        }
+        write!(f, "]")
+      },
+      Value::Map(map) => {
+        // Keys are sorted so that printing a map is deterministic.
+        let mut keys: Vec<&String> = map.keys().collect();
+        keys.sort();
+        write!(f, "{{")?;
+        for (i, key) in keys.into_iter().enumerate() {
+          if i > 0 {
+            write!(f, ", ")?;
+          }
+          match &map[key] {
+            Value::String(s) => write!(f, "{:?}: {:?}", key, s)?,
+            value => write!(f, "{:?}: {}", key, value)?,
+          }
+        }
+        write!(f, "}}")
+      },
+    }
+  }
+}
+
+impl Value {
+  // The name of the value's type in error messages.
+  pub fn type_name(&self) -> &'static str {
+    match self {
+      Value::String(_) => "str",
+      Value::Number(_) => "num",
+      Value::Float(_) => "float",
+      Value::Bool(_) => "bool",
+      Value::List(_) => "list",
+      Value::Map(_) => "map",
+    }
+  }
+}
 
+// The error for the test of an if that isn't a bool.
+pub(crate) fn not_bool(test: &Value) -> AsaErrorKind {
+  AsaErrorKind::type_error("if", "bool", test.type_name())
+}
+
+// The error for an integer result that doesn't fit into an Asa number, depending on which end of
+// the range it fell off.
+pub(crate) fn overflow(positive: bool) -> AsaErrorKind {
+  if positive {
+    AsaErrorKind::NumberOverflow
+  } else {
+    AsaErrorKind::NumberUnderflow
+  }
+}
+
+// Wraps a floating point result of an operator or function, turning NaN and infinities into errors
+// instead of letting them leak into the program.
+pub(crate) fn checked_float(operator: &str, value: f64) -> Result<Value,AsaErrorKind> {
+  if value.is_nan() {
+    Err(AsaErrorKind::type_error(operator, "arguments with a defined result", "NaN"))
+  } else if value.is_infinite() {
+    Err(overflow(value > 0.0))
+  } else {
+    Ok(Value::Float(value))
+  }
+}
+
+// The + operator. Numbers are added (mixing integers and floats gives a float) and strings are
+// concatenated.
+pub(crate) fn add(leftside: Value, rightside: Value) -> Result<Value,AsaErrorKind> {
+  match (leftside, rightside) {
+    (Value::Number(lv), Value::Number(rv)) => lv.checked_add(rv).map(Value::Number).ok_or(overflow(rv > 0)),
+    (Value::String(lv), Value::String(rv)) => Ok(Value::String(lv + &rv)),
+    (Value::String(_), other) | (other, Value::String(_)) => Err(AsaErrorKind::type_error("+", "str", other.type_name())),
+    (Value::Float(lv), Value::Float(rv)) => checked_float("+", lv + rv),
+    (Value::Float(lv), Value::Number(rv)) => checked_float("+", lv + rv as f64),
+    (Value::Number(lv), Value::Float(rv)) => checked_float("+", lv as f64 + rv),
+    (Value::Number(_) | Value::Float(_), other) | (other, _) => Err(AsaErrorKind::type_error("+", "num or str", other.type_name())),
+  }
+}
+
+// The - operator, defined for numbers only.
+pub(crate) fn sub(leftside: Value, rightside: Value) -> Result<Value,AsaErrorKind> {
+  match (leftside, rightside) {
+    (Value::Number(lv), Value::Number(rv)) => lv.checked_sub(rv).map(Value::Number).ok_or(overflow(rv < 0)),
+    (Value::Float(lv), Value::Float(rv)) => checked_float("-", lv - rv),
+    (Value::Float(lv), Value::Number(rv)) => checked_float("-", lv - rv as f64),
+    (Value::Number(lv), Value::Float(rv)) => checked_float("-", lv as f64 - rv),
+    (Value::Number(_) | Value::Float(_), other) | (other, _) => Err(AsaErrorKind::type_error("-", "num", other.type_name())),
+  }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub(crate) enum Comparison {
+  Greater,
+  Less,
+  Equal,
+}
+
+impl Comparison {
+  pub(crate) fn operator(&self) -> &'static str {
+    match self {
+      Comparison::Greater => ">",
+      Comparison::Less => "<",
+      Comparison::Equal => "==",
+    }
+  }
+
+  pub(crate) fn from_operator(operator: &[u8]) -> Comparison {
+    match operator {
+      b">" => Comparison::Greater,
+      b"<" => Comparison::Less,
+      _ => Comparison::Equal,
+    }
+  }
+}
+
+// Compares two values. Numbers (integers and floats) and strings can be ordered; == works on any two
+// values of the same type. Comparing values of different types is a type error.
+pub(crate) fn compare(comparison: Comparison, leftside: Value, rightside: Value) -> Result<Value,AsaErrorKind> {
+  let ordering = match (&leftside, &rightside) {
+    (Value::Number(lv), Value::Number(rv)) => lv.partial_cmp(rv),
+    (Value::Float(lv), Value::Float(rv)) => lv.partial_cmp(rv),
+    (Value::Float(lv), Value::Number(rv)) => lv.partial_cmp(&(*rv as f64)),
+    (Value::Number(lv), Value::Float(rv)) => (*lv as f64).partial_cmp(rv),
+    (Value::String(lv), Value::String(rv)) => lv.partial_cmp(rv),
+    _ if comparison == Comparison::Equal && std::mem::discriminant(&leftside) == std::mem::discriminant(&rightside) => {
+      return Ok(Value::Bool(leftside == rightside));
+    },
+    // The right side is blamed when it differs from the left, and the left when neither can be ordered.
+    _ if std::mem::discriminant(&leftside) != std::mem::discriminant(&rightside) => {
+      return Err(AsaErrorKind::type_error(comparison.operator(), leftside.type_name(), rightside.type_name()));
+    },
+    _ => return Err(AsaErrorKind::type_error(comparison.operator(), "num or str", leftside.type_name())),
+  };
+  let result = match comparison {
+    Comparison::Greater => ordering == Some(std::cmp::Ordering::Greater),
+    Comparison::Less => ordering == Some(std::cmp::Ordering::Less),
+    Comparison::Equal => ordering == Some(std::cmp::Ordering::Equal),
+  };
+  Ok(Value::Bool(result))
+}
+
+type Frame = HashMap<Symbol, Value>;
+
+// What a function or constant is stored under: the namespace of the module that defined it, or None
+// for the main program and the built-ins, and its unqualified name.
+type Key = (Option<Symbol>, Symbol);
+
+// The key a name in the running code refers to when it is qualified, or when it is global.
+fn key(name: &Symbol) -> Key {
+  match name.parts() {
+    Some((namespace, name)) => (Some(namespace.clone()), name.clone()),
+    None => (None, name.clone()),
+  }
+}
+
+// Signature of a host function that can be called from Asa code.
+pub type NativeFunction = dyn Fn(&[Value]) -> Result<Value,AsaErrorKind>;
+
+// Destination of everything Asa code prints. Shared with the output built-ins so that swapping the
+// writer with set_output also redirects functions that were registered earlier.
+pub(crate) type Output = Rc<RefCell<Box<dyn Write>>>;
+
+// Directories the file built-ins may touch, shared with those built-ins like Output.
+pub(crate) type AllowedDirs = Rc<RefCell<Vec<PathBuf>>>;
+
+// Number of arguments a native function accepts.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Arity {
+  Exact(usize),
+  AtLeast(usize),
+}
+
+impl Arity {
+  pub fn accepts(&self, count: usize) -> bool {
+    match self {
+      Arity::Exact(n) => count == *n,
+      Arity::AtLeast(n) => count >= *n,
+    }
+  }
+}
+
+impl fmt::Display for Arity {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Arity::Exact(n) => write!(f, "{}", n),
+      Arity::AtLeast(n) => write!(f, "at least {}", n),
+    }
+  }
+}
+
+impl From<usize> for Arity {
+  fn from(n: usize) -> Arity {
+    Arity::Exact(n)
+  }
+}
+
+// Which engine runs the bodies of user functions. Both give the same results; the bytecode back end
+// compiles each function the first time it is called and runs it on a stack machine.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub enum Backend {
+  #[default]
+  TreeWalker,
+  Bytecode,
+}
+
+// How many user function calls can be in progress at once by default. A deeper call fails with a
+// StackError. Each call also takes native stack, up to about 10 KB in a debug build, so the default
+// fits a 2 MB thread; a host that raises the limit has to give the interpreter a larger stack.
+pub const MAX_CALL_DEPTH: usize = 100;
+
+// A function defined in Asa source code. It is built once when the definition runs and shared by
+// every call, so calling a function never copies its body.
 #[derive(Debug)]
+pub struct FunctionDef {
+  // Namespace of the module that defined the function
+  module: Option<Symbol>,
+  params: Vec<Symbol>,
+  stmts: Vec<Node>,
+  // The compiled body, filled in on the first call with the bytecode back end. None means the
+  // compiler doesn't support the function and it runs on the tree-walker.
+  code: OnceCell<Option<Rc<Chunk>>>,
+}
+
+#[derive(Clone)]
+pub enum Function {
+  // A function defined in Asa source code, and whether it was marked pub.
+  User { def: Rc<FunctionDef>, public: bool },
+  // A function implemented in Rust and registered by the embedding host.
+  Native { arity: Arity, func: Rc<NativeFunction> },
+}
+
+impl Function {
+  pub fn arity(&self) -> Arity {
+    match self {
+      Function::User{def, ..} => Arity::Exact(def.params.len()),
+      Function::Native{arity, ..} => *arity,
+    }
+  }
+}
+
+impl fmt::Debug for Function {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Function::User{def, public} => f.debug_struct("User")
+        .field("module", &def.module)
+        .field("public", public)
+        .field("params", &def.params)
+        .field("stmts", &def.stmts)
+        .finish(),
+      Function::Native{arity, ..} => f.debug_struct("Native").field("arity", arity).finish_non_exhaustive(),
+    }
+  }
+}
+
+// A value bound with const. It is evaluated once when its definition runs and can't be rebound.
+#[derive(Debug, Clone)]
+struct Constant {
+  module: Option<Symbol>,
+  public: bool,
+  value: Value,
+}
+
 pub struct Interpreter {
   // Function Table:
-  // Key - Function name
-  // Value - Vec<Node> arguments, statements
-  functions: HashMap<String, (Arguments,Statements)>,
+  // Key - Function name, with the namespace of the module that defined it
+  // Value - Function definition, either Asa arguments and statements or a native Rust closure
+  functions: HashMap<Key, Function>,
+  // Constant Table:
+  // Key - Constant name, with the namespace of the module that defined it
+  // Value - The constant's value, visible from every function
+  constants: HashMap<Key, Constant>,
   // Stack:
   // Each element in the stack is a function stack frame.
   // Crate a new stack frame on function entry.
@@ -26,129 +317,137 @@ pub struct Interpreter {
   // Key - Variable name
   // Value - Variable value
   stack: Vec<Frame>,
+  // Writer used by print and println, stdout unless the host replaces it.
+  output: Output,
+  // Capability set for file I/O. Empty by default, which denies all file access.
+  allowed_dirs: AllowedDirs,
+  // Imported modules, and the namespace of the module whose code is running (None for the main program).
+  modules: Modules,
+  namespace: Option<Symbol>,
+  backend: Backend,
+  // The calls the last error unwound through, innermost first. Filled in as the error leaves each
+  // function, and cleared when the next call or program starts.
+  backtrace: Vec<CallSite>,
+  // The number of user function calls in progress, on either back end, and how many are allowed.
+  depth: usize,
+  max_depth: usize,
+}
+
+impl fmt::Debug for Interpreter {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("Interpreter")
+      .field("functions", &self.functions)
+      .field("constants", &self.constants)
+      .field("stack", &self.stack)
+      .field("allowed_dirs", &self.allowed_dirs.borrow())
+      .field("modules", &self.modules)
+      .field("backend", &self.backend)
+      .finish_non_exhaustive()
+  }
 }
 
 
 impl Interpreter {
 
   pub fn new() -> Interpreter {
-    Interpreter {
+    let mut interpreter = Interpreter {
       functions: HashMap::new(),
+      constants: HashMap::new(),
       stack: Vec::new(),
-    }
+      output: Rc::new(RefCell::new(Box::new(io::stdout()))),
+      allowed_dirs: Rc::new(RefCell::new(Vec::new())),
+      modules: Modules::default(),
+      namespace: None,
+      backend: Backend::default(),
+      backtrace: Vec::new(),
+      depth: 0,
+      max_depth: MAX_CALL_DEPTH,
+    };
+    stdlib::register(&mut interpreter);
+    interpreter
+  }
+
+  // Selects the engine that runs user functions. Functions already compiled keep their code, so the
+  // back end can be switched between programs.
+  pub fn set_backend(&mut self, backend: Backend) {
+    self.backend = backend;
+  }
+
+  pub fn backend(&self) -> Backend {
+    self.backend
+  }
+
+  // Sets how many user function calls can be in progress at once, MAX_CALL_DEPTH by default.
+  pub fn set_max_call_depth(&mut self, depth: usize) {
+    self.max_depth = depth;
+  }
+
+  // Redirects the output of print and println, e.g. into a buffer so a test or host can capture it.
+  pub fn set_output<W: Write + 'static>(&mut self, writer: W) {
+    *self.output.borrow_mut() = Box::new(writer);
+  }
+
+  pub(crate) fn output(&self) -> Output {
+    self.output.clone()
+  }
+
+  // Grants the file built-ins access to a directory and everything below it. The directory must
+  // exist; it is canonicalized so that paths using ".." or symlinks can't escape it.
+  pub fn allow_dir<P: AsRef<Path>>(&mut self, dir: P) -> Result<(),AsaErrorKind> {
+    let dir = dir.as_ref().canonicalize().map_err(|e| AsaErrorKind::IoError(format!("{}: {}", dir.as_ref().display(), e)))?;
+    self.allowed_dirs.borrow_mut().push(dir);
+    Ok(())
+  }
+
+  pub(crate) fn allowed_dirs(&self) -> AllowedDirs {
+    self.allowed_dirs.clone()
+  }
+
+  // Registers a Rust closure under the given name so Asa code can call it like any other function.
+  // Arguments are evaluated in the caller's scope and passed by value; a call with the wrong number
+  // of arguments fails with ArityMismatch before the closure runs.
+  pub fn register_native<A, F>(&mut self, name: &str, arity: A, func: F)
+  where
+    A: Into<Arity>,
+    F: Fn(&[Value]) -> Result<Value,AsaErrorKind> + 'static,
+  {
+    self.functions.insert(key(&Symbol::new(name)), Function::Native{arity: arity.into(), func: Rc::new(func)});
+  }
+
+  // Loads and runs a program from a file. Imports in the file are resolved relative to its directory.
+  pub fn load_file<P: AsRef<Path>>(&mut self, path: P) -> Result<Value,AsaErrorKind> {
+    let path = path.as_ref().canonicalize().map_err(|e| self.modules.error(Some(path.as_ref()), e.to_string()))?;
+    self.modules.start(path.clone());
+    let result = self.modules.parse(&path).and_then(|tree| self.exec(&tree));
+    self.modules.finish(result.is_ok());
+    result
   }
 
   pub fn exec(&mut self, node: &Node) -> Result<Value,AsaErrorKind> {
     match node {
-      Node::Program{children} => {
-        let mut return_val = Err(AsaErrorKind::NoRun) ;
-        for n in children {
-          match n {
-            Node::FunctionDefine{..} |
-            Node::Expression{..} |
-            Node::VariableDefine{..} |
-            Node::String{..} |
-            Node::Number{..} |
-            Node::Bool{..} => {
-              return_val = Ok (self.exec(n)?) ;
-            }
-            _ => unreachable!(),
-          }
-        }
-       return_val 
-      },
+      Node::Program{children} => self.program(node, children),
+      // Loads an imported module the first time it is imported. Its top-level code runs in a frame of its
+      // own, and its functions are defined under its namespace.
+      Node::Import{path, ..} => self.import(path),
       // Evaluates a mathematical expression based on the elements in the children argument. If the expression is valid, the code evaluates it and returns a new Value object with the resulting value. If the expression is not valid, the code returns an error message.
-      Node::MathExpression{name, children} => {
-        let leftside: Value = self.exec(&children[0])?;
-        let rightside: Value = self.exec(&children[1])?;
-          match String:: from_utf8(name.to_vec()).unwrap().as_str() {
-            "add" => {
-              match (leftside, rightside){
-                (Value::Number(lv), Value::Number(rv)) =>  Ok(Value::Number(lv + rv)),
-                _ => Err(AsaErrorKind::UndefinedFunction) 
-              }
-            },
-            "sub" => {
-              match (leftside, rightside){
-                (Value::Number(lv), Value::Number(rv)) =>  Ok(Value::Number(lv - rv)),
-                _ => Err(AsaErrorKind::UndefinedFunction) 
-              }
-            },
-            _ => Err(AsaErrorKind::UndefinedFunction),
-        }        
-      },
+      Node::MathExpression{name, children, ..} => self.math(name, children),
       // Defines a function that takes some arguments and executes a program based on those arguments. The code first checks if the function exists, and if it does, it creates a new scope in which to execute the function's statements (push a new Frame onto the interpreter stack). The code then executes each statement in the function's statements list and returns the result of the function's execution. You will have to correlate each passed value with the apprpriate variable in the called function. If the wrong number or an wrong type of variable is passed, return an error. On success, insert the return value of the function (if any) into the appropriate entry of the caller's stack.
-      Node::FunctionCall{name, children} => {
-        let func_name = String::from_utf8_lossy(&name).into_owned();// extract function name and arguments
-        let (args,stmts) = if let Some((Node::FunctionArguments { children: args }, Node::FunctionStatements { children: stmts })) = self.functions.get(&func_name) {
-            (args.clone(),stmts.clone())
-        } else {    
-            
-            return Err(AsaErrorKind::UndefinedFunction);
-        };
-        let call_args = if let Some(Node::FunctionArguments { children: args }) = &children.last() {
-            args.clone()
-        } else {
-            Vec::new()
-        };
-        let mut new_frame = Frame::new();
-        for (arg_node, arg_value) in call_args.iter().zip(args.iter()) {
-            let result =  self.exec(&arg_node)?;
-            let arg_name = match arg_value {
-                Node::Expression { children } => {
-                    match &children[0] {
-                        Node::Identifier { value } => {
-                            String::from_utf8_lossy(&value).into_owned()
-                        },
-                        _ => unreachable!(),
-                    }
-                },
-                _ => unreachable!(),
-            };
-            new_frame.insert(arg_name, result);
-        }
-        self.stack.push(new_frame);
-        let mut result = Err(AsaErrorKind::NoRun) ; 
-        for stmt in stmts {
-           result = Ok(self.exec(&stmt)?);
-        }
-        self.stack.pop();
-        result
-      },
+      Node::FunctionCall{name, children, span} => self.function_call(name, children, *span),
       // Defines a new function based on the elements in the children argument. The name of the function is retrieved from the node struct, the arguments are the first child, and the statements that define the function are the second child. A new key-value pair is then inserted into the functions table of the interprer. If the function was successfully defined, the code returns a Value object with a boolean value of true, otherwise an error is returned.
-      Node::FunctionDefine{name, children} => {
-        let function_name = String::from_utf8_lossy(&name).into_owned();
-        let args = match &children[0] {
-            Node::FunctionArguments {..} => children[0].clone(),
-            _ => unreachable!(),
-        };
-        let stmts = match &children[1] {
-            Node::FunctionStatements {..} => children[1].clone(),
-            _ => unreachable!(),
-        };
-        self.functions.insert(function_name.clone(), (args, stmts) );
-        Ok(Value::Bool(true))
-      },
+      Node::FunctionDefine{name, children, ..} => self.define_function(name, children),
+      // Evaluates the value of a constant once and adds it to the constant table. Constants are never
+      // rebound, so defining one twice is an error.
+      Node::ConstDefine{children} => self.define_constant(children),
+      // Defines the wrapped definition, then marks it as exported from its module.
+      Node::Export{children} => self.export(children),
       // Calls the exec() method on the first element in the children argument, which recursively evaluates the AST of the program being executed and returns the resulting value or error message.
-      Node::FunctionReturn{children} => {
-        Ok(self.exec(&children[0])?)
-      },
-      // Retrieves the value of the identifier from the current frame on the stack. If the variable is defined in the current frame, the code returns its value. If the variable is not defined in the current frame, the code returns an error message.
-      Node::Identifier{value} => {
-        let value_str = String::from_utf8_lossy(value).into_owned();
-        match self.stack.last() {
-            Some(frame) => {
-                if let Some(val) = frame.get(&value_str) {
-                    return Ok(val.clone());
-                } else {
-                    return Err(AsaErrorKind::UndefinedVariable)
-                }
-            }
-            _ => {
-                return Err(AsaErrorKind::UndefinedVariable)
-            }
-        }
+      Node::FunctionReturn{children, ..} => {
+        self.exec(&children[0])
       },
+      // Retrieves the value of the identifier from the current frame on the stack. If the variable is not defined in the current frame, the identifier may name a constant. Otherwise the code returns an error message.
+      Node::Identifier{value, ..} => self.variable(value),
+      // Reads a field of a map, or a constant of an imported module.
+      Node::FieldAccess{children, name, ..} => self.field_access(&children[0], name),
       // Checks the type of the first element in the children argument and deciding what to do based on that type. If the type is a VariableDefine or FunctionReturn node, the code runs the run method on that node and returns the result.
       Node::Statement{children} => {
         match children[0] {
@@ -160,25 +459,7 @@ impl Interpreter {
         }
       },
       // Defines a new variable by assigning a name and a value to it. The name is retrieved from the first element of the children argument, and the value is retrieved by running the run method on the second element of the children argument. The key-value pair is then inserted into the last frame on the stack field of the current runtime object.
-      Node::VariableDefine{children} => {
-        let variable_identifier = &children[0];
-        let value = self.exec(&children[1])?;
-        let var_name = match variable_identifier {
-            Node::Identifier {value} => {
-                String::from_utf8_lossy(value).into_owned()
-            },
-            _ => unreachable!(),
-        };
-        if let Some(frame) = self.stack.last_mut() {
-            frame.insert(var_name.clone(), value.clone());
-            Ok(value)
-        } else {
-            let mut frame = Frame::new();
-            frame.insert(var_name.clone(), value.clone());
-            self.stack.push(frame);
-            Ok(value)
-        }
-      },
+      Node::VariableDefine{children} => self.define_variable(children),
       // Evaluate the child node using the exec() method.
       Node::Expression{children} => {
         self.exec(&children[0])
@@ -186,6 +467,9 @@ impl Interpreter {
       Node::Number{value} => {
         Ok(Value::Number(*value))
       }
+      Node::Float{value} => {
+        Ok(Value::Float(*value))
+      }
       Node::String{value} => {
         Ok(Value::String(value.clone()))
       }
@@ -193,105 +477,570 @@ impl Interpreter {
         Ok(Value::Bool(*value))
       },
 
-      Node::IfExpression{children} => {
-        let condition = self.exec(&children[0])?;
-        match condition{
-            Value::Bool(true) => { self.exec(&children[1])?;
-            Ok(Value::Bool(true))
-            }
-            Value::Bool(false) => { self.exec(&children[2])?;
-            Ok(Value::Bool(false))
-            }
-            _ => Err(AsaErrorKind::TypeError),
-        }
+      // Compares two values. Numbers (integers and floats) and strings can be ordered; == works on any two
+      // values of the same type. Comparing values of different types is a type error.
+      Node::Condition{children} => self.condition(children),
+      // Evaluates the condition, then only the branch it selects. The value of the if expression is the
+      // value returned by that branch.
+      Node::IfExpression{children, ..} => self.if_expression(&children[0], &children[1], &children[2]),
+      // An else if: the second test only runs when the first one is false.
+      Node::IfElse{children} => self.if_else(children),
+      // The value returned by a branch of an if expression.
+      Node::ReturnIf{children} |
+      Node::ReturnIfNum{children} |
+      Node::ReturnElse{children} => {
+        self.exec(&children[0])
       },
-      
-      Node::IfElse{children} => {
-        let condition = self.exec(&children[0])?;
-        match condition {
-            Value::Bool(true) => { self.exec(&children[1])?;
-                Ok(Value::Bool(true))
-            }
-            Value::Bool(false) => {
-                let condition2 = self.exec(&children[2])?;
-                match condition2 {
-                    Value::Bool(true) => { self.exec(&children[3])?;
-                    Ok(Value::Bool(true))
-                    }
-                    Value::Bool(false) => { self.exec(&children[4])?;
-                        Ok(Value::Bool(false))
-                    }
-                    _ => Err(AsaErrorKind::TypeError),
-                }
-            },
-            _ => Err(AsaErrorKind::TypeError),
-        }
+
+      Node::IfAssign{children} => self.if_assign(children),
+
+      Node::MultiLineIf{children} => self.multi_line_if(children),
+      Node::Throw{children, ..} => self.throw(&children[0]),
+      Node::Try{children, ..} => self.try_catch(children),
+      // Return an error message.
+      x => {
+        unimplemented!();
       },
+    }
+  }
 
-      Node::IfAssign{children} => {
-        let var_name = match &children[0] {
-            Node::Identifier{value} => String::from_utf8_lossy(&value).into_owned(),
-            _ => return Err(AsaErrorKind::TypeError),
-        };
-        let condition = self.exec(&children[1])?;
-        let value = match condition {
-            Value::Bool(true) => self.exec(&children[2])?,
-            Value::Bool(false) => self.exec(&children[3])?,
-            _ => return Err(AsaErrorKind::TypeError),
-        };
+  // The cases of exec are kept out of exec itself, because every level of Asa recursion goes through
+  // several exec frames, so anything in exec's frame limits how deep a program can recurse. In a debug
+  // build each local of every case would otherwise get its own slot in that frame.
+  fn math(&mut self, name: &[u8], children: &[Node]) -> Result<Value,AsaErrorKind> {
+    let leftside = self.exec(&children[0])?;
+    let rightside = self.exec(&children[1])?;
+    match name {
+      b"add" => add(leftside, rightside),
+      b"sub" => sub(leftside, rightside),
+      _ => Err(AsaErrorKind::UndefinedFunction(String::from_utf8_lossy(name).into_owned())),
+    }
+  }
 
-        if let Some(frame) = self.stack.last_mut() {
-            frame.insert(var_name, value.clone());
-        }else{
-            let mut frame = Frame::new();
-            frame.insert(var_name, value.clone());
-            self.stack.push(frame);
+  fn function_call(&mut self, name: &Symbol, children: &[Node], span: Span) -> Result<Value,AsaErrorKind> {
+    let function = self.callee(name)?;
+    let call_args: &[Node] = if let Some(Node::FunctionArguments { children: args }) = &children.last() {
+      args
+    } else {
+      &[]
+    };
+    let mut values = Vec::with_capacity(call_args.len());
+    for arg_node in call_args {
+      values.push(self.exec(arg_node)?);
+    }
+    self.invoke(name.as_str(), span, &function, values)
+  }
+
+  fn define_constant(&mut self, children: &[Node]) -> Result<Value,AsaErrorKind> {
+    let qualified = self.qualify(identifier(&children[0]));
+    if self.constants.contains_key(&qualified) {
+      return Err(AsaErrorKind::ConstReassignment(identifier_name(&children[0])));
+    }
+    let value = self.exec(&children[1])?;
+    self.constants.insert(qualified, Constant{module: self.namespace.clone(), public: false, value: value.clone()});
+    Ok(value)
+  }
+
+  fn define_variable(&mut self, children: &[Node]) -> Result<Value,AsaErrorKind> {
+    let value = self.exec(&children[1])?;
+    let var_name = identifier(&children[0]).clone();
+    if let Some(frame) = self.stack.last_mut() {
+      frame.insert(var_name, value.clone());
+    } else {
+      let mut frame = Frame::new();
+      frame.insert(var_name, value.clone());
+      self.stack.push(frame);
+    }
+    Ok(value)
+  }
+
+  fn condition(&mut self, children: &[Node]) -> Result<Value,AsaErrorKind> {
+    let leftside = self.exec(&children[0])?;
+    let rightside = self.exec(&children[2])?;
+    match &children[1] {
+      Node::Operator{value, ..} => compare(Comparison::from_operator(value), leftside, rightside),
+      _ => unreachable!(),
+    }
+  }
+
+  fn if_expression(&mut self, test: &Node, then: &Node, otherwise: &Node) -> Result<Value,AsaErrorKind> {
+    match self.exec(test)? {
+      Value::Bool(true) => self.exec(then),
+      Value::Bool(false) => self.exec(otherwise),
+      other => Err(not_bool(&other)),
+    }
+  }
+
+  fn if_else(&mut self, children: &[Node]) -> Result<Value,AsaErrorKind> {
+    match self.exec(&children[0])? {
+      Value::Bool(true) => self.exec(&children[1]),
+      Value::Bool(false) => self.if_expression(&children[2], &children[3], &children[4]),
+      other => Err(not_bool(&other)),
+    }
+  }
+
+  fn throw(&mut self, value: &Node) -> Result<Value,AsaErrorKind> {
+    Err(AsaErrorKind::Thrown(self.exec(value)?))
+  }
+
+  fn program(&mut self, node: &Node, children: &[Node]) -> Result<Value,AsaErrorKind> {
+    // A program that was parsed with errors doesn't run at all.
+    let errors = syntax_errors(node);
+    if !errors.is_empty() {
+      return Err(AsaErrorKind::SyntaxError(errors));
+    }
+    self.backtrace.clear();
+    self.check_constants(children)?;
+    let mut return_val = Err(AsaErrorKind::NoRun) ;
+    for n in children {
+      match n {
+        Node::Import{..} |
+        Node::Export{..} |
+        Node::ConstDefine{..} |
+        Node::FunctionDefine{..} |
+        Node::Expression{..} |
+        Node::VariableDefine{..} |
+        Node::Throw{..} |
+        Node::Try{..} |
+        Node::String{..} |
+        Node::Number{..} |
+        Node::Bool{..} => {
+          return_val = Ok (self.exec(n)?) ;
         }
+        // A return parses as a statement anywhere, but there is no function here to return from.
+        Node::FunctionReturn{..} => return Err(AsaErrorKind::Generic("'return' outside of a function".to_string())),
+        _ => unreachable!(),
+      }
+    }
+    return_val 
+  }
 
+  fn import(&mut self, path: &str) -> Result<Value,AsaErrorKind> {
+    let path = self.modules.resolve(path)?;
+    if self.modules.is_loaded(&path) {
+      return Ok(Value::Bool(true));
+    }
+    let name = self.modules.claim(&path)?;
+    self.modules.start(path.clone());
+    let saved = self.namespace.replace(Symbol::new(&name));
+    let depth = self.stack.len();
+    self.stack.push(Frame::new());
+    let result = self.modules.parse(&path).and_then(|tree| self.exec(&tree)).map_err(|e| self.modules.imported(e));
+    self.stack.truncate(depth);
+    self.namespace = saved;
+    // A module that failed part way leaves none of its definitions behind.
+    if result.is_err() {
+      let namespace = Some(Symbol::new(&name));
+      self.functions.retain(|(module, _), _| *module != namespace);
+      self.constants.retain(|(module, _), _| *module != namespace);
+    }
+    self.modules.finish(result.is_ok());
+    result.map(|_| Value::Bool(true))
+  }
 
-        let result = if let Value::Bool(true) = condition {
-            self.exec(&children[2])?
-        }else{
-            self.exec(&children[3])?
-        };
-      Ok(value)
-    },
+  fn define_function(&mut self, name: &Symbol, children: &[Node]) -> Result<Value,AsaErrorKind> {
+    let params = match &children[0] {
+        Node::FunctionArguments {children} => children.iter().map(|arg| identifier(&arg.children()[0]).clone()).collect(),
+        _ => unreachable!(),
+    };
+    let stmts = match &children[1] {
+        Node::FunctionStatements {children} => children.clone(),
+        _ => unreachable!(),
+    };
+    let function_name = self.qualify(name);
+    let def = FunctionDef{module: self.namespace.clone(), params, stmts, code: OnceCell::new()};
+    self.functions.insert(function_name, Function::User{def: Rc::new(def), public: false});
+    Ok(Value::Bool(true))
+  }
 
-      Node::MultiLineIf{children} => {
-        let condition = self.exec(&children[0])?;
-        match condition {
-            Value::Bool(true) => {
-                if let Node::FunctionStatements{children: then_branch} = &children[1] {
-                    for stmt in then_branch {
-                        self.exec(stmt)?;
-                        }
-                    }
-                    Ok(Value::Bool(true))
-                }
-            Value::Bool(false) => {
-                if let Node::FunctionStatements{children: else_branch} = &children[2] {
-                    for stmt in else_branch{
+  fn export(&mut self, children: &[Node]) -> Result<Value,AsaErrorKind> {
+    let result = self.exec(&children[0])?;
+    match &children[0] {
+        Node::FunctionDefine{name, ..} => {
+            let function_name = self.qualify(name);
+            if let Some(Function::User{public, ..}) = self.functions.get_mut(&function_name) {
+                *public = true;
+            }
+        },
+        Node::ConstDefine{children} => {
+            let const_name = self.qualify(identifier(&children[0]));
+            if let Some(constant) = self.constants.get_mut(&const_name) {
+                constant.public = true;
+            }
+        },
+        _ => (),
+    }
+    Ok(result)
+  }
+
+  fn if_assign(&mut self, children: &[Node]) -> Result<Value,AsaErrorKind> {
+    let var_name = identifier(&children[0]).clone();
+    let condition = self.exec(&children[1])?;
+    let value = match condition {
+        Value::Bool(true) => self.exec(&children[2])?,
+        Value::Bool(false) => self.exec(&children[3])?,
+        other => return Err(not_bool(&other)),
+    };
+
+    if let Some(frame) = self.stack.last_mut() {
+        frame.insert(var_name, value.clone());
+    }else{
+        let mut frame = Frame::new();
+        frame.insert(var_name, value.clone());
+        self.stack.push(frame);
+    }
+    Ok(value)
+  }
+
+  fn multi_line_if(&mut self, children: &[Node]) -> Result<Value,AsaErrorKind> {
+    let condition = self.exec(&children[0])?;
+    match condition {
+        Value::Bool(true) => {
+            if let Node::FunctionStatements{children: then_branch} = &children[1] {
+                for stmt in then_branch {
                     self.exec(stmt)?;
                     }
                 }
-                Ok(Value::Bool(false))
+                Ok(Value::Bool(true))
+            }
+        Value::Bool(false) => {
+            if let Node::FunctionStatements{children: else_branch} = &children[2] {
+                for stmt in else_branch{
+                self.exec(stmt)?;
+                }
             }
-            _ => Err(AsaErrorKind::TypeError),
+            Ok(Value::Bool(false))
+        }
+        other => Err(not_bool(&other)),
+    }
+  }
+
+  // Runs the try block. If it fails, the error is bound to the catch variable and the catch block runs
+  // instead. A thrown value is bound as it is; a built-in error becomes a map with its kind, code and
+  // message. The finally block runs last either way, and its own errors propagate. The catch variable
+  // can be used up to the end of the finally block; after that, the variable of that name outside the
+  // try, if any, has its old value again.
+  fn try_catch(&mut self, children: &[Node]) -> Result<Value,AsaErrorKind> {
+    let mut caught = None;
+    let result = match self.block(&children[0]) {
+      Err(error) => {
+        self.backtrace.clear();
+        let value = match error {
+          AsaErrorKind::Thrown(value) => value,
+          error => Value::Map(HashMap::from([
+            ("kind".to_string(), Value::String(error.kind().to_string())),
+            ("code".to_string(), Value::String(error.code().to_string())),
+            ("message".to_string(), Value::String(error.to_string())),
+          ])),
+        };
+        let name = identifier(&children[1]);
+        if self.stack.is_empty() {
+          self.stack.push(Frame::new());
+        }
+        caught = Some(self.stack.last_mut().unwrap().insert(name.clone(), value));
+        self.block(&children[2])
+      },
+      result => result,
+    };
+    let finally = match children.get(3) {
+      Some(finally) => {
+        // An error on its way out keeps its backtrace while the finally block makes calls of its own.
+        let backtrace = std::mem::take(&mut self.backtrace);
+        let finally = self.block(finally);
+        self.backtrace = backtrace;
+        finally
+      },
+      None => Ok(Value::Bool(true)),
+    };
+    self.restore(&children[1], caught);
+    finally?;
+    result
+  }
+
+  // Puts back the binding a catch variable replaced, or removes the catch variable if it was new.
+  // Nothing was bound if the try block didn't fail.
+  fn restore(&mut self, variable: &Node, caught: Option<Option<Value>>) {
+    let (Some(previous), Some(frame)) = (caught, self.stack.last_mut()) else {
+      return;
+    };
+    let name = identifier(variable);
+    match previous {
+      Some(value) => frame.insert(name.clone(), value),
+      None => frame.remove(name),
+    };
+  }
+
+  // The value of a block is that of its last statement, or true for an empty block.
+  fn block(&mut self, block: &Node) -> Result<Value,AsaErrorKind> {
+    let mut result = Value::Bool(true);
+    for stmt in block.children() {
+      result = self.exec(stmt)?;
+    }
+    Ok(result)
+  }
+
+  // The value of a variable in the current frame, or else of a constant.
+  fn variable(&self, name: &Symbol) -> Result<Value,AsaErrorKind> {
+    match self.stack.last().and_then(|frame| frame.get(name)) {
+      Some(value) => Ok(value.clone()),
+      None => self.constant(name),
+    }
+  }
+
+  fn field_access(&mut self, value: &Node, name: &Symbol) -> Result<Value,AsaErrorKind> {
+    let base = match value {
+      Node::Identifier{value: base, ..} => match self.stack.last().and_then(|frame| frame.get(base)) {
+        Some(base) => base.clone(),
+        None => return self.global_field(base, name),
+      },
+      value => self.exec(value)?,
+    };
+    field(base, name, || path(value))
+  }
+
+  // base.name where base isn't a variable: a field of the constant base if there is one, and otherwise
+  // the constant name of the module whose namespace is base.
+  pub(crate) fn global_field(&self, base: &Symbol, name: &Symbol) -> Result<Value,AsaErrorKind> {
+    if self.resolve_constant(base).is_some() {
+      return field(self.constant(base)?, name, || base.to_string());
+    }
+    let constant = self.constants.get(&(Some(base.clone()), name.clone()));
+    self.visible(constant, || format!("{}.{}", base, name))
+  }
+
+  // Returns true if a function (user defined or native) with this name is in the function table.
+  pub fn has_function(&self, name: &str) -> bool {
+    self.functions.contains_key(&key(&Symbol::new(name)))
+  }
+
+  // The names of the global functions, for completion in the language server.
+  pub(crate) fn function_names(&self) -> impl Iterator<Item = &str> {
+    self.functions.keys().filter(|(module, _)| module.is_none()).map(|(_, name)| name.as_str())
+  }
+
+  // The arity of a function in the function table, for the checker.
+  pub(crate) fn arity(&self, name: &str) -> Option<Arity> {
+    self.functions.get(&key(&Symbol::new(name))).map(Function::arity)
+  }
+
+  // True if a constant with this name has already been defined, for the checker.
+  pub(crate) fn has_constant(&self, name: &str) -> bool {
+    self.resolve_constant(&Symbol::new(name)).is_some()
+  }
+
+  // Calls a previously defined Asa function (or registered native) from Rust. Arguments are converted
+  // with IntoArgs, and the return value is converted back into the requested Rust type with FromValue.
+  pub fn call<A: IntoArgs, R: FromValue>(&mut self, name: &str, args: A) -> Result<R,AsaErrorKind> {
+    let function = match self.functions.get(&key(&Symbol::new(name))) {
+      Some(function) => function.clone(),
+      None => return Err(AsaErrorKind::UndefinedFunction(name.to_string())),
+    };
+    let values = args.into_args()?;
+    R::from_value(self.invoke(name, Span::default(), &function, values)?)
+  }
+
+  // The key a definition in the running module is stored under.
+  fn qualify(&self, name: &Symbol) -> Key {
+    (self.namespace.clone(), name.clone())
+  }
+
+  // Looks up a function called from the running code. Inside a module an unqualified name refers to the
+  // module's own function if there is one, and otherwise to a global one.
+  fn resolve_function(&self, name: &Symbol) -> Option<Function> {
+    let local = match &self.namespace {
+      Some(_) if name.parts().is_none() => self.functions.get(&self.qualify(name)),
+      _ => None,
+    };
+    local.or_else(|| self.functions.get(&key(name))).cloned()
+  }
+
+  // The function a call in the running code refers to. Private functions of a module can only be
+  // called from inside that module.
+  pub(crate) fn callee(&self, name: &Symbol) -> Result<Function,AsaErrorKind> {
+    match self.resolve_function(name) {
+      Some(Function::User{def, public: false}) if def.module.is_some() && self.namespace != def.module => {
+        Err(AsaErrorKind::PrivateAccess{module: def.module.as_ref().unwrap().to_string(), name: name.to_string()})
+      },
+      Some(function) => Ok(function),
+      None => Err(AsaErrorKind::UndefinedFunction(name.to_string())),
+    }
+  }
+
+  // The value of a constant referred to by the running code, with the same privacy rule as callee.
+  pub(crate) fn constant(&self, name: &Symbol) -> Result<Value,AsaErrorKind> {
+    self.visible(self.resolve_constant(name), || name.to_string())
+  }
+
+  // The value of a constant that was found, unless it is private to another module. The name is only
+  // needed for the error.
+  fn visible(&self, constant: Option<&Constant>, name: impl Fn() -> String) -> Result<Value,AsaErrorKind> {
+    match constant {
+      Some(Constant{module: Some(module), public: false, ..}) if self.namespace.as_ref() != Some(module) => {
+        Err(AsaErrorKind::PrivateAccess{module: module.to_string(), name: name()})
+      },
+      Some(constant) => Ok(constant.value.clone()),
+      None => Err(AsaErrorKind::UndefinedVariable(name())),
+    }
+  }
+
+  // Looks up a constant the same way resolve_function looks up a function.
+  fn resolve_constant(&self, name: &Symbol) -> Option<&Constant> {
+    let local = match &self.namespace {
+      Some(_) if name.parts().is_none() => self.constants.get(&self.qualify(name)),
+      _ => None,
+    };
+    local.or_else(|| self.constants.get(&key(name)))
+  }
+
+  // Rejects a program that rebinds a constant before any of it runs. A constant may be defined only once,
+  // and no let anywhere in the program may reuse the name of a constant it defines or can already see.
+  fn check_constants(&self, children: &[Node]) -> Result<(),AsaErrorKind> {
+    let mut names = Vec::new();
+    for node in children {
+      let definition = match node {
+        Node::Export{children} => &children[0],
+        node => node,
+      };
+      if let Node::ConstDefine{children} = definition {
+        let name = identifier_name(&children[0]);
+        if names.contains(&name) {
+          return Err(AsaErrorKind::ConstReassignment(name));
         }
+        names.push(name);
       }
-      // Return an error message.
-      x => {
-        unimplemented!();
+    }
+    let mut bindings = Vec::new();
+    for node in children {
+      let_bindings(node, &mut bindings);
+    }
+    match bindings.into_iter().find(|name| names.contains(name) || self.resolve_constant(&Symbol::new(name)).is_some()) {
+      Some(name) => Err(AsaErrorKind::ConstReassignment(name)),
+      None => Ok(()),
+    }
+  }
+
+  // The calls the last runtime error unwound through, innermost first. The outermost call, from the
+  // host or start_main, has no call site.
+  pub fn backtrace(&self) -> &[CallSite] {
+    &self.backtrace
+  }
+
+  // Calls a function by name from the given call site with already evaluated arguments. If the call
+  // fails, it is added to the backtrace on the way out.
+  pub(crate) fn invoke(&mut self, name: &str, site: Span, function: &Function, values: Vec<Value>) -> Result<Value,AsaErrorKind> {
+    // A backtrace left from before belongs to an error that has already been dealt with.
+    self.backtrace.clear();
+    let result = self.enter(name, function, values);
+    if result.is_err() {
+      self.backtrace.push(CallSite{function: name.to_string(), span: site});
+    }
+    result
+  }
+
+  // Runs a function with already evaluated arguments. User functions get a fresh stack frame with each
+  // parameter bound to the matching argument; native functions receive the argument values directly.
+  fn enter(&mut self, name: &str, function: &Function, values: Vec<Value>) -> Result<Value,AsaErrorKind> {
+    match function {
+      Function::Native{arity, func} => {
+        if !arity.accepts(values.len()) {
+          return Err(AsaErrorKind::ArityMismatch{name: name.to_string(), expected: *arity, found: values.len()});
+        }
+        func(&values)
+      },
+      Function::User{def, ..} => {
+        if values.len() != def.params.len() {
+          return Err(AsaErrorKind::ArityMismatch{name: name.to_string(), expected: Arity::Exact(def.params.len()), found: values.len()});
+        }
+        // Runaway recursion is stopped here, before it overflows the native stack, so the error
+        // unwinds normally and gets a backtrace.
+        if self.depth >= self.max_depth {
+          return Err(AsaErrorKind::StackError);
+        }
+        self.depth += 1;
+        let result = self.run_body(def, values);
+        self.depth -= 1;
+        result
       },
     }
   }
 
+  fn run_body(&mut self, def: &FunctionDef, values: Vec<Value>) -> Result<Value,AsaErrorKind> {
+    // With the bytecode back end the body is compiled on the first call. Functions the compiler
+    // doesn't support keep running on the tree-walker.
+    if self.backend == Backend::Bytecode {
+      if let Some(chunk) = def.code.get_or_init(|| vm::compile(&def.params, &def.stmts).map(Rc::new)) {
+        let saved = std::mem::replace(&mut self.namespace, def.module.clone());
+        let result = vm::run(self, chunk, values);
+        self.namespace = saved;
+        return result;
+      }
+    }
+    let mut new_frame = Frame::new();
+    for (arg_name, result) in def.params.iter().zip(values) {
+      new_frame.insert(arg_name.clone(), result);
+    }
+    let saved = std::mem::replace(&mut self.namespace, def.module.clone());
+    let depth = self.stack.len();
+    self.stack.push(new_frame);
+    let mut result = Err(AsaErrorKind::NoRun);
+    for stmt in &def.stmts {
+      result = self.exec(stmt);
+      if result.is_err() {
+        break;
+      }
+    }
+    // Truncating rather than popping also drops any frame the body pushed before it failed.
+    self.stack.truncate(depth);
+    self.namespace = saved;
+    result
+  }
+
   pub fn start_main(&mut self, arguments: Vec<Node>) -> Result<Value,AsaErrorKind> {
     // This node is equivalent to the following Asa program source code:
     // "main()"
     // It calls the main function with a FunctionArguments node as input.
-    let start_main = Node::FunctionCall{name: "main".into(), children: arguments};
+    let start_main = Node::FunctionCall{name: Symbol::new("main"), children: arguments, span: Span::default()};
     // Call the main function by running this code through the interpreter. 
     self.exec(&start_main)
   }
 }
+
+// A field of a value. Only maps have fields. The path of the value is only needed for the error.
+pub(crate) fn field(value: Value, name: &Symbol, path: impl Fn() -> String) -> Result<Value,AsaErrorKind> {
+  match value {
+    Value::Map(mut map) => map.remove(name.as_str()).ok_or_else(|| AsaErrorKind::UndefinedVariable(format!("{}.{}", path(), name))),
+    other => Err(AsaErrorKind::type_error(".", "map", other.type_name())),
+  }
+}
+
+// The source text of a value whose field is read, e.g. e or config.limits
+fn path(node: &Node) -> String {
+  match node {
+    Node::FieldAccess{children, name, ..} => format!("{}.{}", path(&children[0]), name),
+    Node::Identifier{value, ..} => value.to_string(),
+    _ => unreachable!(),
+  }
+}
+
+fn identifier(node: &Node) -> &Symbol {
+  match node {
+    Node::Identifier{value, ..} => value,
+    _ => unreachable!(),
+  }
+}
+
+fn identifier_name(node: &Node) -> String {
+  identifier(node).to_string()
+}
+
+// Collects the names bound by every let in a tree, including lets inside function bodies.
+fn let_bindings(node: &Node, names: &mut Vec<String>) {
+  match node {
+    Node::VariableDefine{children} | Node::IfAssign{children} => names.push(identifier_name(&children[0])),
+    Node::Try{children, ..} => names.push(identifier_name(&children[1])),
+    _ => (),
+  }
+  for child in node.children() {
+    let_bindings(child, names);
+  }
+}
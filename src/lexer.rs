@@ -1,6 +1,9 @@
 use nom::*;
 
 use core::iter::*;
+use std::fmt;
+use std::ops::Range;
+use std::rc::Rc;
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct Token {
@@ -32,6 +35,41 @@ impl Token {
     self.kind = new_kind;
   }
 
+  pub fn span(&self) -> Span {
+    Span {
+      start_line: self.start_line,
+      start_col: self.start_col,
+      end_line: self.end_line,
+      end_col: self.end_col,
+    }
+  }
+
+}
+
+// The location of a piece of source code, from the first character to the last (inclusive).
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Span {
+  pub start_line: u32,
+  pub start_col: u32,
+  pub end_line: u32,
+  pub end_col: u32,
+}
+
+impl Span {
+  // The span from the start of this one to the end of another.
+  pub fn to(self, end: Span) -> Span {
+    Span {
+      end_line: end.end_line,
+      end_col: end.end_col,
+      ..self
+    }
+  }
+}
+
+impl fmt::Display for Span {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}:{}", self.start_line, self.start_col)
+  }
 }
 
 
@@ -45,6 +83,13 @@ pub enum TokenKind {
   Let,
   If,
   Else,
+  Import,
+  Pub,
+  Const,
+  Try,
+  Catch,
+  Finally,
+  Throw,
   //------
   Alpha,
   Digit,
@@ -64,36 +109,55 @@ pub enum TokenKind {
   NewLine,
   Semicolon,
   Comma,
+  Colon,
+  Dot,
   Slash,
+  // Any other character inside a string literal
+  Text,
+  // A // comment, up to the end of the line
+  Comment,
   Other,
   EOF,
 }
 
-#[derive(Debug, PartialEq, Clone)]
+impl TokenKind {
+  // Tokens that only matter for the layout of the source. The parser never sees them; a lossless
+  // lexer keeps them.
+  pub fn is_trivia(&self) -> bool {
+    matches!(self, TokenKind::WhiteSpace | TokenKind::Tab | TokenKind::NewLine | TokenKind::Comment)
+  }
+}
+
+// A view of the token stream the parser works on. All views share the tokens produced by the lexer
+// and only differ in the range they cover, so taking or skipping tokens never copies them.
+#[derive(Clone)]
 pub struct Tokens {
-    pub tokens: Vec<Token>,
+    storage: Rc<[Token]>,
+    range: Range<usize>,
 }
 
 impl Tokens {
     pub fn new() -> Tokens {
-        Tokens { tokens: vec![] }
+        Tokens::from(vec![])
     }
 
     pub fn from(tokens: Vec<Token>) -> Tokens {
-        Tokens { tokens }
+        let range = 0..tokens.len();
+        Tokens { storage: tokens.into(), range }
     }
 
-    pub fn push(&mut self, token: Token) {
-        self.tokens.push(token);
+    // The tokens in this view
+    pub fn tokens(&self) -> &[Token] {
+      &self.storage[self.range.clone()]
     }
 
     pub fn len(&self) -> usize {
-      self.tokens.len()
+      self.range.len()
     }
 
     pub fn is_done(&self) -> bool {
         if !self.is_empty() {
-            match &self.tokens[0].kind {
+            match &self.tokens()[0].kind {
                 TokenKind::EOF => true,
                 _ => false,
             }
@@ -103,40 +167,57 @@ impl Tokens {
     }
 
     pub fn is_empty(&self) -> bool {
-      self.tokens.is_empty()
+      self.range.is_empty()
+    }
+
+    // A view of part of this one; the range is relative to the start of this view.
+    fn slice(&self, range: Range<usize>) -> Tokens {
+        Tokens {
+            storage: self.storage.clone(),
+            range: self.range.start + range.start..self.range.start + range.end,
+        }
+    }
+
+}
+
+impl fmt::Debug for Tokens {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Tokens").field("tokens", &self.tokens()).finish()
     }
+}
 
+// Two views are equal when they hold the same tokens, wherever those are stored.
+impl PartialEq for Tokens {
+    fn eq(&self, other: &Tokens) -> bool {
+        self.tokens() == other.tokens()
+    }
 }
 
 impl InputLength for Tokens {
     fn input_len(&self) -> usize {
-        self.tokens.len()
+        self.len()
     }
 }
 
 impl InputTake for Tokens{
     fn take(&self, count: usize) -> Self {
-        Tokens {
-            tokens: self.tokens.iter().take(count).cloned().collect(),
-        }
+        self.slice(0..count)
     }
     fn take_split(&self, count: usize) -> (Self, Self) {
-        let (left, right) = self.tokens.split_at(count);
-        (Tokens { tokens: left.to_vec() }, Tokens { tokens: right.to_vec() })
+        (self.slice(0..count), self.slice(count..self.len()))
     }
 }
 
-pub fn split_tokens(input: Tokens) -> IResult<Tokens, Token> {
+pub fn split_tokens<E: error::ParseError<Tokens>>(input: Tokens) -> IResult<Tokens, Token, E> {
   if input.is_empty() {
-      Err(Err::Error(nom::error::Error::new(input, nom::error::ErrorKind::Eof)))
+      Err(Err::Error(E::from_error_kind(input, error::ErrorKind::Eof)))
   } else {
-      let first = input.tokens[0].clone();
-      let rest = input.tokens.into_iter().skip(1).collect();
-      Ok((Tokens::from(rest), first))
+      let first = input.tokens()[0].clone();
+      Ok((input.slice(1..input.len()), first))
   }
 }
 
-pub fn check_token(pred: &dyn Fn(&Token) -> bool) -> impl Fn(Tokens) -> IResult<Tokens, Token> + '_ {
+pub fn check_token<E: error::ParseError<Tokens>>(pred: &dyn Fn(&Token) -> bool) -> impl Fn(Tokens) -> IResult<Tokens, Token, E> + '_ {
   move |input: Tokens| {
       let parse_res = split_tokens(input.clone())?;
       if pred(&(parse_res.1)) {
@@ -147,19 +228,98 @@ pub fn check_token(pred: &dyn Fn(&Token) -> bool) -> impl Fn(Tokens) -> IResult<
   }
 }
 
-pub fn lex(input: &str) -> Tokens {
-  let mut tokens = Tokens::new();
-  let list = input.as_bytes();
-  let mut i = 0;
-  let mut line = 1;
-  let mut col = 1;
-  let mut diff = 0;
-  while i < list.len() {
-    let c = list[i];
+// A character outside of a string literal that can't start any token.
+#[derive(Debug, PartialEq, Clone)]
+pub struct LexError {
+  pub found: char,
+  pub line: u32,
+  pub col: u32,
+}
+
+impl fmt::Display for LexError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "unexpected character {:?} at {}:{}", self.found, self.line, self.col)
+  }
+}
+
+impl std::error::Error for LexError {}
+
+// Produces the tokens of a source text one at a time, ending with an EOF token. Whitespace and
+// comments are skipped, and a character that can't start a token is reported as an error; the lexer
+// carries on after it, so a caller can either collect every error or stop at the first one.
+pub struct Lexer<'a> {
+  source: &'a str,
+  i: usize,
+  line: u32,
+  col: u32,
+  in_string: bool,
+  done: bool,
+  lossless: bool,
+}
+
+impl<'a> Lexer<'a> {
+  pub fn new(source: &'a str) -> Lexer<'a> {
+    Lexer { source, i: 0, line: 1, col: 1, in_string: false, done: false, lossless: false }
+  }
+
+  // A lexer that also yields the trivia tokens and keeps every lexeme exactly as written (an escape
+  // in a string is one Text token with both characters), so the lexemes add up to the source again.
+  pub fn lossless(source: &'a str) -> Lexer<'a> {
+    Lexer { lossless: true, ..Lexer::new(source) }
+  }
+
+  // Reads the token at the current position, including whitespace and Other tokens.
+  fn token(&mut self) -> Token {
+    let list = self.source.as_bytes();
+    let c = list[self.i];
+    let mut diff = 0;
+    // Inside a string literal every character is kept as-is (no keywords, no whitespace filtering)
+    // until the closing quote. Escapes \" \\ \n and \t produce a single token with the escaped byte.
+    if self.in_string {
+      let start = self.i;
+      let start_col = self.col;
+      let (kind, byte) = match c {
+        34 => {
+          self.in_string = false;
+          (TokenKind::Quote, c)
+        },
+        92 if self.i + 1 < list.len() => {
+          self.i += 1;
+          self.col += 1;
+          let escaped = match list[self.i] {
+            b'n' => b'\n',
+            b't' => b'\t',
+            x => x,
+          };
+          (TokenKind::Text, escaped)
+        },
+        48..=57 => (TokenKind::Digit, c),
+        65..=90 | 97..=122 => (TokenKind::Alpha, c),
+        _ => (TokenKind::Text, c),
+      };
+      let token = Token {
+        kind,
+        lexeme: if self.lossless { list[start..=self.i].to_vec() } else { vec![byte] },
+        start_col,
+        end_col: self.col,
+        start_line: self.line,
+        end_line: self.line,
+      };
+      self.i += 1;
+      self.col += 1;
+      if c == 10 {
+        self.line += 1;
+        self.col = 1;
+      }
+      return token;
+    }
+    if c == 34 {
+      self.in_string = true;
+    }
     let mut kind = match c {
         48..=57 => TokenKind::Digit,
-        65..=90 | 97..=122 => TokenKind::Alpha,
-        32 | 10 | 9=> TokenKind::WhiteSpace,
+        65..=90 | 95 | 97..=122 => TokenKind::Alpha,
+        32 | 13 => TokenKind::WhiteSpace,
         10 => TokenKind::NewLine,
         9 => TokenKind::Tab,
         62 => TokenKind::Greater,
@@ -173,130 +333,174 @@ pub fn lex(input: &str) -> Tokens {
         43 => TokenKind::Plus,
         45 => TokenKind::Dash,
         44 => TokenKind::Comma,
+        58 => TokenKind::Colon,
+        46 => TokenKind::Dot,
+        47 => TokenKind::Slash,
         34 => TokenKind::Quote,
-        x => TokenKind::Other,
+        _ => TokenKind::Other,
     };
+    let i = self.i;
+    //check if comment
+    if c == b'/' && list.get(i + 1) == Some(&b'/') {
+      kind = TokenKind::Comment;
+      diff = list[i..].iter().position(|&b| b == b'\n').unwrap_or(list.len() - i) - 1;
+    }
     //check if fn
-    if c == b'f' {
-        if i + 1 < list.len(){
-          if list[i + 1] == b'n' {
-            kind = TokenKind::Fn;
-            i += 1;
-            diff = 1;
-          }
-        }
+    if c == b'f' && i + 1 < list.len() && list[i + 1] == b'n' {
+      kind = TokenKind::Fn;
+      diff = 1;
     }
     //check if true
-    if c == b't' {
-      if i + 3 < list.len(){
-        if list[i + 1] == b'r' && list[i + 2] == b'u' && list[i + 3] == b'e' {
-          kind = TokenKind::True;
-          i += 3;
-          diff = 3;
-        }
-      }
+    if c == b't' && i + 3 < list.len() && &list[i + 1..i + 4] == b"rue" {
+      kind = TokenKind::True;
+      diff = 3;
     }
     //check if false
-    if c == b'f' {
-      if i + 4 < list.len(){
-        if list[i + 1] == b'a' && list[i + 2] == b'l' && list[i + 3] == b's' && list[i + 4] == b'e' {
-          kind = TokenKind::False;
-          i += 4;
-          diff = 4;
-        }
-      }
+    if c == b'f' && i + 4 < list.len() && &list[i + 1..i + 5] == b"alse" {
+      kind = TokenKind::False;
+      diff = 4;
     }
     //check if let
-    if c == b'l' { 
-      if i + 2 < list.len(){
-        if list[i + 1] == b'e' && list[i + 2] == b't' {
-          kind = TokenKind::Let;
-          i += 2;
-          diff = 2;
-        }
-      }
+    if c == b'l' && i + 2 < list.len() && &list[i + 1..i + 3] == b"et" {
+      kind = TokenKind::Let;
+      diff = 2;
     }
     //check if return
-    if c == b'r' {
-      if i + 5 < list.len(){
-        if list[i + 1] == b'e' && list[i + 2] == b't' && list[i + 3] == b'u' && list[i + 4] == b'r' && list[i + 5] == b'n' {
-          kind = TokenKind::Return;
-          i += 5;
-          diff = 5;
-        }
-      }
+    if c == b'r' && i + 5 < list.len() && &list[i + 1..i + 6] == b"eturn" {
+      kind = TokenKind::Return;
+      diff = 5;
     }
-
-     //check if "If"
-     if c == b'i' {
-      if i + 5 < list.len(){
-        if list[i + 1] == b'f' {
-          kind = TokenKind::If;
-          i += 1;
-          diff = 1;
-        }
-      }
+    //check if "If"
+    if c == b'i' && i + 5 < list.len() && list[i + 1] == b'f' {
+      kind = TokenKind::If;
+      diff = 1;
+    }
+    //check if "import"
+    if c == b'i' && i + 5 < list.len() && &list[i + 1..i + 6] == b"mport" && is_word(list, i, i + 6) {
+      kind = TokenKind::Import;
+      diff = 5;
+    }
+    //check if "pub"
+    if c == b'p' && i + 2 < list.len() && &list[i + 1..i + 3] == b"ub" && is_word(list, i, i + 3) {
+      kind = TokenKind::Pub;
+      diff = 2;
+    }
+    //check if "const"
+    if c == b'c' && i + 4 < list.len() && &list[i + 1..i + 5] == b"onst" && is_word(list, i, i + 5) {
+      kind = TokenKind::Const;
+      diff = 4;
     }
-
     //check if "else"
-    if c == b'e' {
-      if i + 3 < list.len(){
-        if list[i + 1] == b'l' && list[i + 2] == b's' && list[i + 3] == b'e' {
-          kind = TokenKind::Else;
-          i += 3;
-          diff = 3;
-        }
-      }
+    if c == b'e' && i + 3 < list.len() && &list[i + 1..i + 4] == b"lse" {
+      kind = TokenKind::Else;
+      diff = 3;
     }
-
-    if c == b'=' {
-      if i + 3 < list.len(){
-        if list[i + 1] == b'=' {
-          kind = TokenKind::DoubleEqual;
-          i += 1;
-          diff = 1;
-        }
-      }
+    //check if "try"
+    if c == b't' && i + 2 < list.len() && &list[i + 1..i + 3] == b"ry" && is_word(list, i, i + 3) {
+      kind = TokenKind::Try;
+      diff = 2;
+    }
+    //check if "catch"
+    if c == b'c' && i + 4 < list.len() && &list[i + 1..i + 5] == b"atch" && is_word(list, i, i + 5) {
+      kind = TokenKind::Catch;
+      diff = 4;
+    }
+    //check if "finally"
+    if c == b'f' && i + 6 < list.len() && &list[i + 1..i + 7] == b"inally" && is_word(list, i, i + 7) {
+      kind = TokenKind::Finally;
+      diff = 6;
+    }
+    //check if "throw"
+    if c == b't' && i + 4 < list.len() && &list[i + 1..i + 5] == b"hrow" && is_word(list, i, i + 5) {
+      kind = TokenKind::Throw;
+      diff = 4;
+    }
+    if c == b'=' && i + 3 < list.len() && list[i + 1] == b'=' {
+      kind = TokenKind::DoubleEqual;
+      diff = 1;
+    }
+    // A character that isn't ASCII is consumed whole, so it is reported once.
+    if kind == TokenKind::Other {
+      diff = self.source[i..].chars().next().map_or(1, char::len_utf8) - 1;
     }
-
-    
-
 
     //create token struct
     let token = Token {
         kind,
-        lexeme: if kind == TokenKind::DoubleEqual{
-          vec![b'=', b'=']
-        }else{
-          vec![c]
-        },
-        start_col: col,
-        end_col: col + diff,
-        start_line: line,
-        end_line: line,
+        lexeme: list[i..i + diff + 1].to_vec(),
+        start_col: self.col,
+        end_col: if kind == TokenKind::Other { self.col } else { self.col + diff as u32 },
+        start_line: self.line,
+        end_line: self.line,
     };
-    i +=1;
-    diff = 0;
-    tokens.push(token.clone());
-    col +=1;
-    if c == 10{
-      line +=1;
-      col = 1;
+    self.i += 1 + diff;
+    self.col = token.end_col + 1;
+    if c == 10 {
+      self.line += 1;
+      self.col = 1;
     }
+    token
   }
+}
 
-  let token = Token {
-    kind: TokenKind::EOF,
-    lexeme: vec![],
-    start_col: col,
-    end_col: col ,
-    start_line: line,
-    end_line: line,
-    };
-  tokens.push(token);
+impl<'a> Iterator for Lexer<'a> {
+  type Item = Result<Token, LexError>;
 
-  
-  let filtered_tokens: Vec<Token> = tokens.tokens.iter().filter(|tkn| tkn.kind != TokenKind::WhiteSpace).cloned().collect();
-  Tokens::from(filtered_tokens)
+  fn next(&mut self) -> Option<Self::Item> {
+    while self.i < self.source.len() {
+      let token = self.token();
+      match token.kind {
+        kind if kind.is_trivia() && !self.lossless => continue,
+        TokenKind::Other => {
+          let found = String::from_utf8_lossy(&token.lexeme).chars().next().unwrap_or(char::REPLACEMENT_CHARACTER);
+          return Some(Err(LexError{found, line: token.start_line, col: token.start_col}));
+        },
+        _ => return Some(Ok(token)),
+      }
+    }
+    if self.done {
+      return None;
+    }
+    self.done = true;
+    Some(Ok(Token {
+      kind: TokenKind::EOF,
+      lexeme: vec![],
+      start_col: self.col,
+      end_col: self.col,
+      start_line: self.line,
+      end_line: self.line,
+    }))
+  }
+}
+
+// True unless the byte at the given index continues an identifier, so that a keyword isn't matched
+// at the start of a longer name like "important".
+fn ends_word(list: &[u8], index: usize) -> bool {
+  !list.get(index).is_some_and(|b| b.is_ascii_alphanumeric() || *b == b'_')
+}
+
+// True when the bytes from start to end (exclusive) are a whole word: neither the byte before nor
+// the byte after continues an identifier, so a keyword isn't matched inside "reimport" either.
+fn is_word(list: &[u8], start: usize, end: usize) -> bool {
+  (start == 0 || ends_word(list, start - 1)) && ends_word(list, end)
 }
 
+// A character the lexer rejected, kept as an Other token.
+fn other_token(e: LexError) -> Token {
+  let mut token = Token::new();
+  token.lexeme = e.found.to_string().into_bytes();
+  (token.start_line, token.end_line, token.start_col, token.end_col) = (e.line, e.line, e.col, e.col);
+  token
+}
+
+// Lexes a whole source text for the parser. Characters the lexer rejects are kept as Other tokens,
+// which no parser accepts, so the error surfaces as a parse failure at that point.
+pub fn lex(input: &str) -> Tokens {
+  Tokens::from(Lexer::new(input).map(|token| token.unwrap_or_else(other_token)).collect())
+}
+
+// Lexes a whole source text without losing anything: trivia is kept, and rejected characters are
+// kept as Other tokens. Joining the lexemes gives back the source byte for byte.
+pub fn lex_lossless(input: &str) -> Vec<Token> {
+  Lexer::lossless(input).map(|token| token.unwrap_or_else(other_token)).collect()
+}
@@ -4,8 +4,26 @@ pub mod interpreter;
 pub mod parser;
 pub mod error;
 pub mod lexer;
+pub mod symbol;
+pub mod convert;
+pub mod checker;
+pub mod typecheck;
+pub mod cst;
+pub mod format;
+pub mod lint;
+pub mod lsp;
+mod stdlib;
+mod module;
+mod vm;
 
 pub use self::parser::*;
 pub use self::interpreter::*;
 pub use self::lexer::*;
+pub use self::symbol::*;
 pub use self::error::*;
+pub use self::convert::*;
+pub use self::checker::*;
+pub use self::typecheck::*;
+pub use self::cst::*;
+pub use self::format::*;
+pub use self::lint::*;
@@ -0,0 +1,290 @@
+use std::collections::HashMap;
+use std::fmt;
+use crate::checker::Severity;
+use crate::cst::Cst;
+use crate::error::AsaErrorKind;
+use crate::lexer::Span;
+use crate::parser::*;
+
+// Style and correctness lints for asac lint. Unlike the checker, which reports what would make a
+// program fail, the linter points at code that runs but is probably not what was meant.
+//
+// Every rule has an ID and a default severity, both of which a LintConfig can change. A finding is
+// suppressed by a pragma comment on its line or the line above it:
+//
+//   // lint: allow(unused-variable, snake-case)
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum Rule {
+  UnusedVariable,
+  UnusedParameter,
+  Shadowing,
+  ConstantCondition,
+  TypeComparison,
+  MissingReturn,
+  SnakeCase,
+}
+
+impl Rule {
+  pub const ALL: [Rule; 7] = [
+    Rule::UnusedVariable,
+    Rule::UnusedParameter,
+    Rule::Shadowing,
+    Rule::ConstantCondition,
+    Rule::TypeComparison,
+    Rule::MissingReturn,
+    Rule::SnakeCase,
+  ];
+
+  // The name used on the command line and in pragmas.
+  pub fn id(&self) -> &'static str {
+    match self {
+      Rule::UnusedVariable => "unused-variable",
+      Rule::UnusedParameter => "unused-parameter",
+      Rule::Shadowing => "shadowing",
+      Rule::ConstantCondition => "constant-condition",
+      Rule::TypeComparison => "type-comparison",
+      Rule::MissingReturn => "missing-return",
+      Rule::SnakeCase => "snake-case",
+    }
+  }
+
+  pub fn from_id(id: &str) -> Option<Rule> {
+    Rule::ALL.into_iter().find(|rule| rule.id() == id)
+  }
+
+  // A comparison of values of different types always fails at run time; everything else is a warning.
+  pub fn default_severity(&self) -> Severity {
+    match self {
+      Rule::TypeComparison => Severity::Error,
+      _ => Severity::Warning,
+    }
+  }
+}
+
+// Which rules run and how severe their findings are. None turns a rule off.
+#[derive(Debug, Default, Clone)]
+pub struct LintConfig {
+  overrides: HashMap<Rule, Option<Severity>>,
+}
+
+impl LintConfig {
+  pub fn set(&mut self, rule: Rule, severity: Option<Severity>) {
+    self.overrides.insert(rule, severity);
+  }
+
+  pub fn severity(&self, rule: Rule) -> Option<Severity> {
+    self.overrides.get(&rule).copied().unwrap_or(Some(rule.default_severity()))
+  }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Lint {
+  pub rule: Rule,
+  pub severity: Severity,
+  pub message: String,
+  pub span: Span,
+}
+
+impl fmt::Display for Lint {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}: {}[{}]: {}", self.span, self.severity, self.rule.id(), self.message)
+  }
+}
+
+// Lints a source file. Findings are sorted by location. Fails if the file can't be parsed.
+pub fn lint(source: &str, config: &LintConfig) -> Result<Vec<Lint>,AsaErrorKind> {
+  let tree = parse_source(source)?;
+  let mut linter = Linter{findings: Vec::new()};
+  let mut scope = Scope::new();
+  for node in tree.children() {
+    linter.visit(node, &mut scope);
+  }
+  let allowed = pragmas(source);
+  let mut lints: Vec<Lint> = linter.findings.into_iter()
+    .filter(|(rule, _, span)| !allowed.iter().any(|(line, rules)| (*line == span.start_line || *line + 1 == span.start_line) && rules.contains(rule)))
+    .filter_map(|(rule, message, span)| config.severity(rule).map(|severity| Lint{rule, severity, message, span}))
+    .collect();
+  lints.sort_by_key(|lint| (lint.span.start_line, lint.span.start_col));
+  Ok(lints)
+}
+
+// The rules allowed by each pragma comment, with the line it is on. Unknown rule IDs are ignored.
+fn pragmas(source: &str) -> Vec<(u32, Vec<Rule>)> {
+  Cst::new(source).comments()
+    .filter_map(|comment| {
+      let text = String::from_utf8_lossy(&comment.lexeme);
+      let rules = text.trim_start_matches('/').trim().strip_prefix("lint:")?.trim().strip_prefix("allow(")?.split_once(')')?.0
+        .split(',')
+        .filter_map(|id| Rule::from_id(id.trim()))
+        .collect();
+      Some((comment.start_line, rules))
+    })
+    .collect()
+}
+
+// The type of a value where it is obvious without running the program: literals and variables bound
+// to them. Integers and floats are both numbers.
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Known {
+  Num,
+  Bool,
+  Str,
+}
+
+impl fmt::Display for Known {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Known::Num => write!(f, "num"),
+      Known::Bool => write!(f, "bool"),
+      Known::Str => write!(f, "str"),
+    }
+  }
+}
+
+struct Binding {
+  name: String,
+  span: Span,
+  parameter: bool,
+  used: bool,
+  known: Option<Known>,
+}
+
+type Scope = Vec<Binding>;
+
+struct Linter {
+  findings: Vec<(Rule, String, Span)>,
+}
+
+impl Linter {
+
+  fn visit(&mut self, node: &Node, scope: &mut Scope) {
+    match node {
+      Node::Export{children} => self.visit(&children[0], scope),
+      Node::FunctionDefine{name, children, span} => self.function(name.as_str(), *span, children),
+      Node::ConstDefine{children} => self.visit(&children[1], scope),
+      Node::VariableDefine{children} | Node::IfAssign{children} => {
+        for child in &children[1..] {
+          self.visit(child, scope);
+        }
+        if let Node::Identifier{value, span} = &children[0] {
+          let name = value.to_string();
+          if scope.iter().any(|binding| binding.name == name) {
+            self.report(Rule::Shadowing, format!("'{}' shadows an earlier binding", name), *span);
+          }
+          self.snake_case("variable", &name, *span);
+          let known = match node {
+            Node::VariableDefine{..} => known(&children[1], scope),
+            _ => None,
+          };
+          scope.push(Binding{name, span: *span, parameter: false, used: false, known});
+        }
+      },
+      Node::Identifier{value, ..} => {
+        let name = value.as_str();
+        if let Some(binding) = scope.iter_mut().rev().find(|binding| binding.name == name) {
+          binding.used = true;
+        }
+      },
+      Node::IfExpression{children, ..} | Node::MultiLineIf{children} | Node::IfElse{children} => {
+        let tests = match node {
+          Node::IfElse{..} => vec![&children[0], &children[2]],
+          _ => vec![&children[0]],
+        };
+        for test in tests {
+          if constant(test) {
+            self.report(Rule::ConstantCondition, "the condition of this if is constant".to_string(), test.span().or(node.span()).unwrap_or_default());
+          }
+        }
+        for child in children {
+          self.visit(child, scope);
+        }
+      },
+      Node::Condition{children} => {
+        if let (Some(left), Some(right)) = (known(&children[0], scope), known(&children[2], scope)) {
+          if left != right {
+            let span = children[1].span().unwrap_or_default();
+            self.report(Rule::TypeComparison, format!("comparing {} with {} always fails", left, right), span);
+          }
+        }
+        for child in children {
+          self.visit(child, scope);
+        }
+      },
+      node => {
+        for child in node.children() {
+          self.visit(child, scope);
+        }
+      },
+    }
+  }
+
+  // A function body is linted in a scope of its own that starts with the parameters.
+  fn function(&mut self, name: &str, span: Span, children: &[Node]) {
+    self.snake_case("function", name, span);
+    let mut scope = Scope::new();
+    for param in children[0].children() {
+      if let Some(Node::Identifier{value, span}) = param.children().first() {
+        let name = value.to_string();
+        self.snake_case("parameter", &name, *span);
+        scope.push(Binding{name, span: *span, parameter: true, used: false, known: None});
+      }
+    }
+    let stmts = children[1].children();
+    for stmt in stmts {
+      self.visit(stmt, &mut scope);
+    }
+    // The value of a function is that of its last statement, so anything but a return there is
+    // probably a forgotten return.
+    if !matches!(stmts.last(), Some(Node::FunctionReturn{..})) {
+      self.report(Rule::MissingReturn, format!("function '{}' doesn't end with a return", name), span);
+    }
+    for binding in scope.into_iter().filter(|binding| !binding.used && !binding.name.starts_with('_')) {
+      if binding.parameter {
+        self.report(Rule::UnusedParameter, format!("unused parameter '{}'", binding.name), binding.span);
+      } else {
+        self.report(Rule::UnusedVariable, format!("unused variable '{}'", binding.name), binding.span);
+      }
+    }
+  }
+
+  fn snake_case(&mut self, what: &str, name: &str, span: Span) {
+    if !name.bytes().all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'_') {
+      self.report(Rule::SnakeCase, format!("{} '{}' should be snake_case", what, name), span);
+    }
+  }
+
+  fn report(&mut self, rule: Rule, message: String, span: Span) {
+    self.findings.push((rule, message, span));
+  }
+}
+
+fn known(node: &Node, scope: &Scope) -> Option<Known> {
+  match node {
+    Node::Expression{children} => known(&children[0], scope),
+    Node::Number{..} | Node::Float{..} => Some(Known::Num),
+    // + also joins strings
+    Node::MathExpression{name, children, ..} if name.as_slice() == b"add" => match (known(&children[0], scope), known(&children[1], scope)) {
+      (Some(left), Some(right)) if left == right && left != Known::Bool => Some(left),
+      _ => None,
+    },
+    Node::MathExpression{..} => Some(Known::Num),
+    Node::Bool{..} | Node::Condition{..} => Some(Known::Bool),
+    Node::String{..} => Some(Known::Str),
+    Node::Identifier{value, ..} => {
+      let name = value.as_str();
+      scope.iter().rev().find(|binding| binding.name == name).and_then(|binding| binding.known)
+    },
+    _ => None,
+  }
+}
+
+// True for a test that doesn't depend on anything: a literal, or a comparison of two literals.
+fn constant(test: &Node) -> bool {
+  match test {
+    Node::Expression{children} => constant(&children[0]),
+    Node::Bool{..} | Node::Number{..} | Node::Float{..} | Node::String{..} => true,
+    Node::Condition{children} => constant(&children[0]) && constant(&children[2]),
+    _ => false,
+  }
+}
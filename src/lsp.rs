@@ -0,0 +1,416 @@
+use std::collections::{HashMap, HashSet};
+use std::io::{self, BufRead, Write};
+use serde_json::{json, Value as Json};
+use crate::checker::{check, Severity};
+use crate::interpreter::Interpreter;
+use crate::lexer::*;
+use crate::parser::*;
+use crate::typecheck::types;
+
+// The language server behind asa-lsp. It speaks the Language Server Protocol over stdin and stdout
+// and answers from the same lexer, parser and checkers as asac: diagnostics whenever a document
+// changes, go to definition for functions and variables, the inferred type of a name on hover, the
+// functions of a document as its symbols, and completion of keywords and the names in scope.
+//
+// Documents are synced in full. LSP positions are zero-based where spans are one-based, and columns
+// are counted in bytes, which is the same as UTF-16 code units for ASCII source.
+
+const KEYWORDS: [&str; 14] = ["fn", "let", "const", "return", "if", "else", "import", "pub", "true", "false", "try", "catch", "finally", "throw"];
+
+// The protocol's numbers for kinds of symbols and completion items
+const SYMBOL_FUNCTION: u32 = 12;
+const COMPLETION_FUNCTION: u32 = 3;
+const COMPLETION_VARIABLE: u32 = 6;
+const COMPLETION_KEYWORD: u32 = 14;
+const COMPLETION_CONSTANT: u32 = 21;
+
+// A line and column, both one-based like the ones in spans.
+type Position = (u32, u32);
+
+struct Document {
+  text: String,
+  // Parsed with error recovery, so everything but the parts with syntax errors is still there while
+  // the user is in the middle of typing something.
+  tree: Node,
+}
+
+#[derive(Default)]
+pub struct Server {
+  documents: HashMap<String, Document>,
+  shutdown: bool,
+  exit: Option<i32>,
+}
+
+impl Server {
+  pub fn new() -> Server {
+    Server::default()
+  }
+
+  // The exit code once the client has sent exit: 0 after a shutdown request, 1 without one.
+  pub fn exit_code(&self) -> Option<i32> {
+    self.exit
+  }
+
+  // Handles one message from the client, returning the messages to send back. Notifications the
+  // server doesn't know are ignored, and requests it doesn't know get a MethodNotFound error.
+  pub fn handle(&mut self, message: &Json) -> Vec<Json> {
+    let params = &message["params"];
+    let uri = params["textDocument"]["uri"].as_str().unwrap_or_default().to_string();
+    let method = match message["method"].as_str() {
+      Some(method) => method,
+      // A response to a request from the server, which never sends any
+      None => return vec![],
+    };
+    let result = match method {
+      "initialize" => json!({
+        "capabilities": {
+          "textDocumentSync": 1,
+          "definitionProvider": true,
+          "hoverProvider": true,
+          "documentSymbolProvider": true,
+          "completionProvider": {},
+        },
+        "serverInfo": {"name": "asa-lsp"},
+      }),
+      "shutdown" => {
+        self.shutdown = true;
+        Json::Null
+      },
+      "exit" => {
+        self.exit = Some(if self.shutdown { 0 } else { 1 });
+        return vec![];
+      },
+      "textDocument/didOpen" => {
+        self.update(&uri, params["textDocument"]["text"].as_str().unwrap_or_default());
+        return vec![self.diagnostics(&uri)];
+      },
+      "textDocument/didChange" => {
+        // With full sync the last change holds the whole text.
+        let changes = params["contentChanges"].as_array().map_or(&[][..], Vec::as_slice);
+        if let Some(text) = changes.last().and_then(|change| change["text"].as_str()) {
+          self.update(&uri, text);
+        }
+        return vec![self.diagnostics(&uri)];
+      },
+      "textDocument/didClose" => {
+        self.documents.remove(&uri);
+        return vec![publish(&uri, Vec::new())];
+      },
+      "textDocument/definition" => self.definition(&uri, position(params)),
+      "textDocument/hover" => self.hover(&uri, position(params)),
+      "textDocument/documentSymbol" => self.symbols(&uri),
+      "textDocument/completion" => self.completion(&uri, position(params)),
+      method => return match message.get("id") {
+        Some(id) => vec![json!({"jsonrpc": "2.0", "id": id, "error": {"code": -32601, "message": format!("unknown method '{}'", method)}})],
+        None => vec![],
+      },
+    };
+    vec![json!({"jsonrpc": "2.0", "id": message["id"], "result": result})]
+  }
+
+  fn update(&mut self, uri: &str, text: &str) {
+    let (_, tree) = program(lex(text)).unwrap_or_else(|_| unreachable!());
+    self.documents.insert(uri.to_string(), Document{text: text.to_string(), tree});
+  }
+
+  // The syntax errors of a document, or the checker's findings if there are none.
+  fn diagnostics(&self, uri: &str) -> Json {
+    let Some(Document{tree, ..}) = self.documents.get(uri) else {
+      return publish(uri, Vec::new());
+    };
+    let errors = syntax_errors(tree);
+    let diagnostics = if errors.is_empty() {
+      check(tree, &Interpreter::new()).into_iter()
+        .map(|finding| diagnostic(finding.span, finding.severity(), &finding.kind.to_string()))
+        .collect()
+    } else {
+      errors.iter().map(|error| diagnostic(error.span, Severity::Error, &error.message)).collect()
+    };
+    publish(uri, diagnostics)
+  }
+
+  fn parsed(&self, uri: &str) -> Option<(&Node, &str)> {
+    self.documents.get(uri).map(|document| (&document.tree, document.text.as_str()))
+  }
+
+  fn definition(&self, uri: &str, at: Position) -> Json {
+    let target = self.parsed(uri).and_then(|(tree, text)| match reference(tree, at)? {
+      (Node::Identifier{value, ..}, _) => binding(tree, text, value.as_str(), at),
+      (_, name) => functions(tree).find(|(defined, _)| *defined == name).map(|(_, span)| span),
+    });
+    match target {
+      Some(span) => json!({"uri": uri, "range": range(span)}),
+      None => Json::Null,
+    }
+  }
+
+  fn hover(&self, uri: &str, at: Position) -> Json {
+    let hover = self.parsed(uri).and_then(|(tree, _)| {
+      let (_, name) = reference(tree, at)?;
+      let (span, ty) = types(tree).into_iter().find(|(span, _)| contains(*span, at))?;
+      Some(json!({"contents": {"kind": "plaintext", "value": format!("{}: {}", name, ty)}, "range": range(span)}))
+    });
+    hover.unwrap_or(Json::Null)
+  }
+
+  fn symbols(&self, uri: &str) -> Json {
+    let Some((tree, text)) = self.parsed(uri) else {
+      return Json::Null;
+    };
+    let types = types(tree);
+    let symbols: Vec<Json> = functions(tree)
+      .map(|(name, span)| {
+        let signature = types.iter().find(|(typed, _)| starts(*typed) == starts(span)).map(|(_, ty)| ty.to_string());
+        json!({
+          "name": name,
+          "detail": signature,
+          "kind": SYMBOL_FUNCTION,
+          "range": range(span.to(block_end(text, span))),
+          "selectionRange": range(span),
+        })
+      })
+      .collect();
+    json!(symbols)
+  }
+
+  // Names in scope at the position first, then the built-in functions and the keywords.
+  fn completion(&self, uri: &str, at: Position) -> Json {
+    let mut items = Vec::new();
+    if let Some((tree, text)) = self.parsed(uri) {
+      let function = enclosing(tree, text, at);
+      if let Some(Node::FunctionDefine{children, ..}) = function {
+        let params = children[0].children().iter().filter_map(|param| param.children().first());
+        for identifier in params.chain(lets(&children[1], at)) {
+          items.push((name_of(identifier), COMPLETION_VARIABLE));
+        }
+      }
+      for item in tree.children() {
+        if let Node::ConstDefine{children} = definition(item) {
+          items.push((name_of(&children[0]), COMPLETION_CONSTANT));
+        }
+      }
+      for identifier in lets(tree, at) {
+        items.push((name_of(identifier), COMPLETION_VARIABLE));
+      }
+      items.extend(functions(tree).map(|(name, _)| (name, COMPLETION_FUNCTION)));
+    }
+    let interpreter = Interpreter::new();
+    let mut builtins: Vec<&str> = interpreter.function_names().collect();
+    builtins.sort_unstable();
+    items.extend(builtins.into_iter().map(|name| (name.to_string(), COMPLETION_FUNCTION)));
+    items.extend(KEYWORDS.iter().map(|keyword| (keyword.to_string(), COMPLETION_KEYWORD)));
+    let mut seen = HashSet::new();
+    let items: Vec<Json> = items.into_iter()
+      .filter(|(label, _)| seen.insert(label.clone()))
+      .map(|(label, kind)| json!({"label": label, "kind": kind}))
+      .collect();
+    json!(items)
+  }
+}
+
+// The name (or call, or function definition) at a position, with the name it refers to.
+fn reference(tree: &Node, at: Position) -> Option<(&Node, String)> {
+  let mut found = None;
+  walk(tree, &mut |node| {
+    match node {
+      Node::Identifier{value, span} | Node::FunctionCall{name: value, span, ..} | Node::FunctionDefine{name: value, span, ..} if contains(*span, at) => {
+        found = Some((node, value.to_string()));
+      },
+      _ => (),
+    }
+    true
+  });
+  found
+}
+
+// Where a variable used at a position is bound: the last let or parameter of that name before it in
+// the enclosing function, then a constant, then a let at the top level.
+fn binding(tree: &Node, text: &str, name: &str, at: Position) -> Option<Span> {
+  let named = |identifier: &&Node| name_of(identifier) == name;
+  let local = match enclosing(tree, text, at) {
+    Some(Node::FunctionDefine{children, ..}) => {
+      let params = children[0].children().iter().filter_map(|param| param.children().first());
+      params.chain(lets(&children[1], at)).rev().find(named)
+    },
+    _ => None,
+  };
+  let constant = || tree.children().iter().find_map(|item| match definition(item) {
+    Node::ConstDefine{children} => Some(&children[0]).filter(named),
+    _ => None,
+  });
+  local.or_else(constant).or_else(|| lets(tree, at).into_iter().rev().find(named)).and_then(Node::span)
+}
+
+// The names bound by lets under a node and before a position, leaving out those in function bodies.
+fn lets(node: &Node, at: Position) -> Vec<&Node> {
+  let mut names = Vec::new();
+  walk(node, &mut |node| match node {
+    Node::FunctionDefine{..} => false,
+    Node::VariableDefine{children} | Node::IfAssign{children} => {
+      if children[0].span().is_some_and(|span| starts(span) <= at) {
+        names.push(&children[0]);
+      }
+      true
+    },
+    Node::Try{children, ..} => {
+      if children[1].span().is_some_and(|span| starts(span) <= at) {
+        names.push(&children[1]);
+      }
+      true
+    },
+    _ => true,
+  });
+  names
+}
+
+// The function definitions of a program with the locations of their names.
+fn functions(tree: &Node) -> impl Iterator<Item = (String, Span)> + '_ {
+  tree.children().iter().filter_map(|item| match definition(item) {
+    Node::FunctionDefine{name, span, ..} => Some((name.to_string(), *span)),
+    _ => None,
+  })
+}
+
+// The function definition a position is in, from its name to its closing brace.
+fn enclosing<'a>(tree: &'a Node, text: &str, at: Position) -> Option<&'a Node> {
+  tree.children().iter().map(definition).find(|item| match item {
+    Node::FunctionDefine{span, ..} => starts(*span) <= at && at <= ends(block_end(text, *span)),
+    _ => false,
+  })
+}
+
+// The span of the closing brace of the function whose name is at the given location.
+fn block_end(text: &str, name: Span) -> Span {
+  let tokens = lex(text);
+  let mut depth = 0;
+  for token in tokens.tokens().iter().skip_while(|token| (token.start_line, token.start_col) < starts(name)) {
+    match token.kind {
+      TokenKind::LeftCurly => depth += 1,
+      TokenKind::RightCurly if depth == 1 => return token.span(),
+      TokenKind::RightCurly => depth -= 1,
+      _ => (),
+    }
+  }
+  name
+}
+
+// The definition in a top-level item, which may be exported.
+fn definition(item: &Node) -> &Node {
+  match item {
+    Node::Export{children} => &children[0],
+    item => item,
+  }
+}
+
+// Visits a node and its descendants in order, skipping the descendants of a node when the visitor
+// returns false for it.
+fn walk<'a>(node: &'a Node, visit: &mut impl FnMut(&'a Node) -> bool) {
+  if visit(node) {
+    for child in node.children() {
+      walk(child, visit);
+    }
+  }
+}
+
+fn name_of(identifier: &Node) -> String {
+  match identifier {
+    Node::Identifier{value, ..} => value.to_string(),
+    _ => String::new(),
+  }
+}
+
+fn starts(span: Span) -> Position {
+  (span.start_line, span.start_col)
+}
+
+fn ends(span: Span) -> Position {
+  (span.end_line, span.end_col)
+}
+
+// True if a position is on a span or just after it, where the cursor is after typing a name.
+fn contains(span: Span, at: Position) -> bool {
+  starts(span) <= at && at <= (span.end_line, span.end_col + 1)
+}
+
+fn position(params: &Json) -> Position {
+  let line = params["position"]["line"].as_u64().unwrap_or_default() as u32;
+  let character = params["position"]["character"].as_u64().unwrap_or_default() as u32;
+  (line + 1, character + 1)
+}
+
+fn range(span: Span) -> Json {
+  json!({
+    "start": {"line": span.start_line.saturating_sub(1), "character": span.start_col.saturating_sub(1)},
+    "end": {"line": span.end_line.saturating_sub(1), "character": span.end_col},
+  })
+}
+
+fn diagnostic(span: Span, severity: Severity, message: &str) -> Json {
+  let severity = match severity {
+    Severity::Error => 1,
+    Severity::Warning => 2,
+  };
+  json!({"range": range(span), "severity": severity, "source": "asa", "message": message})
+}
+
+fn publish(uri: &str, diagnostics: Vec<Json>) -> Json {
+  json!({"jsonrpc": "2.0", "method": "textDocument/publishDiagnostics", "params": {"uri": uri, "diagnostics": diagnostics}})
+}
+
+// Reads one message, or None at the end of the input.
+pub fn read_message(input: &mut impl BufRead) -> io::Result<Option<Json>> {
+  match read_body(input)? {
+    Some(body) => serde_json::from_slice(&body).map(Some).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)),
+    None => Ok(None),
+  }
+}
+
+// Reads the body of the next message, whatever it holds, or None at the end of the input.
+fn read_body(input: &mut impl BufRead) -> io::Result<Option<Vec<u8>>> {
+  let mut length = None;
+  loop {
+    let mut line = String::new();
+    if input.read_line(&mut line)? == 0 {
+      return Ok(None);
+    }
+    let line = line.trim_end();
+    if line.is_empty() {
+      break;
+    }
+    if let Some((name, value)) = line.split_once(':') {
+      if name.eq_ignore_ascii_case("Content-Length") {
+        length = value.trim().parse().ok();
+      }
+    }
+  }
+  let length = length.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "message without a Content-Length header"))?;
+  let mut body = vec![0; length];
+  input.read_exact(&mut body)?;
+  Ok(Some(body))
+}
+
+pub fn write_message(output: &mut impl Write, message: &Json) -> io::Result<()> {
+  let body = message.to_string();
+  write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+  output.flush()
+}
+
+// Serves a client until it exits, returning the exit code. A client that goes away without exiting
+// counts as one that didn't shut the server down.
+pub fn serve(mut input: impl BufRead, mut output: impl Write) -> io::Result<i32> {
+  let mut server = Server::new();
+  while let Some(body) = read_body(&mut input)? {
+    let replies = match serde_json::from_slice(&body) {
+      Ok(message) => server.handle(&message),
+      // A body that isn't JSON has no id to answer to, so the parse error goes out with a null id.
+      Err(e) => vec![json!({"jsonrpc": "2.0", "id": null, "error": {"code": -32700, "message": format!("parse error: {}", e)}})],
+    };
+    for reply in replies {
+      write_message(&mut output, &reply)?;
+    }
+    if let Some(code) = server.exit_code() {
+      return Ok(code);
+    }
+  }
+  Ok(1)
+}
@@ -1,26 +1,284 @@
 extern crate nom;
 extern crate asalang;
 
+use std::env;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::process;
+use std::thread;
+
 use asalang::*;
 
-fn main() -> Result<(), AsaErrorKind> {
-  
-  let tokens = lex("fn main(){return foo();} fn foo(){return 5;}");
-  match program(tokens) {
-    Ok((tokens, tree)) => {
-      println!("{:?}", tokens);
-      println!("Tree: {:#?}", tree);
-      let mut interpreter = Interpreter::new();
-      let result = interpreter.exec(&tree);
-      println!("Interpreter Result: {:?}", result);
-      println!("{:?}", interpreter);
-      let result = interpreter.start_main(vec![]);
-      println!("Interpreter Result: {:?}", result);
-
-    },
-    Err(e) => println!("Error: {:?}", e),
-  }
-
-    
-  Ok(())
+// asac - runs an Asa program.
+//
+//   asac [--allow-dir DIR]... [--typecheck] [--backend tree|bytecode] [--repl] file.asa [args...]
+//   asac fmt [--check] file.asa...
+//   asac lint [--allow RULE | --warn RULE | --deny RULE]... file.asa...
+//
+// The file is read into memory, lexed, parsed, checked and interpreted, along with any files it imports. Checker findings
+// are printed as file:line:col messages, and errors stop the program from running. If it defines main(), main is called
+// with the remaining command-line arguments bound to its parameters, and an integer return value
+// becomes the process exit code. A program without main() just runs its top-level code and exits
+// with 0. With --repl, main isn't called; instead a REPL reads statements from stdin and runs them on
+// top of the program.
+// A runtime error is printed with its code, followed by the calls it unwound through; a call
+// repeated many times over, as in runaway recursion, is printed once with a count.
+//
+// Scripts run by asac can't read or write any files unless --allow-dir grants access to a directory,
+// which can be given more than once; --allow-dir . grants the current directory. --typecheck also runs the static type checker before the program runs.
+// --backend selects the engine that runs functions: the tree-walking interpreter (the default) or
+// the bytecode VM.
+//
+// asac fmt rewrites files in the canonical layout, keeping their comments. With --check nothing is
+// written; files that aren't formatted are listed and the exit code is 1.
+//
+// asac lint prints the lints found in each file and exits with 1 if any of them is an error.
+// --allow turns a rule off, and --warn and --deny make its findings warnings or errors.
+
+const USAGE: &str = "usage: asac [--allow-dir DIR]... [--typecheck] [--backend tree|bytecode] [--repl] <file.asa> [args...]\n       asac fmt [--check] <file.asa>...\n       asac lint [--allow RULE | --warn RULE | --deny RULE]... <file.asa>...";
+
+// Every Asa call also recurses on the native stack, so programs run on a thread with room for
+// CALL_DEPTH calls even in a debug build, well beyond the interpreter's default limit.
+const STACK_SIZE: usize = 256 << 20;
+const CALL_DEPTH: usize = 5000;
+
+fn main() {
+  let asac = thread::Builder::new().stack_size(STACK_SIZE).spawn(asac).expect("can't start the interpreter thread");
+  // A panic has already been reported by the thread.
+  if asac.join().is_err() {
+    process::exit(101);
+  }
+}
+
+fn asac() {
+  let args: Vec<String> = env::args().skip(1).collect();
+  match args.split_first().map(|(command, rest)| (command.as_str(), rest)) {
+    Some(("fmt", rest)) => process::exit(fmt(rest)),
+    Some(("lint", rest)) => process::exit(lint_files(rest)),
+    _ => (),
+  }
+  let mut allowed_dirs = Vec::new();
+  let mut types = false;
+  let mut interactive = false;
+  let mut backend = Backend::TreeWalker;
+  let mut rest = &args[..];
+  loop {
+    match rest {
+      [flag, dir, tail @ ..] if flag == "--allow-dir" => {
+        allowed_dirs.push(dir.clone());
+        rest = tail;
+      },
+      [flag, tail @ ..] if flag == "--typecheck" => {
+        types = true;
+        rest = tail;
+      },
+      [flag, tail @ ..] if flag == "--repl" => {
+        interactive = true;
+        rest = tail;
+      },
+      [flag, name, tail @ ..] if flag == "--backend" => {
+        backend = match name.as_str() {
+          "tree" => Backend::TreeWalker,
+          "bytecode" => Backend::Bytecode,
+          _ => usage(),
+        };
+        rest = tail;
+      },
+      _ => break,
+    }
+  }
+  let (path, script_args) = match rest.split_first() {
+    Some((path, _)) if path.starts_with("--") => usage(),
+    Some((path, script_args)) => (path, script_args),
+    None => usage(),
+  };
+  let mut interpreter = Interpreter::new();
+  interpreter.set_backend(backend);
+  interpreter.set_max_call_depth(CALL_DEPTH);
+  match run(&mut interpreter, path, script_args, &allowed_dirs, types, interactive) {
+    Ok(code) => process::exit(code),
+    Err(e) => {
+      eprintln!("error[{}]: {}", e.code(), e);
+      for line in backtrace_lines(interpreter.backtrace()) {
+        eprintln!("{}", line);
+      }
+      process::exit(1);
+    }
+  }
+}
+
+fn usage() -> ! {
+  eprintln!("{}", USAGE);
+  process::exit(2);
+}
+
+fn run(interpreter: &mut Interpreter, path: &str, script_args: &[String], allowed_dirs: &[String], types: bool, interactive: bool) -> Result<i32,AsaErrorKind> {
+  for dir in allowed_dirs {
+    interpreter.allow_dir(dir)?;
+  }
+  // A file that can't be read or parsed is reported by load_file.
+  if let Ok(tree) = fs::read_to_string(path).map_err(|e| AsaErrorKind::IoError(e.to_string())).and_then(|source| parse_source(&source)) {
+    let mut findings = check(&tree, interpreter);
+    if types {
+      findings.extend(typecheck(&tree));
+      findings.sort_by_key(|finding| (finding.span.start_line, finding.span.start_col));
+    }
+    for finding in &findings {
+      eprintln!("{}:{}", path, finding);
+    }
+    if findings.iter().any(|finding| finding.severity() == Severity::Error) {
+      return Ok(1);
+    }
+  }
+  interpreter.load_file(path)?;
+  if interactive {
+    repl(interpreter);
+    return Ok(0);
+  }
+  if !interpreter.has_function("main") {
+    return Ok(0);
+  }
+  let arguments = Node::FunctionArguments{children: script_args.iter().map(|arg| argument(arg)).collect()};
+  match interpreter.start_main(vec![arguments])? {
+    Value::Number(code) => Ok(code),
+    _ => Ok(0),
+  }
+}
+
+fn fmt(args: &[String]) -> i32 {
+  let (check, paths) = match args {
+    [flag, paths @ ..] if flag == "--check" => (true, paths),
+    paths => (false, paths),
+  };
+  if paths.is_empty() || paths.iter().any(|path| path.starts_with("--")) {
+    usage();
+  }
+  let mut code = 0;
+  for path in paths {
+    let formatted = fs::read_to_string(path).map_err(|e| AsaErrorKind::IoError(e.to_string()))
+      .and_then(|source| format_source(&source).map(|formatted| (formatted != source, formatted)));
+    match formatted {
+      Ok((false, _)) => (),
+      Ok((true, _)) if check => {
+        eprintln!("{}: not formatted", path);
+        code = 1;
+      },
+      Ok((true, formatted)) => {
+        if let Err(e) = fs::write(path, formatted) {
+          eprintln!("{}: error: {}", path, e);
+          code = 1;
+        }
+      },
+      Err(e) => {
+        eprintln!("{}: error[{}]: {}", path, e.code(), e);
+        code = 1;
+      },
+    }
+  }
+  code
+}
+
+fn lint_files(args: &[String]) -> i32 {
+  let mut config = LintConfig::default();
+  let mut rest = args;
+  loop {
+    match rest {
+      [flag, id, tail @ ..] if flag == "--allow" || flag == "--warn" || flag == "--deny" => {
+        let rule = match Rule::from_id(id) {
+          Some(rule) => rule,
+          None => {
+            eprintln!("unknown lint rule '{}'", id);
+            process::exit(2);
+          },
+        };
+        let severity = match flag.as_str() {
+          "--allow" => None,
+          "--warn" => Some(Severity::Warning),
+          _ => Some(Severity::Error),
+        };
+        config.set(rule, severity);
+        rest = tail;
+      },
+      _ => break,
+    }
+  }
+  if rest.is_empty() || rest.iter().any(|path| path.starts_with("--")) {
+    usage();
+  }
+  let mut code = 0;
+  for path in rest {
+    match fs::read_to_string(path).map_err(|e| AsaErrorKind::IoError(e.to_string())).and_then(|source| lint(&source, &config)) {
+      Ok(lints) => {
+        for lint in &lints {
+          eprintln!("{}:{}", path, lint);
+        }
+        if lints.iter().any(|lint| lint.severity == Severity::Error) {
+          code = 1;
+        }
+      },
+      Err(e) => {
+        eprintln!("{}: error[{}]: {}", path, e.code(), e);
+        code = 1;
+      },
+    }
+  }
+  code
+}
+
+fn parse_source(source: &str) -> Result<Node,AsaErrorKind> {
+  parse(lex(source)).map_err(AsaErrorKind::SyntaxError)
+}
+
+// Command-line arguments are coerced to the most specific Asa type they can be read as: integers
+// become numbers, "true" and "false" become booleans and everything else is passed as a string.
+fn argument(arg: &str) -> Node {
+  let value = if let Ok(value) = arg.parse::<i32>() {
+    Node::Number{value}
+  } else if let Ok(value) = arg.parse::<bool>() {
+    Node::Bool{value}
+  } else {
+    Node::String{value: arg.to_string()}
+  };
+  Node::Expression{children: vec![value]}
+}
+
+fn repl(interpreter: &mut Interpreter) {
+  let stdin = io::stdin();
+  let mut line = String::new();
+  loop {
+    print!("> ");
+    let _ = io::stdout().flush();
+    line.clear();
+    match stdin.lock().read_line(&mut line) {
+      Ok(0) | Err(_) => break,
+      Ok(_) => {},
+    }
+    if line.trim().is_empty() {
+      continue;
+    }
+    match parse_source(&line).and_then(|tree| interpreter.exec(&tree)) {
+      Ok(value) => println!("{}", value),
+      Err(e) => {
+        println!("error[{}]: {}", e.code(), e);
+        for line in backtrace_lines(interpreter.backtrace()) {
+          println!("{}", line);
+        }
+      },
+    }
+  }
+}
+
+// The calls of a backtrace, one per line. A run of identical calls is shown once, with a count.
+fn backtrace_lines(backtrace: &[CallSite]) -> Vec<String> {
+  let mut lines = Vec::new();
+  let mut rest = backtrace;
+  while let Some(call) = rest.first() {
+    let repeats = rest.iter().take_while(|other| *other == call).count();
+    lines.push(format!("  {}", call));
+    if repeats > 1 {
+      lines.push(format!("  ... repeated {} more times", repeats - 1));
+    }
+    rest = &rest[repeats..];
+  }
+  lines
 }
@@ -0,0 +1,114 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use crate::lexer::*;
+use crate::parser::*;
+use crate::error::*;
+
+// Bookkeeping for `import "file.asa";`. Every file is identified by its canonical path, which makes
+// a file reached through two different relative paths load only once. The functions of an imported
+// file live in the function table under its namespace, the file name without extension, so
+// `import "lib/util.asa";` makes `util.helper()` available. Two different files with the same name
+// would share a namespace, so importing the second is an error.
+#[derive(Debug, Default)]
+pub(crate) struct Modules {
+  // Files that finished loading, with their namespace.
+  loaded: HashMap<PathBuf, String>,
+  // Files that are currently loading, outermost first. An import of a file in this list is a cycle.
+  loading: Vec<PathBuf>,
+  // The file each namespace belongs to.
+  namespaces: HashMap<String, PathBuf>,
+}
+
+impl Modules {
+
+  // Resolves an import relative to the directory of the importing file, or the current directory
+  // for code that didn't come from a file.
+  pub(crate) fn resolve(&self, import: &str) -> Result<PathBuf,AsaErrorKind> {
+    let base = match self.loading.last().and_then(|file| file.parent()) {
+      Some(dir) => dir.join(import),
+      None => PathBuf::from(import),
+    };
+    let path = base.canonicalize().map_err(|e| self.error(Some(&base), e.to_string()))?;
+    if self.loading.contains(&path) {
+      return Err(self.error(Some(&path), "import cycle".to_string()));
+    }
+    Ok(path)
+  }
+
+  // The namespace of an imported file, which must not already belong to another file.
+  pub(crate) fn claim(&mut self, path: &Path) -> Result<String,AsaErrorKind> {
+    let name = namespace(path);
+    match self.namespaces.get(&name) {
+      Some(owner) if owner != path => {
+        Err(self.error(Some(path), format!("namespace '{}' is already used by {}", name, owner.display())))
+      },
+      _ => {
+        self.namespaces.insert(name.clone(), path.to_path_buf());
+        Ok(name)
+      },
+    }
+  }
+
+  pub(crate) fn is_loaded(&self, path: &Path) -> bool {
+    self.loaded.contains_key(path)
+  }
+
+  // Marks the start of loading a file; every import executed until the matching finish is resolved
+  // relative to it.
+  pub(crate) fn start(&mut self, path: PathBuf) {
+    self.loading.push(path);
+  }
+
+  // Marks the end of loading the current file. A file that failed to load gives up the namespace it
+  // claimed, so another file can use it.
+  pub(crate) fn finish(&mut self, loaded: bool) {
+    if let Some(path) = self.loading.pop() {
+      let name = namespace(&path);
+      if loaded {
+        self.loaded.insert(path, name);
+      } else if self.namespaces.get(&name) == Some(&path) {
+        self.namespaces.remove(&name);
+      }
+    }
+  }
+
+  // Reads and parses a file, failing with all of its syntax errors if it can't be parsed completely.
+  pub(crate) fn parse(&self, path: &Path) -> Result<Node,AsaErrorKind> {
+    let source = fs::read_to_string(path).map_err(|e| self.error(None, e.to_string()))?;
+    parse(lex(&source)).map_err(AsaErrorKind::SyntaxError)
+  }
+
+  // Any error from loading or running an imported file is reported with the chain of imports that led
+  // to it. An error from a nested import already carries its chain.
+  pub(crate) fn imported(&self, error: AsaErrorKind) -> AsaErrorKind {
+    match error {
+      AsaErrorKind::ModuleError{..} => error,
+      AsaErrorKind::SyntaxError(errors) => {
+        let messages: Vec<String> = errors.iter().map(SyntaxError::to_string).collect();
+        self.error(None, messages.join("; "))
+      },
+      error => self.error(None, error.to_string()),
+    }
+  }
+
+  // A module error carrying the names of the files being loaded, from the outermost file to the
+  // one that failed, e.g. ["main.asa", "util.asa", "main.asa"] for a cycle.
+  pub(crate) fn error(&self, failed: Option<&Path>, message: String) -> AsaErrorKind {
+    let chain = self.loading.iter()
+      .map(PathBuf::as_path)
+      .chain(failed)
+      .map(file_name)
+      .collect();
+    AsaErrorKind::ModuleError{chain, message}
+  }
+}
+
+// The namespace of a module is its file name without the extension.
+pub(crate) fn namespace(path: &Path) -> String {
+  path.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default()
+}
+
+fn file_name(path: &Path) -> String {
+  path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_else(|| path.display().to_string())
+}
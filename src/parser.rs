@@ -3,13 +3,17 @@
 // If you want to use it in your parser, you need to import it here. I've already imported a couple.
 
 //use nom::*;
+use std::fmt;
 use crate::lexer::*;
+use crate::symbol::Symbol;
+use crate::typecheck::Type;
+use crate::error::{AsaErrorKind, SyntaxError};
 use nom::sequence::tuple;
-use nom::combinator::map;
+use nom::combinator::{cut, map};
+use nom::error::context;
 
 
  use nom::{
-  IResult,
   branch::alt,
   combinator::opt, 
   multi::{many1, many0},
@@ -25,11 +29,11 @@ use nom::combinator::map;
 pub enum Node {
   Program { children: Vec<Node> },
   Statement { children: Vec<Node> },
-  FunctionDefine {name: Vec<u8>, children: Vec<Node> },
+  FunctionDefine {name: Symbol, children: Vec<Node>, span: Span },
   FunctionArguments { children: Vec<Node> },
   FunctionStatements { children: Vec<Node> },
   Condition {children: Vec<Node> },
-  IfExpression {children: Vec<Node> },
+  IfExpression {children: Vec<Node>, span: Span },
   MultiLineIf {children: Vec<Node> },
   IfElse {children: Vec<Node> },
   IfAssign {children: Vec<Node> },
@@ -38,54 +42,280 @@ pub enum Node {
   ReturnIfNum { children: Vec<Node>},
   ReturnElse {children: Vec<Node>},
   Expression { children: Vec<Node> },
-  MathExpression {name: Vec<u8>, children: Vec<Node> },
-  FunctionCall { name: Vec<u8>, children: Vec<Node> },
+  MathExpression {name: Vec<u8>, children: Vec<Node>, span: Span },
+  FunctionCall { name: Symbol, children: Vec<Node>, span: Span },
   VariableDefine { children: Vec<Node> },
-  FunctionReturn { children: Vec<Node> },
+  ConstDefine { children: Vec<Node> },
+  Import { path: String, span: Span },
+  Export { children: Vec<Node> },
+  FunctionReturn { children: Vec<Node>, span: Span },
+  // throw value. The span is that of the keyword.
+  Throw { children: Vec<Node>, span: Span },
+  // try {..} catch name {..} finally {..}: the try block, the name the error is bound to, the catch
+  // block and, if there is one, the finally block. The span is that of the try keyword.
+  Try { children: Vec<Node>, span: Span },
   Number { value: i32 },
+  Float { value: f64 },
   Bool { value: bool },
-  Identifier { value: Vec<u8> },
-  Operator {value: Vec<u8>},
+  Identifier { value: Symbol, span: Span },
+  // value.name: a field of the value, which is the only child, e.g. e.kind. When the value is a name
+  // bound to nothing, it is the namespace of a module, e.g. util.LIMIT. The span covers both.
+  FieldAccess { children: Vec<Node>, name: Symbol, span: Span },
+  Operator {value: Vec<u8>, span: Span},
+  TypeAnnotation { value: Type },
   String { value: String },
   Comment { value: Vec<u8> },
+  // A part of the source that couldn't be parsed, skipped to carry on after it. The span is where
+  // the parser got stuck.
+  Error { message: String, span: Span },
   NewLine,
   Tab,
   Null,
 }
 
+impl Node {
+  // The child nodes of any node, empty for leaves. Useful for passes that walk the whole tree.
+  pub fn children(&self) -> &[Node] {
+    match self {
+      Node::Program{children} |
+      Node::Statement{children} |
+      Node::FunctionDefine{children, ..} |
+      Node::FunctionArguments{children} |
+      Node::FunctionStatements{children} |
+      Node::Condition{children} |
+      Node::IfExpression{children, ..} |
+      Node::MultiLineIf{children} |
+      Node::IfElse{children} |
+      Node::IfAssign{children} |
+      Node::IfIndent{children} |
+      Node::ReturnIf{children} |
+      Node::ReturnIfNum{children} |
+      Node::ReturnElse{children} |
+      Node::Expression{children} |
+      Node::MathExpression{children, ..} |
+      Node::FunctionCall{children, ..} |
+      Node::VariableDefine{children} |
+      Node::ConstDefine{children} |
+      Node::Export{children} |
+      Node::FunctionReturn{children, ..} |
+      Node::Throw{children, ..} |
+      Node::Try{children, ..} |
+      Node::FieldAccess{children, ..} => children,
+      _ => &[],
+    }
+  }
+
+  // The tree with every span cleared, for comparing what two trees mean regardless of layout.
+  pub fn without_spans(mut self) -> Node {
+    self.clear_spans();
+    self
+  }
+
+  fn clear_spans(&mut self) {
+    match self {
+      Node::FunctionDefine{span, ..} |
+      Node::IfExpression{span, ..} |
+      Node::MathExpression{span, ..} |
+      Node::FunctionCall{span, ..} |
+      Node::Import{span, ..} |
+      Node::FunctionReturn{span, ..} |
+      Node::Throw{span, ..} |
+      Node::Try{span, ..} |
+      Node::Identifier{span, ..} |
+      Node::FieldAccess{span, ..} |
+      Node::Operator{span, ..} |
+      Node::Error{span, ..} => *span = Span::default(),
+      _ => (),
+    }
+    for child in self.children_mut() {
+      child.clear_spans();
+    }
+  }
+
+  fn children_mut(&mut self) -> &mut [Node] {
+    match self {
+      Node::Program{children} |
+      Node::Statement{children} |
+      Node::FunctionDefine{children, ..} |
+      Node::FunctionArguments{children} |
+      Node::FunctionStatements{children} |
+      Node::Condition{children} |
+      Node::IfExpression{children, ..} |
+      Node::MultiLineIf{children} |
+      Node::IfElse{children} |
+      Node::IfAssign{children} |
+      Node::IfIndent{children} |
+      Node::ReturnIf{children} |
+      Node::ReturnIfNum{children} |
+      Node::ReturnElse{children} |
+      Node::Expression{children} |
+      Node::MathExpression{children, ..} |
+      Node::FunctionCall{children, ..} |
+      Node::VariableDefine{children} |
+      Node::ConstDefine{children} |
+      Node::Export{children} |
+      Node::FunctionReturn{children, ..} |
+      Node::Throw{children, ..} |
+      Node::Try{children, ..} |
+      Node::FieldAccess{children, ..} => children,
+      _ => &mut [],
+    }
+  }
+
+  // The location of a node: its own span if it has one, otherwise that of its first descendant with a span.
+  pub fn span(&self) -> Option<Span> {
+    match self {
+      Node::Identifier{span, ..} |
+      Node::FieldAccess{span, ..} |
+      Node::Import{span, ..} |
+      Node::FunctionCall{span, ..} |
+      Node::FunctionDefine{span, ..} |
+      Node::FunctionReturn{span, ..} |
+      Node::Throw{span, ..} |
+      Node::Try{span, ..} |
+      Node::MathExpression{span, ..} |
+      Node::IfExpression{span, ..} |
+      Node::Operator{span, ..} |
+      Node::Error{span, ..} => Some(*span),
+      node => node.children().iter().find_map(Node::span),
+    }
+  }
+}
+
+// The error of the parsers in this file. A failed parse is reported at the furthest point any
+// alternative got to, together with everything that would have been accepted there, e.g.
+// "expected ';' after statement, found 'let' at 3:14". Alternatives that fail at the same point add
+// to what was expected; one that got further replaces it. Once a construct can't be anything else,
+// like after the operator of a comparison or the colon of a type annotation, the rest of it is cut,
+// so a failure there is reported as it is instead of being dropped for a shorter alternative.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+  // The input left at the point of failure
+  pub input: Tokens,
+  // Descriptions of the tokens or constructs that would have been accepted, like "';'" or "an expression"
+  pub expected: Vec<&'static str>,
+  // Where in the program the failure is, like "after statement"
+  pub context: Option<&'static str>,
+  // What was wrong with input that did have the expected form, like "number literal out of range"
+  pub problem: Option<&'static str>,
+}
+
+pub type IResult<I, O, E = ParseError> = nom::IResult<I, O, E>;
+
+impl ParseError {
+  pub fn expected(input: Tokens, what: &'static str) -> ParseError {
+    ParseError{input, expected: vec![what], context: None, problem: None}
+  }
+
+  // An error about the tokens at the start of the input, which can't be parsed any other way.
+  pub fn invalid(input: Tokens, problem: &'static str) -> nom::Err<ParseError> {
+    nom::Err::Failure(ParseError{input, expected: Vec::new(), context: None, problem: Some(problem)})
+  }
+
+  // The location of the token the parser failed on.
+  pub fn span(&self) -> Span {
+    self.input.tokens().first().map(Token::span).unwrap_or_default()
+  }
+
+  // The error without its location.
+  pub fn message(&self) -> String {
+    if let Some(problem) = self.problem {
+      return problem.to_string();
+    }
+    let found = match self.input.tokens().first() {
+      Some(token) if token.kind != TokenKind::EOF => format!("'{}'", String::from_utf8_lossy(&token.lexeme)),
+      _ => "end of input".to_string(),
+    };
+    let expected = match self.expected.as_slice() {
+      [] => return format!("unexpected {}", found),
+      [one] => one.to_string(),
+      [init @ .., last] => format!("{} or {}", init.join(", "), last),
+    };
+    match self.context {
+      Some(context) => format!("expected {} {}, found {}", expected, context, found),
+      None => format!("expected {}, found {}", expected, found),
+    }
+  }
+}
+
+impl fmt::Display for ParseError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{} at {}", self.message(), self.span())
+  }
+}
+
+impl std::error::Error for ParseError {}
+
+impl nom::error::ParseError<Tokens> for ParseError {
+  fn from_error_kind(input: Tokens, _kind: nom::error::ErrorKind) -> ParseError {
+    ParseError{input, expected: Vec::new(), context: None, problem: None}
+  }
+
+  fn append(_input: Tokens, _kind: nom::error::ErrorKind, other: ParseError) -> ParseError {
+    other
+  }
+
+  // Less input left means the parser got further.
+  fn or(self, other: ParseError) -> ParseError {
+    if self.input.len() < other.input.len() {
+      return self;
+    }
+    if other.input.len() < self.input.len() {
+      return other;
+    }
+    let mut merged = self;
+    for what in other.expected {
+      if !merged.expected.contains(&what) {
+        merged.expected.push(what);
+      }
+    }
+    merged.context = merged.context.or(other.context);
+    merged
+  }
+}
+
+// The innermost context of an error is the one that describes it best.
+impl nom::error::ContextError<Tokens> for ParseError {
+  fn add_context(_input: Tokens, context: &'static str, mut other: ParseError) -> ParseError {
+    other.context = other.context.or(Some(context));
+    other
+  }
+}
+
+// A single token of the given kind, described as `what` in error messages.
+fn token(kind: TokenKind, what: &'static str) -> impl Fn(Tokens) -> IResult<Tokens, Token> {
+  move |input: Tokens| match input.tokens().first() {
+    Some(token) if token.kind == kind => split_tokens(input),
+    _ => Err(nom::Err::Error(ParseError::expected(input, what))),
+  }
+}
+
+// Describes what a parser accepts as a whole when it fails on the first token, instead of listing
+// every token it could have started with. Failures further in keep their own description.
+fn expecting<O>(what: &'static str, mut parser: impl FnMut(Tokens) -> IResult<Tokens, O>) -> impl FnMut(Tokens) -> IResult<Tokens, O> {
+  move |input: Tokens| match parser(input.clone()) {
+    Err(nom::Err::Error(e)) if e.input.len() == input.len() => Err(nom::Err::Error(ParseError::expected(input, what))),
+    result => result,
+  }
+}
+
 // Some helper functions to use Tokens instead of a &str with Nom. 
 // You'll probably have to create more of these as needed.
 
 pub fn t_alpha(input: Tokens) -> IResult<Tokens, Token> {
-  let fxn = check_token(& |tk| match tk.kind {
-    TokenKind::Alpha => true,
-    _ => false,
-  });
-  fxn(input.clone())
+  token(TokenKind::Alpha, "a letter")(input)
 }
 
 pub fn t_digit(input: Tokens) -> IResult<Tokens, Token> {
-  let fxn = check_token(& |tk| match tk.kind {
-    TokenKind::Digit => true,
-    _ => false,
-  });
-  fxn(input.clone())
+  token(TokenKind::Digit, "a digit")(input)
 }
 
 pub fn t_true(input: Tokens) -> IResult<Tokens, Token> {
-  let fxn = check_token(& |tk| match tk.kind {
-    TokenKind::True => true,
-    _ => false,
-  });
-  fxn(input.clone())
+  token(TokenKind::True, "'true'")(input)
 }
 
 pub fn t_false(input: Tokens) -> IResult<Tokens, Token> {
-  let fxn = check_token(& |tk| match tk.kind {
-    TokenKind::False => true,
-    _ => false,
-  });
-  fxn(input.clone())
+  token(TokenKind::False, "'false'")(input)
 }
 
 pub fn t_alpha1(input: Tokens) -> IResult<Tokens, Vec<Token>> {
@@ -108,208 +338,190 @@ pub fn t_alphanumeric0(input: Tokens) -> IResult<Tokens, Vec<Token>> {
 // keywords 
 
 pub fn t_left_paren(input: Tokens) -> IResult<Tokens, Token> {
-  let fxn = check_token(&|tk| match tk.kind {
-    TokenKind::LeftParen => true,
-    _=> false,
-  }) ;
-  fxn(input.clone())
+  token(TokenKind::LeftParen, "'('")(input)
 }
 
 pub fn t_right_paren(input: Tokens) -> IResult<Tokens, Token> {
-  let fxn = check_token(&|tk| match tk.kind {
-    TokenKind::RightParen => true,
-    _=> false,
-  }) ;
-  fxn(input.clone())
+  token(TokenKind::RightParen, "')'")(input)
 }
 
 // Helper function to parse the curly brackets
 pub fn t_left_curly(input: Tokens) -> IResult<Tokens, Token> {
-  let fxn = check_token(&|tk| match tk.kind {
-    TokenKind::LeftCurly => true,
-    _=> false,
-  }) ;
-  fxn(input.clone())
+  token(TokenKind::LeftCurly, "'{'")(input)
 }
 
 pub fn t_right_curly(input: Tokens) -> IResult<Tokens, Token> {
-  let fxn = check_token(&|tk| match tk.kind {
-    TokenKind::RightCurly => true,
-    _=> false,
-  }) ;
-  fxn(input.clone())
+  token(TokenKind::RightCurly, "'}'")(input)
 }
 
 pub fn t_quote(input: Tokens) -> IResult<Tokens, Token> {
-  let fxn = check_token(& |tk| match tk.kind {
-    TokenKind::Quote => true,
-    _ => false,
-  });
-  fxn(input.clone())
+  token(TokenKind::Quote, "'\"'")(input)
+}
+
+pub fn t_text(input: Tokens) -> IResult<Tokens, Token> {
+  token(TokenKind::Text, "text")(input)
 }
 
 pub fn t_slash(input: Tokens) -> IResult<Tokens, Token> {
-  let fxn = check_token(& |tk| match tk.kind {
-    TokenKind::Slash => true,
-    _ => false,
-  });
-  fxn(input.clone())
+  token(TokenKind::Slash, "'/'")(input)
+}
+
+pub fn t_dot(input: Tokens) -> IResult<Tokens, Token> {
+  token(TokenKind::Dot, "'.'")(input)
+}
+
+pub fn t_colon(input: Tokens) -> IResult<Tokens, Token> {
+  token(TokenKind::Colon, "':'")(input)
 }
 
 pub fn t_comma(input: Tokens) -> IResult<Tokens, Token> {
-  let fxn = check_token(&|tk| match tk.kind {
-    TokenKind::Comma => true,
-    _=> false,
-  }) ;
-  fxn(input.clone())
+  token(TokenKind::Comma, "','")(input)
 }
 
 pub fn t_semicolon(input: Tokens) -> IResult<Tokens, Token> {
-  let fxn = check_token(&|tk| match tk.kind {
-    TokenKind::Semicolon => true,
-    _=> false,
-  }) ;
-  fxn(input.clone())
+  token(TokenKind::Semicolon, "';'")(input)
 }
 
 
 pub fn t_let(input: Tokens) -> IResult<Tokens, Token> {
-  let fxn = check_token(& |tk| match tk.kind {
-    TokenKind::Let => true,
-    _ => false,
-  });
-  fxn(input.clone())
+  token(TokenKind::Let, "'let'")(input)
 }
 
 pub fn t_if(input: Tokens) -> IResult<Tokens, Token> {
-  let fxn = check_token(& |tk| match tk.kind {
-    TokenKind::If => true,
-    _ => false,
-  });
-  fxn(input.clone())
+  token(TokenKind::If, "'if'")(input)
 }
 
 pub fn t_greater(input: Tokens) -> IResult<Tokens, Token> {
-  let fxn = check_token(& |tk| match tk.kind {
-    TokenKind::Greater => true,
-    _ => false,
-  });
-  fxn(input.clone())
+  token(TokenKind::Greater, "'>'")(input)
 }
 
 pub fn t_lessthan(input: Tokens) -> IResult<Tokens, Token> {
-  let fxn = check_token(& |tk| match tk.kind {
-    TokenKind::LessThan => true,
-    _ => false,
-  });
-  fxn(input.clone())
+  token(TokenKind::LessThan, "'<'")(input)
 }
 
 pub fn t_double_equal(input: Tokens) -> IResult<Tokens, Token>{
-  let (input, x) = t_equal(input)?;
+  // The lexer usually emits == as one DoubleEqual token, but near the end of the input as two Equal tokens
+  let double = token(TokenKind::DoubleEqual, "'=='")(input.clone());
+  if double.is_ok() {
+    return double;
+  }
+  let (input, _) = t_equal(input).or(double)?;
   let (input, x) = t_equal(input)?;
    let token = Token{kind: TokenKind::DoubleEqual,
   lexeme: vec![b'=', b'='],
-start_line: input.tokens[0].start_line,
-start_col: input.tokens[0].start_col,
-end_line: input.tokens[0].end_line,
-end_col: input.tokens[0].end_col,};
+start_line: input.tokens()[0].start_line,
+start_col: input.tokens()[0].start_col,
+end_line: input.tokens()[0].end_line,
+end_col: input.tokens()[0].end_col,};
 Ok((input, token))
 }
 
 
 pub fn t_else(input: Tokens) -> IResult<Tokens, Token> {
-  let fxn = check_token(& |tk| match tk.kind {
-    TokenKind::Else => true,
-    _ => false,
-  });
-  fxn(input.clone())
+  token(TokenKind::Else, "'else'")(input)
+}
+
+pub fn t_import(input: Tokens) -> IResult<Tokens, Token> {
+  token(TokenKind::Import, "'import'")(input)
+}
+
+pub fn t_pub(input: Tokens) -> IResult<Tokens, Token> {
+  token(TokenKind::Pub, "'pub'")(input)
+}
+
+pub fn t_const(input: Tokens) -> IResult<Tokens, Token> {
+  token(TokenKind::Const, "'const'")(input)
+}
+
+pub fn t_try(input: Tokens) -> IResult<Tokens, Token> {
+  token(TokenKind::Try, "'try'")(input)
+}
+
+pub fn t_catch(input: Tokens) -> IResult<Tokens, Token> {
+  token(TokenKind::Catch, "'catch'")(input)
+}
+
+pub fn t_finally(input: Tokens) -> IResult<Tokens, Token> {
+  token(TokenKind::Finally, "'finally'")(input)
+}
+
+pub fn t_throw(input: Tokens) -> IResult<Tokens, Token> {
+  token(TokenKind::Throw, "'throw'")(input)
 }
 
 pub fn t_fn(input: Tokens) -> IResult<Tokens, Token> {
-  let fxn = check_token(& |tk| match tk.kind {
-    TokenKind::Fn => true,
-    _ => false,
-  });
-  fxn(input.clone())
+  token(TokenKind::Fn, "'fn'")(input)
 }
 
 pub fn t_return(input: Tokens) -> IResult<Tokens, Token> {
-  let fxn = check_token(& |tk| match tk.kind {
-    TokenKind::Return => true,
-    _ => false,
-  });
-  fxn(input.clone())
+  token(TokenKind::Return, "'return'")(input)
 }
 
 pub fn t_whitespace(input: Tokens) -> IResult<Tokens, Token> {
-  let fxn = check_token(& |tk| match tk.kind {
-    TokenKind::WhiteSpace => true,
-    _ => false,
-  });
-  fxn(input.clone())
+  token(TokenKind::WhiteSpace, "whitespace")(input)
 }
 
 pub fn t_tab(input: Tokens) -> IResult<Tokens, Token> {
-  let fxn = check_token(& |tk| match tk.kind {
-    TokenKind::Tab => true,
-    _ => false,
-  });
-  fxn(input.clone())
+  token(TokenKind::Tab, "a tab")(input)
 }
 
 pub fn t_newline(input: Tokens) -> IResult<Tokens, Token> {
-  let fxn = check_token(& |tk| match tk.kind {
-    TokenKind::NewLine => true,
-    _ => false,
-  });
-  fxn(input.clone())
+  token(TokenKind::NewLine, "a new line")(input)
 }
 
 pub fn t_plus(input: Tokens) -> IResult<Tokens, Token> {
-  let fxn = check_token(& |tk| match tk.kind {
-    TokenKind::Plus => true,
-    _ => false,
-  });
-  fxn(input.clone())
+  token(TokenKind::Plus, "'+'")(input)
 }
 
 pub fn t_dash(input: Tokens) -> IResult<Tokens, Token> {
-  let fxn = check_token(& |tk| match tk.kind {
-    TokenKind::Dash => true,
-    _ => false,
-  });
-  fxn(input.clone())
+  token(TokenKind::Dash, "'-'")(input)
 }
 
 pub fn t_equal(input: Tokens) -> IResult<Tokens, Token> {
-  let fxn = check_token(& |tk| match tk.kind {
-    TokenKind::Equal => true,
-    _ => false,
-  });
-  fxn(input.clone())
+  token(TokenKind::Equal, "'='")(input)
 }
 
 pub fn identifier(input: Tokens) -> IResult<Tokens, Node> {
-  let (input, first) = t_alpha(input)?;
+  let (input, first) = expecting("an identifier", t_alpha)(input)?;
   let (input, rest) = t_alphanumeric0(input)?;
+  let span = first.span().to(rest.last().unwrap_or(&first).span());
   let mut identifier = first.lexeme;
   for mut tk in rest {
     identifier.append(&mut tk.lexeme);
   }
-  Ok((input,Node::Identifier{value: identifier}))
+  Ok((input,Node::Identifier{value: Symbol::new(&String::from_utf8_lossy(&identifier)), span}))
 }
 
 pub fn number(input: Tokens) -> IResult<Tokens, Node> {
-  let (input, digits) = many1(t_digit)(input)?;
+  let start = input.clone();
+  let (input, digits) = expecting("a number", many1(t_digit))(input)?;
   let value: Vec<u8> = digits.iter()
                              .flat_map(|token| token.lexeme.iter())
                              .cloned()
                              .collect();
-  let parsed_value: i32 = std::str::from_utf8(&value).unwrap().parse::<i32>().unwrap();
+  let parsed_value: i32 = std::str::from_utf8(&value).unwrap().parse::<i32>().map_err(|_| ParseError::invalid(start, "number literal out of range"))?;
   Ok((input, Node::Number { value: parsed_value }))
 }
 
+pub fn float(input: Tokens) -> IResult<Tokens, Node> {
+  let start = input.clone();
+  let (input, whole) = expecting("a number", many1(t_digit))(input)?;
+  let (input, dot) = t_dot(input)?;
+  let (input, fraction) = many1(t_digit)(input)?;
+  let value: Vec<u8> = whole.iter()
+                            .chain(std::iter::once(&dot))
+                            .chain(fraction.iter())
+                            .flat_map(|token| token.lexeme.iter())
+                            .cloned()
+                            .collect();
+  // A literal too large for f64 parses as infinity instead of failing.
+  let parsed_value: f64 = std::str::from_utf8(&value).unwrap().parse::<f64>().unwrap();
+  if !parsed_value.is_finite() {
+    return Err(ParseError::invalid(start, "number literal out of range"));
+  }
+  Ok((input, Node::Float { value: parsed_value }))
+}
+
 pub fn boolean(input: Tokens) -> IResult<Tokens, Node> {
   let (input, token) = alt((t_true, t_false))(input)?;
    let value = match token.kind {
@@ -322,17 +534,42 @@ pub fn boolean(input: Tokens) -> IResult<Tokens, Node> {
 
 pub fn string(input: Tokens) -> IResult<Tokens, Node> {
  let (input, _) = t_quote(input)?;
-  let (input, string) = t_alphanumeric0(input)?;
+  let (input, string) = many0(alt((t_alpha, t_digit, t_text)))(input)?;
  let (input, _) = t_quote(input)?;
  let value: Vec<u8> = string.into_iter()
-                               .map(|token| token.lexeme)
-                               .flatten()
+                               .flat_map(|token| token.lexeme)
                                .collect();
- Ok((input, Node::String{ value: String::from_utf8(value).unwrap() }))
+ Ok((input, Node::String{ value: String::from_utf8_lossy(&value).into_owned() }))
+}
+
+// A name that may be qualified with the namespace of an imported module, e.g. util.helper
+pub fn qualified_identifier(input: Tokens) -> IResult<Tokens, Node> {
+  let (input, first) = identifier(input)?;
+  let (input, rest) = opt(preceded(t_dot, identifier))(input)?;
+  match (first, rest) {
+    (Node::Identifier{value: namespace, span}, Some(Node::Identifier{value: name, span: end})) => {
+      Ok((input, Node::Identifier{value: Symbol::qualified(&namespace, &name), span: span.to(end)}))
+    },
+    (first, _) => Ok((input, first)),
+  }
+}
+
+// A name followed by any number of fields, e.g. e.kind or util.LIMIT
+pub fn field_access(input: Tokens) -> IResult<Tokens, Node> {
+  let (input, first) = identifier(input)?;
+  let (input, fields) = many0(preceded(t_dot, identifier))(input)?;
+  let node = fields.into_iter().fold(first, |value, field| match field {
+    Node::Identifier{value: name, span: end} => {
+      let span = value.span().unwrap_or(end).to(end);
+      Node::FieldAccess{children: vec![value], name, span}
+    },
+    _ => unreachable!(),
+  });
+  Ok((input, node))
 }
 
 pub fn function_call(input: Tokens) -> IResult<Tokens, Node> {
-  let (input, fxn_name) = identifier(input)?;
+  let (input, fxn_name) = qualified_identifier(input)?;
   let (input, _) = (t_left_paren)(input)?;
   let (input, args) = many0(arguments)(input)?;
   let (input, _) = (t_right_paren)(input)?;
@@ -341,33 +578,33 @@ pub fn function_call(input: Tokens) -> IResult<Tokens, Node> {
   } else {
     args
   };
-  let name: Vec<u8> = match fxn_name {
-    Node::Identifier{value} => value,
+  let (name, span) = match fxn_name {
+    Node::Identifier{value, span} => (value, span),
     _ => unreachable!(),
   }; 
-  Ok((input, Node::FunctionCall{name, children: args}))
+  Ok((input, Node::FunctionCall{name, children: args, span}))
 }
 
 pub fn value(input: Tokens) -> IResult<Tokens, Node> {
-  alt((number, identifier, boolean))(input)
+  expecting("a value", alt((float, number, function_call, field_access, boolean, string)))(input)
 }
 
 pub fn math_expression(input: Tokens) -> IResult<Tokens, Node> {
   let (input, leftside) = value(input)?;
   let (input, operator) = alt((t_plus, t_dash))(input)?;
-  let (input, rightside) = value(input)?;
+  let (input, rightside) = cut(value)(input)?;
   let name = match operator.kind {
     TokenKind::Plus => b"add",
     TokenKind::Dash => b"sub",
     _ => unreachable!(),
   };
-  Ok((input, Node::MathExpression{name: name.to_vec(), children: vec![leftside, rightside] }))
+  Ok((input, Node::MathExpression{name: name.to_vec(), children: vec![leftside, rightside], span: operator.span() }))
 }
 
 pub fn return_if(input: Tokens) -> IResult<Tokens, Node> {
   let (input, x) = t_left_curly(input)?;
   let (input, x) = t_return(input)?;
-  let (input, value) = boolean(input)?; // Parses a boolean as the return value
+  let (input, value) = alt((math_expression, value))(input)?; // Parses the value returned by the branch
   let (input, x) = t_semicolon(input)?;
   let (input, x) = t_right_curly(input)?;
   Ok((input, Node::ReturnIf{children: vec![value]}))
@@ -376,7 +613,7 @@ pub fn return_if(input: Tokens) -> IResult<Tokens, Node> {
 pub fn return_ifnum(input: Tokens) -> IResult<Tokens, Node>{
   let (input, x) = t_left_curly(input)?;
   let (input, x) = t_return(input)?;
-  let (input, num_node) = number(input)?; // Parses a digit as the return value
+  let (input, num_node) = alt((math_expression, value))(input)?; // Parses the value returned by the branch
   let (input, x) = t_semicolon(input)?;
   let (input, x) = t_right_curly(input)?;
   Ok((input, Node::ReturnIfNum{children: vec![num_node]}))
@@ -385,17 +622,23 @@ pub fn return_ifnum(input: Tokens) -> IResult<Tokens, Node>{
 pub fn return_else(input: Tokens)->IResult<Tokens, Node>{
   let (input, x) = t_left_curly(input)?;
   let (input, x) = t_return(input)?;
-  let (input, num_node) = number(input)?;
+  let (input, num_node) = alt((math_expression, value))(input)?;
+  let (input, _) = opt(t_semicolon)(input)?;
   let (input, x) = t_right_curly(input)?;
   Ok((input, Node::ReturnElse{children: vec![num_node]}))
 }
 
 pub fn condition(input: Tokens) -> IResult<Tokens, Node> {
-  let (input, leftside) = identifier(input)?;
+  let (input, leftside) = value(input)?;
   let (input, operator) = alt((t_greater, t_lessthan, t_double_equal))(input)?;
-  let (input, rightside) = identifier(input)?;
+  let (input, rightside) = cut(value)(input)?;
+  let span = operator.span();
+  Ok((input, Node::Condition {children: vec![leftside, Node::Operator{value: operator.lexeme, span}, rightside] }))
+}
 
-  Ok((input, Node::Condition {children: vec![leftside, Node::Operator{value: operator.lexeme}, rightside] }))
+// The test of an if expression: a comparison or a single value that evaluates to a boolean.
+pub fn if_test(input: Tokens) -> IResult<Tokens, Node> {
+  alt((condition, value))(input)
 }
 
 pub fn if_indent(input: Tokens) -> IResult<Tokens, Node> {
@@ -406,35 +649,44 @@ pub fn if_indent(input: Tokens) -> IResult<Tokens, Node> {
 
 
 pub fn if_expression(input: Tokens) -> IResult<Tokens, Node> {
-  let (input, x) = t_if(input)?;
-  let (input, condition) = condition(input)?;
+  let (input, keyword) = t_if(input)?;
+  let (input, condition) = if_test(input)?;
   let (input, val1) = return_if(input)?;
   let (input, x) = t_else(input)?;
   let (input, val2) = return_if(input)?;
-  Ok((input, Node::IfExpression{children: vec! [condition, val1, val2]}))
+  Ok((input, Node::IfExpression{children: vec! [condition, val1, val2], span: keyword.span()}))
 }
 
 
 pub fn if_multi_line(input: Tokens) ->IResult<Tokens, Node>{
   let (input, x) = t_if(input)?;
   let (input, boolean1) = boolean(input)?;
-  let (input, x) = t_left_curly(input)?;
-  let (input, val1) = preceded(if_indent, return_if)(input)?;
-  let (input, x) = t_right_curly(input)?;
+  let (input, val1) = multi_line_branch(input)?;
   let (input, x) = t_else(input)?;
-  let (input, x) = t_left_curly(input)?;
-  let (input, val2) = preceded(if_indent, return_if)(input)?;
-  let (input, x) = t_right_curly(input)?;
+  let (input, val2) = multi_line_branch(input)?;
   Ok((input, Node::MultiLineIf{children: vec! [boolean1, val1, val2]}))
 }
 
+// A branch of a multi-line if: { return value; } with the return on a line of its own. The indent
+// is only present in a token stream that keeps trivia.
+fn multi_line_branch(input: Tokens) -> IResult<Tokens, Node> {
+  let (input, _) = t_left_curly(input)?;
+  let (input, _) = opt(if_indent)(input)?;
+  let (input, _) = t_return(input)?;
+  let (input, value) = alt((math_expression, value))(input)?;
+  let (input, _) = t_semicolon(input)?;
+  let (input, _) = opt(t_newline)(input)?;
+  let (input, _) = t_right_curly(input)?;
+  Ok((input, Node::ReturnIf{children: vec![value]}))
+}
+
 pub fn if_else(input: Tokens) ->IResult<Tokens, Node>{
   let (input, x) = t_if(input)?;
-  let (input, condition1) = condition(input)?;
+  let (input, condition1) = if_test(input)?;
   let (input, val1) = return_ifnum(input)?;
   let (input, x) = t_else(input)?;
   let (input, x) = t_if(input)?;
-  let (input, condition2) = condition(input)?;
+  let (input, condition2) = if_test(input)?;
   let (input, val2) = return_ifnum(input)?;
   let (input, x) = t_else(input)?;
   let (input, val3) = return_else(input)?;
@@ -456,20 +708,60 @@ pub fn if_assign(input: Tokens)->IResult<Tokens, Node>{
 }
 
 pub fn expression(input: Tokens) -> IResult<Tokens, Node> {
-   let (input, result) =  alt((boolean, math_expression, function_call, number, string,identifier))(input)?;
+   let (input, result) =  expecting("an expression", alt((if_else, if_expression, condition, boolean, math_expression, function_call, float, number, string,field_access)))(input)?;
    Ok((input, Node::Expression{children: vec! [result]}))
 }
 
 pub fn statement(input: Tokens) -> IResult<Tokens, Node> {
-  let (input, result) = alt((variable_define, expression, function_return))(input)?;
-  let (input, _) = (t_semicolon)(input)?;
+  let (input, result) = alt((variable_define, try_catch, throw, expression, function_return))(input)?;
+  // The semicolon is optional after a statement that ends with the closing brace of an if expression
+  let (input, _) = if ends_with_block(&result) {
+    opt(t_semicolon)(input)?
+  } else {
+    map(context("after statement", t_semicolon), Some)(input)?
+  };
   Ok((input, result))
 }
 
+fn ends_with_block(node: &Node) -> bool {
+  match node {
+    Node::IfExpression{..} | Node::IfElse{..} | Node::IfAssign{..} | Node::MultiLineIf{..} | Node::Try{..} => true,
+    Node::VariableDefine{children} | Node::Expression{children} => children.last().is_some_and(ends_with_block),
+    _ => false,
+  }
+}
+
 pub fn function_return(input: Tokens) -> IResult<Tokens, Node> {
-  let (input, _) = t_return(input)?;
-  let (input, result) = alt((function_call, expression, identifier))(input)?;
-  Ok((input, Node::FunctionReturn{children: vec! [result]}))
+  let (input, keyword) = t_return(input)?;
+  let (input, result) = alt((expression, function_call, identifier))(input)?;
+  Ok((input, Node::FunctionReturn{children: vec! [result], span: keyword.span()}))
+}
+
+pub fn throw(input: Tokens) -> IResult<Tokens, Node> {
+  let (input, keyword) = t_throw(input)?;
+  let (input, value) = cut(expression)(input)?;
+  Ok((input, Node::Throw{children: vec![value], span: keyword.span()}))
+}
+
+// try { .. } catch e { .. } with an optional finally { .. }
+pub fn try_catch(input: Tokens) -> IResult<Tokens, Node> {
+  let (input, keyword) = t_try(input)?;
+  let (input, body) = cut(block)(input)?;
+  let (input, _) = cut(context("after the try block", t_catch))(input)?;
+  let (input, variable) = cut(identifier)(input)?;
+  let (input, handler) = cut(block)(input)?;
+  let (input, finally) = opt(preceded(t_finally, cut(block)))(input)?;
+  let mut children = vec![body, variable, handler];
+  children.extend(finally);
+  Ok((input, Node::Try{children, span: keyword.span()}))
+}
+
+// The statements between braces. Unlike a function body, a block may be empty.
+fn block(input: Tokens) -> IResult<Tokens, Node> {
+  let (input, _) = t_left_curly(input)?;
+  let (input, statements) = recover(expecting("a statement", statement), input, true);
+  let (input, _) = context("to end the block", t_right_curly)(input)?;
+  Ok((input, Node::FunctionStatements{children: statements}))
 }
 
 pub fn variable_define(input: Tokens) -> IResult<Tokens, Node> {
@@ -491,29 +783,88 @@ pub fn arguments(input: Tokens) -> IResult<Tokens, Node> {
 
 pub fn other_arg(input: Tokens) -> IResult<Tokens, Node> {
   let (input, _) = t_comma(input)?;
-  expression(input)
+  cut(expression)(input)
 }
 
 pub fn function_define(input: Tokens) -> IResult<Tokens, Node> {
   let (input, _) = t_fn(input)?;
   let (input, fxn_name) = identifier(input)?;
-  let name = match fxn_name {
-    Node::Identifier{value} => value,
+  let (name, span) = match fxn_name {
+    Node::Identifier{value, span} => (value, span),
     _ => unreachable!(),
   };
   let (input, _) = t_left_paren(input)?;
-  let (input, args) = many0(arguments)(input)?;
+  let (input, args) = opt(parameters)(input)?;
   let (input, _) = t_right_paren(input)?;
+  let (input, return_type) = opt(preceded(tuple((t_dash, t_greater)), cut(type_annotation)))(input)?;
   let (input, _) = t_left_curly(input)?;
-  let (input, statements) = many1(statement)(input)?;
-  let (input, _) = t_right_curly(input)?;
+  let (input, statements) = recover(expecting("a statement", statement), input, true);
+  if statements.is_empty() {
+    return Err(nom::Err::Error(ParseError::expected(input, "a statement")));
+  }
+  let (input, _) = context("to end the function body", t_right_curly)(input)?;
   let fxn_statements = Node::FunctionStatements{children: statements};
-  let fxn_arguments = if args.is_empty() {
-    Node::FunctionArguments{children: vec![]}
-  } else {
-    args[0].clone()
+  let fxn_arguments = args.unwrap_or(Node::FunctionArguments{children: vec![]});
+  let mut children = vec![fxn_arguments,fxn_statements];
+  children.extend(return_type);
+  Ok((input, Node::FunctionDefine{name, children, span }))
+}
+
+// The parameter list of a function definition. Each parameter is an identifier with an optional
+// type annotation, e.g. fn add(a: num, b)
+pub fn parameters(input: Tokens) -> IResult<Tokens, Node> {
+  let (input, first) = parameter(input)?;
+  let (input, others) = many0(preceded(t_comma, cut(parameter)))(input)?;
+  let mut params = vec![first];
+  params.extend(others);
+  Ok((input, Node::FunctionArguments{children: params}))
+}
+
+pub fn parameter(input: Tokens) -> IResult<Tokens, Node> {
+  let (input, name) = identifier(input)?;
+  let (input, annotation) = opt(preceded(t_colon, cut(type_annotation)))(input)?;
+  let mut children = vec![name];
+  children.extend(annotation);
+  Ok((input, Node::Expression{children}))
+}
+
+// A type: num, bool, str, list<T>, map<T> (maps have string keys) or fn(T, ...) -> T
+pub fn type_annotation(input: Tokens) -> IResult<Tokens, Node> {
+  let (input, value) = type_name(input)?;
+  Ok((input, Node::TypeAnnotation{value}))
+}
+
+fn type_name(input: Tokens) -> IResult<Tokens, Type> {
+  if let Ok((input, _)) = t_fn(input.clone()) {
+    let (input, _) = t_left_paren(input)?;
+    let (input, first) = opt(type_name)(input)?;
+    let (input, others) = many0(preceded(t_comma, cut(type_name)))(input)?;
+    let (input, _) = t_right_paren(input)?;
+    let (input, _) = tuple((t_dash, t_greater))(input)?;
+    let (input, result) = type_name(input)?;
+    let params = first.into_iter().chain(others).collect();
+    return Ok((input, Type::Function(params, Box::new(result))));
+  }
+  let (rest, name) = identifier(input.clone())?;
+  let name = match name {
+    Node::Identifier{value, ..} => value,
+    _ => unreachable!(),
   };
-  Ok((input, Node::FunctionDefine{name, children: vec![fxn_arguments,fxn_statements] }))
+  match name.as_str() {
+    "num" => Ok((rest, Type::Num)),
+    "bool" => Ok((rest, Type::Bool)),
+    "str" => Ok((rest, Type::Str)),
+    "list" | "map" => {
+      let (rest, _) = t_lessthan(rest)?;
+      let (rest, item) = type_name(rest)?;
+      let (rest, _) = t_greater(rest)?;
+      match name.as_str() {
+        "list" => Ok((rest, Type::List(Box::new(item)))),
+        _ => Ok((rest, Type::Map(Box::new(item)))),
+      }
+    },
+    _ => Err(nom::Err::Error(ParseError::expected(input, "a type"))),
+  }
 }
 
 pub fn comment(input: Tokens) -> IResult<Tokens, Node> {
@@ -527,7 +878,119 @@ pub fn comment(input: Tokens) -> IResult<Tokens, Node> {
   Ok((input, Node::Comment{ value: comment_text }))
 }
 
+pub fn import(input: Tokens) -> IResult<Tokens, Node> {
+  let (input, keyword) = t_import(input)?;
+  let (input, path) = string(input)?;
+  let (input, _) = context("after import", t_semicolon)(input)?;
+  let path = match path {
+    Node::String{value} => value,
+    _ => unreachable!(),
+  };
+  Ok((input, Node::Import{path, span: keyword.span()}))
+}
+
+pub fn const_define(input: Tokens) -> IResult<Tokens, Node> {
+  let (input, _) = t_const(input)?;
+  let (input, constant) = identifier(input)?;
+  let (input, _) = t_equal(input)?;
+  let (input, value) = expression(input)?;
+  let (input, _) = context("after constant", t_semicolon)(input)?;
+  Ok((input, Node::ConstDefine{children: vec![constant, value]}))
+}
+
+// A definition marked with "pub", which makes it reachable from files that import this one.
+pub fn export(input: Tokens) -> IResult<Tokens, Node> {
+  let (input, _) = t_pub(input)?;
+  let (input, definition) = alt((function_define, const_define))(input)?;
+  Ok((input, Node::Export{children: vec![definition]}))
+}
+
+// A whole program. The parser doesn't stop at the first item it can't parse: it skips to the end of
+// it, puts a Node::Error in its place and carries on with the next one, so a single run finds every
+// syntax error in a file. Function bodies recover the same way, one statement at a time.
 pub fn program(input: Tokens) -> IResult<Tokens, Node> {
-  let (input, result) = many1(alt((function_define,expression,statement,string,boolean,number)))(input)?;
+  let item = expecting("a definition or statement", alt((import,export,const_define,function_define,statement,expression,string,boolean,number)));
+  let (input, result) = recover(item, input, false);
   Ok((input, Node::Program{ children: result }))
 }
+
+// Parses nodes until the end of the input or, in a block, the } that closes it. Anything that can't
+// be parsed is skipped up to the next ; or } outside of braces and replaced with an error node.
+fn recover(mut parser: impl FnMut(Tokens) -> IResult<Tokens, Node>, mut input: Tokens, block: bool) -> (Tokens, Vec<Node>) {
+  let mut nodes = Vec::new();
+  while !(input.is_done() || (block && input.tokens()[0].kind == TokenKind::RightCurly)) {
+    match parser(input.clone()) {
+      Ok((rest, node)) if rest.len() < input.len() => {
+        nodes.push(node);
+        input = rest;
+      },
+      result => {
+        let mut error = match result {
+          Err(nom::Err::Error(e)) | Err(nom::Err::Failure(e)) => e,
+          _ => ParseError{input: input.clone(), expected: Vec::new(), context: None, problem: None},
+        };
+        // Where a statement should start, the block could have ended instead.
+        if block && error.input.len() == input.len() {
+          error.expected.push("'}'");
+        }
+        let (rest, error) = skip(input, &error);
+        nodes.push(error);
+        input = rest;
+      },
+    }
+  }
+  (input, nodes)
+}
+
+// Skips what couldn't be parsed, up to and including the next ; or the } of a block opened in it,
+// or up to the } that closes the enclosing block.
+fn skip(input: Tokens, error: &ParseError) -> (Tokens, Node) {
+  let tokens = input.tokens();
+  let mut depth = 0;
+  let mut end = tokens.len();
+  for (i, token) in tokens.iter().enumerate() {
+    match token.kind {
+      TokenKind::EOF => end = i,
+      TokenKind::Semicolon if depth == 0 => end = i + 1,
+      TokenKind::LeftCurly => depth += 1,
+      // A } that closes nothing is skipped when it comes first, since it can't be parsed on its own.
+      TokenKind::RightCurly if depth == 0 => end = i.max(1),
+      TokenKind::RightCurly if depth == 1 => end = i + 1,
+      TokenKind::RightCurly => depth -= 1,
+      _ => continue,
+    }
+    if end != tokens.len() {
+      break;
+    }
+  }
+  let (rest, _) = nom::multi::count(split_tokens::<ParseError>, end)(input).unwrap();
+  (rest, Node::Error{message: error.message(), span: error.span()})
+}
+
+// Parses a whole program, failing with every syntax error in it if any part can't be parsed.
+pub fn parse(input: Tokens) -> Result<Node,Vec<SyntaxError>> {
+  let tree = match program(input) {
+    Ok((_, tree)) => tree,
+    // Syntax errors are part of the tree
+    Err(_) => unreachable!(),
+  };
+  let errors = syntax_errors(&tree);
+  if errors.is_empty() {
+    Ok(tree)
+  } else {
+    Err(errors)
+  }
+}
+
+// The errors a tree was parsed with, in the order they appear in the source.
+pub fn syntax_errors(tree: &Node) -> Vec<SyntaxError> {
+  match tree {
+    Node::Error{message, span} => vec![SyntaxError{message: message.clone(), span: *span}],
+    node => node.children().iter().flat_map(syntax_errors).collect(),
+  }
+}
+
+// Parses a whole source file for the tools that work on one.
+pub(crate) fn parse_source(source: &str) -> Result<Node,AsaErrorKind> {
+  parse(lex(source)).map_err(AsaErrorKind::SyntaxError)
+}
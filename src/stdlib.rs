@@ -0,0 +1,322 @@
+use std::fs;
+use std::io::Write;
+use std::num::IntErrorKind;
+use std::path::{Path, PathBuf};
+use crate::interpreter::*;
+use crate::error::*;
+
+// Built-in functions available to every Asa program. They are ordinary native functions, registered
+// when the interpreter is created, so a program may still define its own function with the same name.
+pub(crate) fn register(interpreter: &mut Interpreter) {
+  register_output(interpreter);
+  register_strings(interpreter);
+  register_math(interpreter);
+  register_files(interpreter);
+}
+
+// Argument helpers. A wrong argument is a type error naming the function it was given to.
+fn string_arg<'a>(function: &str, args: &'a [Value], i: usize) -> Result<&'a str,AsaErrorKind> {
+  match &args[i] {
+    Value::String(s) => Ok(s),
+    other => Err(AsaErrorKind::type_error(function, "str", other.type_name())),
+  }
+}
+
+fn number_arg(function: &str, args: &[Value], i: usize) -> Result<i32,AsaErrorKind> {
+  match &args[i] {
+    Value::Number(n) => Ok(*n),
+    other => Err(AsaErrorKind::type_error(function, "num", other.type_name())),
+  }
+}
+
+// Numeric arguments accept both numbers and floats, converting numbers to floats where needed.
+fn float_arg(function: &str, args: &[Value], i: usize) -> Result<f64,AsaErrorKind> {
+  match &args[i] {
+    Value::Number(n) => Ok(*n as f64),
+    Value::Float(n) => Ok(*n),
+    other => Err(AsaErrorKind::type_error(function, "num", other.type_name())),
+  }
+}
+
+fn list_arg<'a>(function: &str, args: &'a [Value], i: usize) -> Result<&'a [Value],AsaErrorKind> {
+  match &args[i] {
+    Value::List(items) => Ok(items),
+    other => Err(AsaErrorKind::type_error(function, "list", other.type_name())),
+  }
+}
+
+// print(args...)   - writes the arguments separated by spaces
+// println(args...) - same as print, followed by a newline
+// format(fmt, args...) - replaces each "{}" in fmt with the next argument, "{{" and "}}" are literal braces
+fn register_output(interpreter: &mut Interpreter) {
+  let output = interpreter.output();
+  interpreter.register_native("print", Arity::AtLeast(0), move |args| {
+    write_values(&mut **output.borrow_mut(), args, "")
+  });
+  let output = interpreter.output();
+  interpreter.register_native("println", Arity::AtLeast(0), move |args| {
+    write_values(&mut **output.borrow_mut(), args, "\n")
+  });
+  interpreter.register_native("format", Arity::AtLeast(1), |args| match &args[0] {
+    Value::String(fmt) => Ok(Value::String(format(fmt, &args[1..])?)),
+    other => Err(AsaErrorKind::type_error("format", "str", other.type_name())),
+  });
+}
+
+fn write_values(out: &mut dyn Write, args: &[Value], end: &str) -> Result<Value,AsaErrorKind> {
+  let text = args.iter().map(|arg| arg.to_string()).collect::<Vec<String>>().join(" ");
+  write!(out, "{}{}", text, end)
+    .and_then(|_| out.flush())
+    .map_err(|e| AsaErrorKind::Generic(e.to_string()))?;
+  Ok(Value::Bool(true))
+}
+
+// Every argument after the format string fills one placeholder, so their numbers must match.
+fn format(fmt: &str, args: &[Value]) -> Result<String,AsaErrorKind> {
+  let mut result = String::new();
+  let mut placeholders = 0;
+  let mut chars = fmt.chars().peekable();
+  while let Some(c) = chars.next() {
+    match (c, chars.peek()) {
+      ('{', Some('{')) | ('}', Some('}')) => {
+        chars.next();
+        result.push(c);
+      },
+      ('{', Some('}')) => {
+        chars.next();
+        if let Some(arg) = args.get(placeholders) {
+          result.push_str(&arg.to_string());
+        }
+        placeholders += 1;
+      },
+      _ => result.push(c),
+    }
+  }
+  if placeholders != args.len() {
+    return Err(AsaErrorKind::ArityMismatch{name: "format".to_string(), expected: Arity::Exact(placeholders + 1), found: args.len() + 1});
+  }
+  Ok(result)
+}
+
+// String functions work on Unicode scalar values, so len, substring and friends count characters
+// rather than bytes.
+fn register_strings(interpreter: &mut Interpreter) {
+  interpreter.register_native("len", 1, |args| {
+    let len = match &args[0] {
+      Value::String(s) => s.chars().count(),
+      Value::List(items) => items.len(),
+      Value::Map(map) => map.len(),
+      other => return Err(AsaErrorKind::type_error("len", "str, list or map", other.type_name())),
+    };
+    i32::try_from(len).map(Value::Number).map_err(|_| AsaErrorKind::NumberOverflow)
+  });
+  // substring(s, start, end) - characters from index start up to, but not including, end
+  interpreter.register_native("substring", 3, |args| {
+    let s = string_arg("substring", args, 0)?;
+    let start = usize::try_from(number_arg("substring", args, 1)?).map_err(|_| AsaErrorKind::IndexOutOfBounds)?;
+    let end = usize::try_from(number_arg("substring", args, 2)?).map_err(|_| AsaErrorKind::IndexOutOfBounds)?;
+    if start > end || end > s.chars().count() {
+      return Err(AsaErrorKind::IndexOutOfBounds);
+    }
+    Ok(Value::String(s.chars().skip(start).take(end - start).collect()))
+  });
+  // split(s, sep) - an empty separator splits s into its characters
+  interpreter.register_native("split", 2, |args| {
+    let s = string_arg("split", args, 0)?;
+    let parts: Vec<Value> = match string_arg("split", args, 1)? {
+      "" => s.chars().map(|c| Value::String(c.to_string())).collect(),
+      sep => s.split(sep).map(|part| Value::String(part.to_string())).collect(),
+    };
+    Ok(Value::List(parts))
+  });
+  interpreter.register_native("join", 2, |args| {
+    let sep = string_arg("join", args, 1)?;
+    let parts = list_arg("join", args, 0)?.iter().map(|item| item.to_string()).collect::<Vec<String>>();
+    Ok(Value::String(parts.join(sep)))
+  });
+  interpreter.register_native("trim", 1, |args| {
+    Ok(Value::String(string_arg("trim", args, 0)?.trim().to_string()))
+  });
+  interpreter.register_native("upper", 1, |args| {
+    Ok(Value::String(string_arg("upper", args, 0)?.to_uppercase()))
+  });
+  interpreter.register_native("lower", 1, |args| {
+    Ok(Value::String(string_arg("lower", args, 0)?.to_lowercase()))
+  });
+  // contains(s, sub) on strings, contains(list, value) on lists
+  interpreter.register_native("contains", 2, |args| match (&args[0], &args[1]) {
+    (Value::String(s), Value::String(sub)) => Ok(Value::Bool(s.contains(sub.as_str()))),
+    (Value::List(items), value) => Ok(Value::Bool(items.contains(value))),
+    (Value::String(_), other) => Err(AsaErrorKind::type_error("contains", "str", other.type_name())),
+    (other, _) => Err(AsaErrorKind::type_error("contains", "str or list", other.type_name())),
+  });
+  interpreter.register_native("starts_with", 2, |args| {
+    Ok(Value::Bool(string_arg("starts_with", args, 0)?.starts_with(string_arg("starts_with", args, 1)?)))
+  });
+  interpreter.register_native("ends_with", 2, |args| {
+    Ok(Value::Bool(string_arg("ends_with", args, 0)?.ends_with(string_arg("ends_with", args, 1)?)))
+  });
+  interpreter.register_native("replace", 3, |args| {
+    Ok(Value::String(string_arg("replace", args, 0)?.replace(string_arg("replace", args, 1)?, string_arg("replace", args, 2)?)))
+  });
+  interpreter.register_native("parse_int", 1, |args| {
+    let s = string_arg("parse_int", args, 0)?;
+    match s.trim().parse::<i32>() {
+      Ok(n) => Ok(Value::Number(n)),
+      Err(e) => match e.kind() {
+        IntErrorKind::PosOverflow => Err(AsaErrorKind::NumberOverflow),
+        IntErrorKind::NegOverflow => Err(AsaErrorKind::NumberUnderflow),
+        _ => Err(AsaErrorKind::InvalidNumber(s.to_string())),
+      },
+    }
+  });
+  interpreter.register_native("to_string", 1, |args| {
+    Ok(Value::String(args[0].to_string()))
+  });
+}
+
+// Math functions keep integers as integers where the result is exact (abs, min, max, pow, clamp, gcd)
+// and use floats otherwise. Results that don't fit an Asa number fail with NumberOverflow or
+// NumberUnderflow, and arguments outside a function's domain, like sqrt(-1), fail with TypeError.
+// The type error then describes the value the function expected rather than its type.
+fn register_math(interpreter: &mut Interpreter) {
+  interpreter.register_native("abs", 1, |args| match args[0] {
+    Value::Number(n) => n.checked_abs().map(Value::Number).ok_or(AsaErrorKind::NumberOverflow),
+    Value::Float(n) => checked_float("abs", n.abs()),
+    ref other => Err(AsaErrorKind::type_error("abs", "num", other.type_name())),
+  });
+  interpreter.register_native("min", Arity::AtLeast(1), |args| extreme("min", args, |a, b| a < b));
+  interpreter.register_native("max", Arity::AtLeast(1), |args| extreme("max", args, |a, b| a > b));
+  interpreter.register_native("pow", 2, |args| match (&args[0], &args[1]) {
+    (Value::Number(base), Value::Number(exp)) => {
+      let exp = u32::try_from(*exp).map_err(|_| AsaErrorKind::type_error("pow", "a non-negative exponent", &exp.to_string()))?;
+      base.checked_pow(exp).map(Value::Number).ok_or(overflow(*base > 0 || exp % 2 == 0))
+    },
+    _ => checked_float("pow", float_arg("pow", args, 0)?.powf(float_arg("pow", args, 1)?)),
+  });
+  interpreter.register_native("sqrt", 1, |args| {
+    let n = float_arg("sqrt", args, 0)?;
+    if n < 0.0 {
+      return Err(AsaErrorKind::type_error("sqrt", "a non-negative number", &args[0].to_string()));
+    }
+    checked_float("sqrt", n.sqrt())
+  });
+  interpreter.register_native("floor", 1, |args| to_number("floor", args, f64::floor));
+  interpreter.register_native("ceil", 1, |args| to_number("ceil", args, f64::ceil));
+  interpreter.register_native("round", 1, |args| to_number("round", args, f64::round));
+  // clamp(x, lo, hi) - lo must not be greater than hi
+  interpreter.register_native("clamp", 3, |args| {
+    if float_arg("clamp", args, 1)? > float_arg("clamp", args, 2)? {
+      return Err(AsaErrorKind::type_error("clamp", "a low bound not above the high bound", &format!("{} > {}", args[1], args[2])));
+    }
+    let low = extreme("clamp", &[args[0].clone(), args[1].clone()], |a, b| a > b)?;
+    extreme("clamp", &[low, args[2].clone()], |a, b| a < b)
+  });
+  interpreter.register_native("gcd", 2, |args| {
+    let (mut a, mut b) = (number_arg("gcd", args, 0)?.unsigned_abs(), number_arg("gcd", args, 1)?.unsigned_abs());
+    while b != 0 {
+      (a, b) = (b, a % b);
+    }
+    i32::try_from(a).map(Value::Number).map_err(|_| AsaErrorKind::NumberOverflow)
+  });
+  interpreter.register_native("sin", 1, |args| checked_float("sin", float_arg("sin", args, 0)?.sin()));
+  interpreter.register_native("cos", 1, |args| checked_float("cos", float_arg("cos", args, 0)?.cos()));
+  interpreter.register_native("tan", 1, |args| checked_float("tan", float_arg("tan", args, 0)?.tan()));
+  interpreter.register_native("asin", 1, |args| checked_float("asin", float_arg("asin", args, 0)?.asin()));
+  interpreter.register_native("acos", 1, |args| checked_float("acos", float_arg("acos", args, 0)?.acos()));
+  interpreter.register_native("atan", 1, |args| checked_float("atan", float_arg("atan", args, 0)?.atan()));
+}
+
+// Picks the argument that wins every comparison. Integers are compared as integers and returned
+// unchanged; as soon as a float is involved the comparison is done on floats.
+fn extreme(function: &str, args: &[Value], wins: fn(f64, f64) -> bool) -> Result<Value,AsaErrorKind> {
+  let (mut best, mut best_value) = (&args[0], float_arg(function, args, 0)?);
+  for (i, arg) in args.iter().enumerate().skip(1) {
+    let value = float_arg(function, args, i)?;
+    if wins(value, best_value) {
+      (best, best_value) = (arg, value);
+    }
+  }
+  Ok(best.clone())
+}
+
+fn to_number(function: &str, args: &[Value], op: fn(f64) -> f64) -> Result<Value,AsaErrorKind> {
+  match args[0] {
+    Value::Number(n) => Ok(Value::Number(n)),
+    Value::Float(n) => {
+      let n = op(n);
+      if n.is_nan() {
+        Err(AsaErrorKind::type_error(function, "a number", "NaN"))
+      } else if n > i32::MAX as f64 || n < i32::MIN as f64 {
+        Err(overflow(n > 0.0))
+      } else {
+        Ok(Value::Number(n as i32))
+      }
+    },
+    ref other => Err(AsaErrorKind::type_error(function, "num", other.type_name())),
+  }
+}
+
+// File functions only work inside the directories granted with Interpreter::allow_dir. Any other
+// path, including one that doesn't exist, fails with PermissionDenied so scripts can't probe the
+// file system outside the sandbox.
+fn register_files(interpreter: &mut Interpreter) {
+  let allowed = interpreter.allowed_dirs();
+  interpreter.register_native("read_file", 1, move |args| {
+    let path = checked_path(&allowed.borrow(), string_arg("read_file", args, 0)?)?;
+    fs::read_to_string(&path).map(Value::String).map_err(|e| io_error(&path, e))
+  });
+  let allowed = interpreter.allowed_dirs();
+  interpreter.register_native("read_lines", 1, move |args| {
+    let path = checked_path(&allowed.borrow(), string_arg("read_lines", args, 0)?)?;
+    let contents = fs::read_to_string(&path).map_err(|e| io_error(&path, e))?;
+    Ok(Value::List(contents.lines().map(|line| Value::String(line.to_string())).collect()))
+  });
+  // write_file(path, contents) - creates or truncates the file
+  let allowed = interpreter.allowed_dirs();
+  interpreter.register_native("write_file", 2, move |args| {
+    let path = checked_path(&allowed.borrow(), string_arg("write_file", args, 0)?)?;
+    fs::write(&path, string_arg("write_file", args, 1)?).map_err(|e| io_error(&path, e))?;
+    Ok(Value::Bool(true))
+  });
+  let allowed = interpreter.allowed_dirs();
+  interpreter.register_native("exists", 1, move |args| {
+    let path = checked_path(&allowed.borrow(), string_arg("exists", args, 0)?)?;
+    Ok(Value::Bool(path.exists()))
+  });
+}
+
+// Resolves a script supplied path and checks it against the allowed directories. Only the deepest
+// part of the path that exists is canonicalized, and the missing names below it are added back, so
+// files and directories that don't exist yet can still be written or tested with exists.
+fn checked_path(allowed: &[PathBuf], path: &str) -> Result<PathBuf,AsaErrorKind> {
+  let denied = || AsaErrorKind::PermissionDenied(path.to_string());
+  let mut existing = Path::new(path);
+  let mut missing = Vec::new();
+  let mut resolved = loop {
+    match existing.canonicalize() {
+      Ok(resolved) => break resolved,
+      // A symlink that can't be resolved is denied, since writing through it would create its
+      // target wherever that is.
+      Err(_) if existing.symlink_metadata().is_ok() => return Err(denied()),
+      // A missing name must be a plain one; a missing path ending in .. can't be resolved.
+      Err(_) => {
+        missing.push(existing.file_name().ok_or_else(denied)?);
+        existing = match existing.parent() {
+          Some(parent) if !parent.as_os_str().is_empty() => parent,
+          _ => Path::new("."),
+        };
+      },
+    }
+  };
+  resolved.extend(missing.iter().rev());
+  if allowed.iter().any(|dir| resolved.starts_with(dir)) {
+    Ok(resolved)
+  } else {
+    Err(denied())
+  }
+}
+
+fn io_error(path: &Path, e: std::io::Error) -> AsaErrorKind {
+  AsaErrorKind::IoError(format!("{}: {}", path.display(), e))
+}
@@ -0,0 +1,77 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::rc::Rc;
+
+// An interned name. The parser interns every name it reads, so all copies of a name share one
+// allocation: binding a variable only copies a pointer, and names are compared and hashed by that
+// pointer rather than by their text. A qualified name like util.helper is split into its namespace
+// and name when it is interned, so looking it up never has to split or format a string.
+#[derive(Clone)]
+pub struct Symbol(Rc<Name>);
+
+struct Name {
+  text: Rc<str>,
+  parts: Option<(Symbol, Symbol)>,
+}
+
+thread_local! {
+  // Every name interned on this thread. Trees and interpreters can't leave the thread they were made
+  // on, so symbols from different threads are never compared.
+  static SYMBOLS: RefCell<HashMap<Rc<str>, Symbol>> = RefCell::new(HashMap::new());
+}
+
+impl Symbol {
+  // The one symbol for a name.
+  pub fn new(text: &str) -> Symbol {
+    if let Some(symbol) = SYMBOLS.with(|symbols| symbols.borrow().get(text).cloned()) {
+      return symbol;
+    }
+    let parts = text.split_once('.').map(|(namespace, name)| (Symbol::new(namespace), Symbol::new(name)));
+    let text: Rc<str> = Rc::from(text);
+    let symbol = Symbol(Rc::new(Name{text: text.clone(), parts}));
+    SYMBOLS.with(|symbols| symbols.borrow_mut().insert(text, symbol.clone()));
+    symbol
+  }
+
+  // The name qualified with a namespace, e.g. util.helper for a definition in util.asa.
+  pub fn qualified(namespace: &Symbol, name: &Symbol) -> Symbol {
+    Symbol::new(&format!("{}.{}", namespace, name))
+  }
+
+  pub fn as_str(&self) -> &str {
+    &self.0.text
+  }
+
+  // The namespace and the name of a qualified name.
+  pub fn parts(&self) -> Option<(&Symbol, &Symbol)> {
+    self.0.parts.as_ref().map(|(namespace, name)| (namespace, name))
+  }
+}
+
+impl PartialEq for Symbol {
+  fn eq(&self, other: &Symbol) -> bool {
+    Rc::ptr_eq(&self.0, &other.0)
+  }
+}
+
+impl Eq for Symbol {}
+
+impl Hash for Symbol {
+  fn hash<H: Hasher>(&self, state: &mut H) {
+    Rc::as_ptr(&self.0).hash(state)
+  }
+}
+
+impl fmt::Debug for Symbol {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{:?}", self.as_str())
+  }
+}
+
+impl fmt::Display for Symbol {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}", self.as_str())
+  }
+}
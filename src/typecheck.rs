@@ -0,0 +1,409 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use crate::checker::{Finding, FindingKind};
+use crate::lexer::Span;
+use crate::parser::Node;
+
+// An opt-in static type checker. Asa is dynamically typed and runs without it; when it is enabled,
+// programs are checked before they are executed and type errors are reported as checker findings.
+//
+// Types are inferred by unification. A local gets the type of the value it is bound to, and an
+// unannotated parameter or return type starts out unknown and is inferred from how the function
+// uses it and how it is called. Annotations (fn add(a: num, b: num) -> num) are checked the same
+// way. A function's body is checked before the first call to it, wherever it is defined, and from
+// then on it is generic in whatever is still unknown, so fn id(a){return a;} can be called with any
+// type. Only calls within a recursive cycle use the signature while it is still being inferred. Built-ins and functions of imported modules
+// are not typed, and their results are unknown.
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Type {
+  // Numbers, both integers and floats
+  Num,
+  Bool,
+  Str,
+  List(Box<Type>),
+  // Maps always have string keys, so only the value type is given
+  Map(Box<Type>),
+  Function(Vec<Type>, Box<Type>),
+  // A type that hasn't been inferred (yet)
+  Var(usize),
+}
+
+impl fmt::Display for Type {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Type::Num => write!(f, "num"),
+      Type::Bool => write!(f, "bool"),
+      Type::Str => write!(f, "str"),
+      Type::List(item) => write!(f, "list<{}>", item),
+      Type::Map(item) => write!(f, "map<{}>", item),
+      Type::Function(params, result) => {
+        let params: Vec<String> = params.iter().map(Type::to_string).collect();
+        write!(f, "fn({}) -> {}", params.join(", "), result)
+      },
+      Type::Var(_) => write!(f, "_"),
+    }
+  }
+}
+
+// Type checks a program, returning a finding for each type error, sorted by location.
+pub fn typecheck(tree: &Node) -> Vec<Finding> {
+  let mut findings = Inference::run(tree).findings;
+  findings.sort_by_key(|finding| (finding.span.start_line, finding.span.start_col));
+  findings
+}
+
+// The inferred type of every name in a program, wherever it is defined or used, sorted by location.
+// Types still unknown after checking the whole program print as _.
+pub fn types(tree: &Node) -> Vec<(Span, Type)> {
+  let inference = Inference::run(tree);
+  let mut types: Vec<(Span, Type)> = inference.types.iter().map(|(span, ty)| (*span, inference.resolve(ty))).collect();
+  types.sort_by_key(|(span, _)| (span.start_line, span.start_col));
+  types
+}
+
+type Scope = HashMap<String, Type>;
+
+#[derive(Default)]
+struct Inference<'a> {
+  // What each type variable has been unified with so far
+  vars: Vec<Option<Type>>,
+  // Signatures of the program's functions, and the functions whose bodies have been checked
+  functions: HashMap<String, Type>,
+  checked: HashSet<String>,
+  // The definition of each function, and those whose bodies are being checked right now
+  definitions: HashMap<String, &'a Node>,
+  checking: HashSet<String>,
+  constants: HashMap<String, Type>,
+  findings: Vec<Finding>,
+  // The type of each name at the location it appears
+  types: Vec<(Span, Type)>,
+}
+
+impl<'a> Inference<'a> {
+
+  fn run(tree: &'a Node) -> Inference<'a> {
+    let mut inference = Inference::default();
+    inference.declare(tree.children());
+    let mut scope = Scope::new();
+    for node in tree.children() {
+      inference.infer(node, &mut scope, None);
+    }
+    inference
+  }
+
+  // Gives every function a signature and every constant a type up front, so they can be used
+  // before (above) their definitions.
+  fn declare(&mut self, children: &'a [Node]) {
+    for node in children {
+      let definition = match node {
+        Node::Export{children} => &children[0],
+        node => node,
+      };
+      match definition {
+        Node::FunctionDefine{name, children, ..} => {
+          let params = children[0].children().iter().map(|param| self.annotation(param.children().get(1))).collect();
+          let result = self.annotation(children.get(2));
+          let name = name.to_string();
+          self.functions.insert(name.clone(), Type::Function(params, Box::new(result)));
+          self.definitions.insert(name, definition);
+        },
+        Node::ConstDefine{children} => {
+          let constant = self.fresh();
+          self.constants.insert(name_of(&children[0]), constant);
+        },
+        _ => (),
+      }
+    }
+  }
+
+  // The type of a node, checking its children along the way. `result` is the return type of the
+  // function being checked, if any.
+  fn infer(&mut self, node: &Node, scope: &mut Scope, result: Option<&Type>) -> Type {
+    match node {
+      Node::Number{..} | Node::Float{..} => Type::Num,
+      Node::Bool{..} => Type::Bool,
+      Node::String{..} => Type::Str,
+      Node::Identifier{value, span} => {
+        let name = value.to_string();
+        let ty = match scope.get(&name).or_else(|| self.constants.get(&name)) {
+          Some(ty) => ty.clone(),
+          None => self.fresh(),
+        };
+        self.types.push((*span, ty.clone()));
+        ty
+      },
+      Node::Expression{children} |
+      Node::Statement{children} |
+      Node::ReturnIf{children} |
+      Node::ReturnIfNum{children} |
+      Node::ReturnElse{children} => self.infer(&children[0], scope, result),
+      Node::Export{children} => self.infer(&children[0], scope, result),
+      Node::MathExpression{name, children, span} => {
+        let left = self.infer(&children[0], scope, result);
+        let right = self.infer(&children[1], scope, result);
+        if name.as_slice() == b"add" {
+          self.expect(&left, &right, children[1].span().unwrap_or(*span));
+          self.operand("+", &left, *span, &[Type::Num, Type::Str]);
+          left
+        } else {
+          self.expect(&Type::Num, &left, children[0].span().unwrap_or(*span));
+          self.expect(&Type::Num, &right, children[1].span().unwrap_or(*span));
+          Type::Num
+        }
+      },
+      Node::Condition{children} => {
+        let left = self.infer(&children[0], scope, result);
+        let right = self.infer(&children[2], scope, result);
+        if let Node::Operator{value, span} = &children[1] {
+          self.expect(&left, &right, *span);
+          if value.as_slice() != b"==" {
+            self.operand(&String::from_utf8_lossy(value), &left, *span, &[Type::Num, Type::Str]);
+          }
+        }
+        Type::Bool
+      },
+      Node::IfExpression{children, span} => self.branches(&[&children[0]], &[&children[1], &children[2]], *span, scope, result),
+      Node::MultiLineIf{children} => self.branches(&[&children[0]], &[&children[1], &children[2]], node.span().unwrap_or_default(), scope, result),
+      Node::IfElse{children} => self.branches(&[&children[0], &children[2]], &[&children[1], &children[3], &children[4]], node.span().unwrap_or_default(), scope, result),
+      Node::IfAssign{children} => {
+        let ty = self.branches(&[&children[1]], &[&children[2], &children[3]], node.span().unwrap_or_default(), scope, result);
+        self.bind(&children[0], &ty, scope);
+        ty
+      },
+      Node::VariableDefine{children} => {
+        let ty = self.infer(&children[1], scope, result);
+        self.bind(&children[0], &ty, scope);
+        ty
+      },
+      Node::ConstDefine{children} => {
+        let ty = self.infer(&children[1], scope, result);
+        if let Some(constant) = self.constants.get(&name_of(&children[0])).cloned() {
+          self.expect(&constant, &ty, children[0].span().unwrap_or_default());
+          self.types.push((children[0].span().unwrap_or_default(), constant));
+        }
+        ty
+      },
+      Node::FunctionReturn{children, span} => {
+        let ty = self.infer(&children[0], scope, result);
+        if let Some(expected) = result {
+          self.expect(expected, &ty, children[0].span().unwrap_or(*span));
+        }
+        ty
+      },
+      Node::Throw{children, ..} => {
+        self.infer(&children[0], scope, result);
+        self.fresh()
+      },
+      // The catch variable may hold a thrown value of any type, or the map of a built-in error.
+      Node::Try{children, ..} => {
+        for stmt in children[0].children() {
+          self.infer(stmt, scope, result);
+        }
+        let error = self.fresh();
+        self.bind(&children[1], &error, scope);
+        for block in &children[2..] {
+          for stmt in block.children() {
+            self.infer(stmt, scope, result);
+          }
+        }
+        self.fresh()
+      },
+      Node::FunctionCall{name, children, span} => {
+        let args: Vec<&Node> = children.last().map_or(&[][..], Node::children).iter().collect();
+        let types: Vec<Type> = args.iter().map(|arg| self.infer(arg, scope, result)).collect();
+        let name = name.to_string();
+        if let Some(definition) = self.definitions.get(&name).copied() {
+          self.function(definition);
+        }
+        let signature = match self.functions.get(&name).cloned() {
+          Some(signature) if self.checked.contains(&name) => self.instantiate(&signature),
+          Some(signature) => signature,
+          None => return self.fresh(),
+        };
+        self.types.push((*span, signature.clone()));
+        match signature {
+          Type::Function(params, ret) if params.len() == types.len() => {
+            for ((param, ty), arg) in params.iter().zip(&types).zip(&args) {
+              self.expect(param, ty, arg.span().unwrap_or(*span));
+            }
+            *ret
+          },
+          // A wrong number of arguments is reported by the checker
+          _ => self.fresh(),
+        }
+      },
+      Node::FunctionDefine{..} => {
+        self.function(node);
+        self.fresh()
+      },
+      _ => self.fresh(),
+    }
+  }
+
+  // Checks the body of a function, unless it has been checked already or is being checked further up.
+  fn function(&mut self, definition: &Node) {
+    let Node::FunctionDefine{name, children, span} = definition else {
+      return;
+    };
+    let name = name.to_string();
+    if self.checked.contains(&name) || self.checking.contains(&name) {
+      return;
+    }
+    if let Some(Type::Function(params, ret)) = self.functions.get(&name).cloned() {
+      self.checking.insert(name.clone());
+      self.types.push((*span, Type::Function(params.clone(), ret.clone())));
+      let mut locals = Scope::new();
+      for (param, ty) in children[0].children().iter().zip(params) {
+        if let Some(identifier) = param.children().first() {
+          self.bind(identifier, &ty, &mut locals);
+        }
+      }
+      for stmt in children[1].children() {
+        self.infer(stmt, &mut locals, Some(&ret));
+      }
+      self.checking.remove(&name);
+      self.checked.insert(name);
+    }
+  }
+
+  // Binds a let or parameter name in the scope.
+  fn bind(&mut self, identifier: &Node, ty: &Type, scope: &mut Scope) {
+    self.types.push((identifier.span().unwrap_or_default(), ty.clone()));
+    scope.insert(name_of(identifier), ty.clone());
+  }
+
+  // Checks the tests of an if expression, which must be booleans, and that all branches have the same type.
+  fn branches(&mut self, tests: &[&Node], branches: &[&Node], span: Span, scope: &mut Scope, result: Option<&Type>) -> Type {
+    for test in tests {
+      let ty = self.infer(test, scope, result);
+      self.expect(&Type::Bool, &ty, test.span().unwrap_or(span));
+    }
+    let first = self.infer(branches[0], scope, result);
+    for branch in &branches[1..] {
+      let ty = self.infer(branch, scope, result);
+      if !self.unify(&first, &ty) {
+        let (then, otherwise) = (self.resolve(&first), self.resolve(&ty));
+        self.report(FindingKind::BranchMismatch{then, otherwise}, span);
+      }
+    }
+    first
+  }
+
+  // Reports a type error unless `found` can be unified with `expected`.
+  fn expect(&mut self, expected: &Type, found: &Type, span: Span) {
+    if !self.unify(expected, found) {
+      let (expected, found) = (self.resolve(expected), self.resolve(found));
+      self.report(FindingKind::TypeMismatch{expected, found}, span);
+    }
+  }
+
+  // Reports an operand whose type is known and is not one of the types the operator works on.
+  fn operand(&mut self, operator: &str, ty: &Type, span: Span, allowed: &[Type]) {
+    let found = self.resolve(ty);
+    if !matches!(found, Type::Var(_)) && !allowed.contains(&found) {
+      self.report(FindingKind::InvalidOperand{operator: operator.to_string(), found}, span);
+    }
+  }
+
+  fn unify(&mut self, a: &Type, b: &Type) -> bool {
+    match (self.shallow(a), self.shallow(b)) {
+      (Type::Var(x), Type::Var(y)) if x == y => true,
+      (Type::Var(x), other) | (other, Type::Var(x)) => {
+        if self.occurs(x, &other) {
+          return false;
+        }
+        self.vars[x] = Some(other);
+        true
+      },
+      (Type::List(a), Type::List(b)) | (Type::Map(a), Type::Map(b)) => self.unify(&a, &b),
+      (Type::Function(a_params, a_result), Type::Function(b_params, b_result)) => {
+        a_params.len() == b_params.len()
+          && a_params.iter().zip(&b_params).all(|(a, b)| self.unify(a, b))
+          && self.unify(&a_result, &b_result)
+      },
+      (a, b) => a == b,
+    }
+  }
+
+  // Follows a type variable to what it has been unified with, one level deep.
+  fn shallow(&self, ty: &Type) -> Type {
+    match ty {
+      Type::Var(x) => match &self.vars[*x] {
+        Some(ty) => self.shallow(ty),
+        None => ty.clone(),
+      },
+      ty => ty.clone(),
+    }
+  }
+
+  // Replaces every type variable that has been inferred with what it stands for.
+  fn resolve(&self, ty: &Type) -> Type {
+    match self.shallow(ty) {
+      Type::List(item) => Type::List(Box::new(self.resolve(&item))),
+      Type::Map(item) => Type::Map(Box::new(self.resolve(&item))),
+      Type::Function(params, result) => Type::Function(params.iter().map(|param| self.resolve(param)).collect(), Box::new(self.resolve(&result))),
+      ty => ty,
+    }
+  }
+
+  fn occurs(&self, var: usize, ty: &Type) -> bool {
+    match self.resolve(ty) {
+      Type::Var(x) => x == var,
+      Type::List(item) | Type::Map(item) => self.occurs(var, &item),
+      Type::Function(params, result) => params.iter().any(|param| self.occurs(var, param)) || self.occurs(var, &result),
+      _ => false,
+    }
+  }
+
+  // A copy of a checked function's signature with fresh variables for the types still unknown.
+  fn instantiate(&mut self, ty: &Type) -> Type {
+    let mut fresh = HashMap::new();
+    let ty = self.resolve(ty);
+    self.replace_vars(&ty, &mut fresh)
+  }
+
+  fn replace_vars(&mut self, ty: &Type, fresh: &mut HashMap<usize, Type>) -> Type {
+    match ty {
+      Type::Var(x) => {
+        if let Some(var) = fresh.get(x) {
+          return var.clone();
+        }
+        let var = self.fresh();
+        fresh.insert(*x, var.clone());
+        var
+      },
+      Type::List(item) => Type::List(Box::new(self.replace_vars(item, fresh))),
+      Type::Map(item) => Type::Map(Box::new(self.replace_vars(item, fresh))),
+      Type::Function(params, result) => {
+        let params = params.iter().map(|param| self.replace_vars(param, fresh)).collect();
+        Type::Function(params, Box::new(self.replace_vars(result, fresh)))
+      },
+      ty => ty.clone(),
+    }
+  }
+
+  fn fresh(&mut self) -> Type {
+    self.vars.push(None);
+    Type::Var(self.vars.len() - 1)
+  }
+
+  // The annotated type, or a fresh variable when there is no annotation.
+  fn annotation(&mut self, node: Option<&Node>) -> Type {
+    match node {
+      Some(Node::TypeAnnotation{value}) => value.clone(),
+      _ => self.fresh(),
+    }
+  }
+
+  fn report(&mut self, kind: FindingKind, span: Span) {
+    self.findings.push(Finding{kind, span});
+  }
+}
+
+fn name_of(node: &Node) -> String {
+  match node {
+    Node::Identifier{value, ..} => value.to_string(),
+    _ => unreachable!(),
+  }
+}
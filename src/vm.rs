@@ -0,0 +1,279 @@
+use crate::error::AsaErrorKind;
+use crate::lexer::Span;
+use crate::interpreter::{add, compare, field, not_bool, sub, Comparison, Function, Interpreter, Value};
+use crate::symbol::Symbol;
+use crate::parser::Node;
+
+// The bytecode back end. A user function is compiled into a Chunk of instructions for a small stack
+// machine: its parameters and lets live in numbered local slots instead of a hash map frame, and its
+// literals are kept in a constant pool. Everything with run time behaviour (arithmetic, comparisons,
+// resolving names, calling functions) goes through the same helpers the tree-walker uses, so both
+// back ends give the same results and the same errors.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op {
+  // Pushes constants[n]
+  Const(usize),
+  // Pushes the value in a local slot. A slot that hasn't been assigned yet falls back to the constant
+  // with the slot's name, like a variable lookup in the tree-walker.
+  Load(usize),
+  // Pushes the value of the constant names[n]
+  LoadGlobal(usize),
+  // Pushes the field names[n] of the value in a local slot. A slot that hasn't been assigned yet is
+  // read as a name that isn't a variable, like GlobalField.
+  LoadField(usize, usize),
+  // Pushes the field names[n] of the constant names[a], or else the constant names[n] of the module
+  // with the namespace names[a].
+  GlobalField(usize, usize),
+  // Copies the top of the stack into a local slot, leaving it on the stack as the value of the let.
+  Store(usize),
+  Pop,
+  Add,
+  Sub,
+  Compare(Comparison),
+  // Looks up the function names[n] and remembers it for the next Call, before the arguments are evaluated.
+  Resolve(usize),
+  // Calls the most recently resolved function with the top n values as arguments. The span is the
+  // call site, for backtraces.
+  Call(usize, Span),
+  // Pops a condition and jumps to the target if it is false. A condition that isn't a bool is a type error.
+  JumpIfFalse(usize),
+  Jump(usize),
+}
+
+#[derive(Debug)]
+pub struct Chunk {
+  code: Vec<Op>,
+  constants: Vec<Value>,
+  names: Vec<Symbol>,
+  // Names of the local slots; the parameters come first.
+  locals: Vec<Symbol>,
+  params: usize,
+}
+
+// Compiles the body of a user function. Returns None for functions using a construct the compiler
+// doesn't handle, which then keep running on the tree-walker.
+pub fn compile(params: &[Symbol], stmts: &[Node]) -> Option<Chunk> {
+  if stmts.is_empty() {
+    return None;
+  }
+  let mut chunk = Chunk {
+    code: Vec::new(),
+    constants: Vec::new(),
+    names: Vec::new(),
+    // Every parameter gets a slot of its own, even a repeated name, so that arguments bind in order.
+    locals: params.to_vec(),
+    params: params.len(),
+  };
+  for (i, stmt) in stmts.iter().enumerate() {
+    if i > 0 {
+      chunk.code.push(Op::Pop);
+    }
+    chunk.node(stmt)?;
+  }
+  Some(chunk)
+}
+
+impl Chunk {
+
+  fn node(&mut self, node: &Node) -> Option<()> {
+    match node {
+      Node::Statement{children} | Node::Expression{children} | Node::FunctionReturn{children, ..} |
+      Node::ReturnIf{children} | Node::ReturnIfNum{children} | Node::ReturnElse{children} => self.node(&children[0])?,
+      Node::Number{value} => self.constant(Value::Number(*value)),
+      Node::Float{value} => self.constant(Value::Float(*value)),
+      Node::String{value} => self.constant(Value::String(value.clone())),
+      Node::Bool{value} => self.constant(Value::Bool(*value)),
+      Node::Identifier{value, ..} => {
+        match self.slot(value) {
+          Some(slot) => self.code.push(Op::Load(slot)),
+          None => {
+            let name = self.name(value);
+            self.code.push(Op::LoadGlobal(name));
+          },
+        }
+      },
+      Node::FieldAccess{children, name, ..} => {
+        let field = self.name(name);
+        match &children[0] {
+          Node::Identifier{value, ..} => match self.slot(value) {
+            Some(slot) => self.code.push(Op::LoadField(slot, field)),
+            None => {
+              let base = self.name(value);
+              self.code.push(Op::GlobalField(base, field));
+            },
+          },
+          // Fields of fields are left to the tree-walker.
+          _ => return None,
+        }
+      },
+      Node::VariableDefine{children} => {
+        self.node(&children[1])?;
+        let name = match &children[0] {
+          Node::Identifier{value, ..} => value.clone(),
+          _ => return None,
+        };
+        // A let rebinding a parameter assigns the last slot with that name, the one a later load reads.
+        let slot = match self.slot(&name) {
+          Some(slot) => slot,
+          None => {
+            self.locals.push(name);
+            self.locals.len() - 1
+          },
+        };
+        self.code.push(Op::Store(slot));
+      },
+      Node::MathExpression{name, children, ..} => {
+        let op = match name.as_slice() {
+          b"add" => Op::Add,
+          b"sub" => Op::Sub,
+          _ => return None,
+        };
+        self.node(&children[0])?;
+        self.node(&children[1])?;
+        self.code.push(op);
+      },
+      Node::Condition{children} => {
+        let comparison = match &children[1] {
+          Node::Operator{value, ..} => Comparison::from_operator(value),
+          _ => return None,
+        };
+        self.node(&children[0])?;
+        self.node(&children[2])?;
+        self.code.push(Op::Compare(comparison));
+      },
+      Node::FunctionCall{name, children, span} => {
+        let name = self.name(name);
+        self.code.push(Op::Resolve(name));
+        let args = children.last().map_or(&[][..], |args| args.children());
+        for arg in args {
+          self.node(arg)?;
+        }
+        self.code.push(Op::Call(args.len(), *span));
+      },
+      Node::IfExpression{children, ..} => self.branches(&children[0], &children[1], &children[2])?,
+      // if a {..} else if b {..} else {..} is compiled as if a {..} else {if b {..} else {..}}
+      Node::IfElse{children} => {
+        self.node(&children[0])?;
+        let otherwise = self.jump(Op::JumpIfFalse(0));
+        self.node(&children[1])?;
+        let end = self.jump(Op::Jump(0));
+        self.patch(otherwise);
+        self.branches(&children[2], &children[3], &children[4])?;
+        self.patch(end);
+      },
+      _ => return None,
+    }
+    Some(())
+  }
+
+  fn branches(&mut self, condition: &Node, then: &Node, otherwise: &Node) -> Option<()> {
+    self.node(condition)?;
+    let jump_else = self.jump(Op::JumpIfFalse(0));
+    self.node(then)?;
+    let jump_end = self.jump(Op::Jump(0));
+    self.patch(jump_else);
+    self.node(otherwise)?;
+    self.patch(jump_end);
+    Some(())
+  }
+
+  // Emits a jump whose target is filled in by patch once it is known.
+  fn jump(&mut self, op: Op) -> usize {
+    self.code.push(op);
+    self.code.len() - 1
+  }
+
+  fn patch(&mut self, at: usize) {
+    let target = self.code.len();
+    match &mut self.code[at] {
+      Op::JumpIfFalse(to) | Op::Jump(to) => *to = target,
+      _ => unreachable!(),
+    }
+  }
+
+  fn constant(&mut self, value: Value) {
+    let index = match self.constants.iter().position(|constant| *constant == value) {
+      Some(index) => index,
+      None => {
+        self.constants.push(value);
+        self.constants.len() - 1
+      },
+    };
+    self.code.push(Op::Const(index));
+  }
+
+  fn name(&mut self, name: &Symbol) -> usize {
+    match self.names.iter().position(|known| known == name) {
+      Some(index) => index,
+      None => {
+        self.names.push(name.clone());
+        self.names.len() - 1
+      },
+    }
+  }
+
+  fn slot(&self, name: &Symbol) -> Option<usize> {
+    self.locals.iter().rposition(|local| local == name)
+  }
+}
+
+// Runs a compiled function with already evaluated and counted arguments. Functions it calls go back
+// through Interpreter::invoke, so they run on whichever back end applies to them.
+pub fn run(interpreter: &mut Interpreter, chunk: &Chunk, values: Vec<Value>) -> Result<Value,AsaErrorKind> {
+  let mut slots: Vec<Option<Value>> = Vec::with_capacity(chunk.locals.len());
+  slots.extend(values.into_iter().take(chunk.params).map(Some));
+  slots.resize(chunk.locals.len(), None);
+  let mut stack: Vec<Value> = Vec::new();
+  let mut callees: Vec<(usize, Function)> = Vec::new();
+  let mut pc = 0;
+  while let Some(op) = chunk.code.get(pc) {
+    pc += 1;
+    match *op {
+      Op::Const(index) => stack.push(chunk.constants[index].clone()),
+      Op::Load(slot) => {
+        let value = match &slots[slot] {
+          Some(value) => value.clone(),
+          None => interpreter.constant(&chunk.locals[slot])?,
+        };
+        stack.push(value);
+      },
+      Op::LoadGlobal(name) => stack.push(interpreter.constant(&chunk.names[name])?),
+      Op::LoadField(slot, name) => {
+        let value = match &slots[slot] {
+          Some(value) => field(value.clone(), &chunk.names[name], || chunk.locals[slot].to_string())?,
+          None => interpreter.global_field(&chunk.locals[slot], &chunk.names[name])?,
+        };
+        stack.push(value);
+      },
+      Op::GlobalField(base, name) => stack.push(interpreter.global_field(&chunk.names[base], &chunk.names[name])?),
+      Op::Store(slot) => slots[slot] = stack.last().cloned(),
+      Op::Pop => {
+        stack.pop();
+      },
+      Op::Add | Op::Sub | Op::Compare(_) => {
+        let rightside = stack.pop().unwrap();
+        let leftside = stack.pop().unwrap();
+        stack.push(match *op {
+          Op::Add => add(leftside, rightside)?,
+          Op::Sub => sub(leftside, rightside)?,
+          Op::Compare(comparison) => compare(comparison, leftside, rightside)?,
+          _ => unreachable!(),
+        });
+      },
+      Op::Resolve(name) => callees.push((name, interpreter.callee(&chunk.names[name])?)),
+      Op::Call(count, site) => {
+        let values = stack.split_off(stack.len() - count);
+        let (name, function) = callees.pop().unwrap();
+        stack.push(interpreter.invoke(chunk.names[name].as_str(), site, &function, values)?);
+      },
+      Op::JumpIfFalse(target) => match stack.pop().unwrap() {
+        Value::Bool(true) => (),
+        Value::Bool(false) => pc = target,
+        other => return Err(not_bool(&other)),
+      },
+      Op::Jump(target) => pc = target,
+    }
+  }
+  Ok(stack.pop().unwrap())
+}
@@ -0,0 +1,193 @@
+use std::fs;
+use std::path::PathBuf;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+// Writes an Asa program to a scratch file and runs the asac binary on it with the given arguments.
+fn run_asac(name: &str, source: &str, args: &[&str]) -> std::process::Output {
+  let path: PathBuf = std::env::temp_dir().join(format!("asac-test-{}-{}.asa", name, std::process::id()));
+  fs::write(&path, source).unwrap();
+  let output = Command::new(env!("CARGO_BIN_EXE_asac")).arg(&path).args(args).output().unwrap();
+  fs::remove_file(&path).unwrap();
+  output
+}
+
+#[test]
+fn asac_main_exit_code() {
+  let output = run_asac("exit", r#"fn main(){return 7;}"#, &[]);
+  assert_eq!(output.status.code(), Some(7));
+}
+
+#[test]
+fn asac_main_numeric_args() {
+  let output = run_asac("numeric", r#"fn main(a,b){return a+b;}"#, &["40", "2"]);
+  assert_eq!(output.status.code(), Some(42));
+}
+
+#[test]
+fn asac_main_mixed_args() {
+  let output = run_asac("mixed", r#"fn main(name,flag,n){return n;}"#, &["foo", "true", "3"]);
+  assert_eq!(output.status.code(), Some(3));
+}
+
+#[test]
+fn asac_main_arity_mismatch() {
+  let output = run_asac("arity", r#"fn main(a){return a;}"#, &[]);
+  assert_eq!(output.status.code(), Some(1));
+  assert!(String::from_utf8_lossy(&output.stderr).contains("error[E0002]: 'main' expects 1 argument(s) but is called with 0"));
+}
+
+#[test]
+fn asac_backtrace() {
+  let output = run_asac("backtrace", "fn half(n){\n  return n - true;\n}\nfn main(){\n  return half(4);\n}", &[]);
+  assert_eq!(output.status.code(), Some(1));
+  assert_eq!(String::from_utf8_lossy(&output.stderr), "error[E0004]: '-' expects num, found bool\n  in half, called at 5:10\n  in main\n");
+}
+
+#[test]
+fn asac_runaway_recursion() {
+  let output = run_asac("recursion", "fn main(){\n  return main();\n}", &[]);
+  assert_eq!(output.status.code(), Some(1));
+  assert_eq!(String::from_utf8_lossy(&output.stderr), "error[E0008]: call stack too deep\n  in main, called at 2:10\n  ... repeated 4999 more times\n  in main\n");
+}
+
+// With --repl the program runs, then the REPL reads statements from stdin.
+#[test]
+fn asac_repl_return_outside_function() {
+  let path: PathBuf = std::env::temp_dir().join(format!("asac-test-repl-{}.asa", std::process::id()));
+  fs::write(&path, "let a = 1;").unwrap();
+  let mut child = Command::new(env!("CARGO_BIN_EXE_asac")).arg("--repl").arg(&path).stdin(Stdio::piped()).stdout(Stdio::piped()).spawn().unwrap();
+  child.stdin.take().unwrap().write_all(b"return 2;\n3;\n").unwrap();
+  let output = child.wait_with_output().unwrap();
+  fs::remove_file(&path).unwrap();
+  assert_eq!(output.status.code(), Some(0));
+  assert_eq!(String::from_utf8_lossy(&output.stdout), "> error[E0019]: 'return' outside of a function\n> 3\n> ");
+}
+
+// Without main the program's top-level code runs and asac exits, even with stdin left open.
+#[test]
+fn asac_no_main_exits() {
+  let path: PathBuf = std::env::temp_dir().join(format!("asac-test-no-main-{}.asa", std::process::id()));
+  fs::write(&path, r#"println("ran");"#).unwrap();
+  let mut child = Command::new(env!("CARGO_BIN_EXE_asac")).arg(&path).stdin(Stdio::piped()).stdout(Stdio::piped()).spawn().unwrap();
+  let _stdin = child.stdin.take();
+  let started = std::time::Instant::now();
+  while child.try_wait().unwrap().is_none() {
+    if started.elapsed() > std::time::Duration::from_secs(30) {
+      child.kill().unwrap();
+      panic!("asac is waiting for input");
+    }
+    std::thread::sleep(std::time::Duration::from_millis(10));
+  }
+  let output = child.wait_with_output().unwrap();
+  fs::remove_file(&path).unwrap();
+  assert_eq!(output.status.code(), Some(0));
+  assert_eq!(String::from_utf8_lossy(&output.stdout), "ran\n");
+}
+
+#[test]
+fn asac_main_non_numeric_result() {
+  let output = run_asac("bool", r#"fn main(){return true;}"#, &[]);
+  assert_eq!(output.status.code(), Some(0));
+}
+
+#[test]
+fn asac_allow_dir() {
+  let dir = std::env::temp_dir().join(format!("asac-allow-{}", std::process::id()));
+  fs::create_dir_all(&dir).unwrap();
+  let script = dir.join("script.asa");
+  fs::write(&script, format!(r#"fn main(){{write_file("{}/out.txt", "ok"); return 0;}}"#, dir.display())).unwrap();
+  let denied = Command::new(env!("CARGO_BIN_EXE_asac")).arg(&script).output().unwrap();
+  assert_eq!(denied.status.code(), Some(1));
+  assert!(String::from_utf8_lossy(&denied.stderr).contains("error[E0011]: permission denied"));
+  let allowed = Command::new(env!("CARGO_BIN_EXE_asac")).arg("--allow-dir").arg(&dir).arg(&script).output().unwrap();
+  assert_eq!(allowed.status.code(), Some(0));
+  assert_eq!(fs::read_to_string(dir.join("out.txt")).unwrap(), "ok");
+  fs::remove_dir_all(&dir).unwrap();
+}
+
+// Not even the current directory is accessible by default.
+#[test]
+fn asac_current_dir_denied_by_default() {
+  let dir = std::env::temp_dir().join(format!("asac-cwd-{}", std::process::id()));
+  fs::create_dir_all(&dir).unwrap();
+  fs::write(dir.join("script.asa"), r#"fn main(){write_file("out.txt", "ok"); return 0;}"#).unwrap();
+  let denied = Command::new(env!("CARGO_BIN_EXE_asac")).arg("script.asa").current_dir(&dir).output().unwrap();
+  assert_eq!(denied.status.code(), Some(1));
+  assert!(String::from_utf8_lossy(&denied.stderr).contains("error[E0011]: permission denied: out.txt"));
+  assert!(!dir.join("out.txt").exists());
+  let allowed = Command::new(env!("CARGO_BIN_EXE_asac")).args(["--allow-dir", ".", "script.asa"]).current_dir(&dir).output().unwrap();
+  assert_eq!(allowed.status.code(), Some(0));
+  assert_eq!(fs::read_to_string(dir.join("out.txt")).unwrap(), "ok");
+  fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn asac_checker_errors_stop_the_program() {
+  let output = run_asac("checker", "fn main(){\n  println(\"ran\");\n  return foo();\n}", &[]);
+  assert_eq!(output.status.code(), Some(1));
+  assert!(String::from_utf8_lossy(&output.stderr).contains(":3:10: error: undefined function 'foo'"));
+  assert_eq!(String::from_utf8_lossy(&output.stdout), "");
+}
+
+#[test]
+fn asac_checker_warnings_still_run() {
+  let output = run_asac("warning", r#"fn main(){let x = 1; return 3;}"#, &[]);
+  assert_eq!(output.status.code(), Some(3));
+  assert!(String::from_utf8_lossy(&output.stderr).contains("warning: unused variable 'x'"));
+}
+
+#[test]
+fn asac_typecheck_is_opt_in() {
+  let source = r#"fn main(){let x = if true {return 5;} else {return true;}; return x;}"#;
+  let unchecked = run_asac("untyped", source, &[]);
+  assert_eq!(unchecked.status.code(), Some(5));
+  let path = std::env::temp_dir().join(format!("asac-test-typed-{}.asa", std::process::id()));
+  fs::write(&path, source).unwrap();
+  let checked = Command::new(env!("CARGO_BIN_EXE_asac")).arg("--typecheck").arg(&path).output().unwrap();
+  fs::remove_file(&path).unwrap();
+  assert_eq!(checked.status.code(), Some(1));
+  assert!(String::from_utf8_lossy(&checked.stderr).contains(":1:19: error: if branches have different types: num and bool"));
+}
+
+#[test]
+fn asac_bytecode_backend() {
+  let path = std::env::temp_dir().join(format!("asac-test-backend-{}.asa", std::process::id()));
+  fs::write(&path, r#"fn twice(n){return n + n;} fn main(a){return twice(a);}"#).unwrap();
+  let output = Command::new(env!("CARGO_BIN_EXE_asac")).args(["--backend", "bytecode"]).arg(&path).arg("21").output().unwrap();
+  let unknown = Command::new(env!("CARGO_BIN_EXE_asac")).args(["--backend", "jit"]).arg(&path).output().unwrap();
+  fs::remove_file(&path).unwrap();
+  assert_eq!(output.status.code(), Some(42));
+  assert_eq!(unknown.status.code(), Some(2));
+}
+
+#[test]
+fn asac_fmt() {
+  let path = std::env::temp_dir().join(format!("asac-test-fmt-{}.asa", std::process::id()));
+  fs::write(&path, "fn main(){return 1+2;} // three\n").unwrap();
+  let check = Command::new(env!("CARGO_BIN_EXE_asac")).args(["fmt", "--check"]).arg(&path).output().unwrap();
+  let format = Command::new(env!("CARGO_BIN_EXE_asac")).arg("fmt").arg(&path).output().unwrap();
+  let formatted = fs::read_to_string(&path).unwrap();
+  let recheck = Command::new(env!("CARGO_BIN_EXE_asac")).args(["fmt", "--check"]).arg(&path).output().unwrap();
+  fs::remove_file(&path).unwrap();
+  assert_eq!(check.status.code(), Some(1));
+  assert!(String::from_utf8_lossy(&check.stderr).contains("not formatted"));
+  assert_eq!(format.status.code(), Some(0));
+  assert_eq!(formatted, "fn main() {\n  return 1 + 2;\n} // three\n");
+  assert_eq!(recheck.status.code(), Some(0));
+}
+
+#[test]
+fn asac_lint() {
+  let path = std::env::temp_dir().join(format!("asac-test-lint-{}.asa", std::process::id()));
+  fs::write(&path, "fn main(){let x = 1; return 2;}").unwrap();
+  let warned = Command::new(env!("CARGO_BIN_EXE_asac")).arg("lint").arg(&path).output().unwrap();
+  let denied = Command::new(env!("CARGO_BIN_EXE_asac")).args(["lint", "--deny", "unused-variable"]).arg(&path).output().unwrap();
+  let allowed = Command::new(env!("CARGO_BIN_EXE_asac")).args(["lint", "--allow", "unused-variable"]).arg(&path).output().unwrap();
+  fs::remove_file(&path).unwrap();
+  assert_eq!(warned.status.code(), Some(0));
+  assert!(String::from_utf8_lossy(&warned.stderr).contains(":1:15: warning[unused-variable]: unused variable 'x'"));
+  assert_eq!(denied.status.code(), Some(1));
+  assert!(String::from_utf8_lossy(&denied.stderr).contains("error[unused-variable]"));
+  assert_eq!(allowed.stderr, b"");
+}
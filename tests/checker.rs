@@ -0,0 +1,106 @@
+use asalang::*;
+
+// Parses a program and checks it against a fresh interpreter, returning each finding as its
+// location and kind.
+fn check_source(source: &str) -> Vec<(String, FindingKind)> {
+  let (tokens, tree) = program(lex(source)).unwrap();
+  assert!(tokens.is_done());
+  let interpreter = Interpreter::new();
+  check(&tree, &interpreter).into_iter().map(|finding| (finding.span.to_string(), finding.kind)).collect()
+}
+
+#[test]
+fn checker_clean_program() {
+  assert_eq!(check_source(r#"const LIMIT = 2; fn add(a,b){let x = a + b; return x;} fn main(){return add(LIMIT, len("abc"));}"#), vec![]);
+}
+
+#[test]
+fn checker_undefined_variable() {
+  assert_eq!(check_source("fn main(){\n  return y;\n}"), vec![
+    ("2:10".to_string(), FindingKind::UndefinedVariable("y".to_string())),
+  ]);
+}
+
+#[test]
+fn checker_top_level_variable_not_visible_in_function() {
+  assert_eq!(check_source("let x = 1;\nfn main(){return x;}"), vec![
+    ("2:18".to_string(), FindingKind::UndefinedVariable("x".to_string())),
+  ]);
+}
+
+// The catch variable and its fields are defined in the catch and finally blocks, but not in the try block
+// or after the try.
+#[test]
+fn checker_catch_variable() {
+  assert_eq!(check_source("fn main(){\n  try {throw e;} catch e {println(e.kind);} finally {println(e);}\n  return e.kind;\n}"), vec![
+    ("2:14".to_string(), FindingKind::UndefinedVariable("e".to_string())),
+    ("3:10".to_string(), FindingKind::UndefinedVariable("e.kind".to_string())),
+  ]);
+}
+
+#[test]
+fn checker_undefined_function() {
+  assert_eq!(check_source("fn main(){return foo(1);}"), vec![
+    ("1:18".to_string(), FindingKind::UndefinedFunction("foo".to_string())),
+  ]);
+}
+
+#[test]
+fn checker_duplicate_function() {
+  assert_eq!(check_source("fn f(){return 1;}\nfn f(){return 2;}"), vec![
+    ("2:4".to_string(), FindingKind::DuplicateFunction("f".to_string())),
+  ]);
+}
+
+#[test]
+fn checker_arity_mismatch() {
+  assert_eq!(check_source("fn f(a){return a;}\nfn main(){return f(1, 2) + substring(\"abc\");}"), vec![
+    ("2:18".to_string(), FindingKind::ArityMismatch{name: "f".to_string(), expected: Arity::Exact(1), found: 2}),
+    ("2:28".to_string(), FindingKind::ArityMismatch{name: "substring".to_string(), expected: Arity::Exact(3), found: 1}),
+  ]);
+}
+
+#[test]
+fn checker_variadic_arity() {
+  assert_eq!(check_source(r#"fn main(){return min();}"#), vec![
+    ("1:18".to_string(), FindingKind::ArityMismatch{name: "min".to_string(), expected: Arity::AtLeast(1), found: 0}),
+  ]);
+}
+
+#[test]
+fn checker_code_after_return() {
+  assert_eq!(check_source("fn main(){\n  return 1;\n  let x = 2;\n  return x;\n}"), vec![
+    ("3:7".to_string(), FindingKind::CodeAfterReturn),
+  ]);
+}
+
+// Code after a return isn't skipped: it runs, and its value replaces the returned one.
+#[test]
+fn checker_code_after_return_runs() {
+  let source = "fn main(){\n  return 1;\n  let y = 2;\n}";
+  let (_, tree) = program(lex(source)).unwrap();
+  let mut interpreter = Interpreter::new();
+  let findings = check(&tree, &interpreter);
+  assert_eq!(findings[0].to_string(), "3:7: warning: code after return still runs, and the last statement's value is returned instead");
+  interpreter.exec(&tree).unwrap();
+  assert_eq!(interpreter.start_main(vec![]), Ok(Value::Number(2)));
+}
+
+#[test]
+fn checker_unused_variable() {
+  assert_eq!(check_source("fn main(){\n  let x = 1;\n  let y = 2;\n  return y;\n}"), vec![
+    ("2:7".to_string(), FindingKind::UnusedVariable("x".to_string())),
+  ]);
+}
+
+#[test]
+fn checker_imported_names_accepted() {
+  assert_eq!(check_source(r#"import "util.asa"; fn main(){return util.helper(util.LIMIT);}"#), vec![]);
+}
+
+#[test]
+fn checker_finding_display() {
+  let (_, tree) = program(lex("fn main(){return foo();}")).unwrap();
+  let findings = check(&tree, &Interpreter::new());
+  assert_eq!(findings[0].to_string(), "1:18: error: undefined function 'foo'");
+}
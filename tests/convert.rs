@@ -0,0 +1,100 @@
+extern crate asalang;
+
+use std::collections::HashMap;
+use asalang::*;
+
+fn load(source: &str) -> Result<Interpreter,AsaErrorKind> {
+  let tokens = lex(source);
+  let (_, tree) = program(tokens).map_err(|e| AsaErrorKind::Generic(format!("{:?}",e)))?;
+  let mut interpreter = Interpreter::new();
+  interpreter.exec(&tree)?;
+  Ok(interpreter)
+}
+
+#[test]
+fn convert_call_tuple_args() -> Result<(),AsaErrorKind> {
+  let mut interpreter = load(r#"fn score(a,b){return a+b;}"#)?;
+  let n: i64 = interpreter.call("score", (40, 2))?;
+  assert_eq!(n, 42);
+  Ok(())
+}
+
+#[test]
+fn convert_call_value_slice() -> Result<(),AsaErrorKind> {
+  let mut interpreter = load(r#"fn id(a){return a;}"#)?;
+  let result: Value = interpreter.call("id", &[Value::String("asa".to_string())])?;
+  assert_eq!(result, Value::String("asa".to_string()));
+  Ok(())
+}
+
+#[test]
+fn convert_call_no_args() -> Result<(),AsaErrorKind> {
+  let mut interpreter = load(r#"fn truth(){return true;}"#)?;
+  let result: bool = interpreter.call("truth", ())?;
+  assert!(result);
+  Ok(())
+}
+
+#[test]
+fn convert_call_undefined() -> Result<(),AsaErrorKind> {
+  let mut interpreter = load(r#"fn truth(){return true;}"#)?;
+  assert_eq!(interpreter.call::<_, Value>("lie", ()), Err(AsaErrorKind::UndefinedFunction("lie".to_string())));
+  Ok(())
+}
+
+#[test]
+fn convert_call_arity_mismatch() -> Result<(),AsaErrorKind> {
+  let mut interpreter = load(r#"fn score(a,b){return a+b;}"#)?;
+  assert_eq!(interpreter.call::<_, i32>("score", (1,)), Err(AsaErrorKind::ArityMismatch{name: "score".to_string(), expected: Arity::Exact(2), found: 1}));
+  Ok(())
+}
+
+#[test]
+fn convert_call_wrong_return_type() -> Result<(),AsaErrorKind> {
+  let mut interpreter = load(r#"fn truth(){return true;}"#)?;
+  assert_eq!(interpreter.call::<_, String>("truth", ()), Err(AsaErrorKind::type_error("from_value", "str", "bool")));
+  Ok(())
+}
+
+#[test]
+fn convert_call_native_list() -> Result<(),AsaErrorKind> {
+  let mut interpreter = Interpreter::new();
+  interpreter.register_native("count", 1, |args| match args {
+    [Value::List(items)] => Ok(Value::Number(items.len() as i32)),
+    _ => Err(AsaErrorKind::type_error("count", "list", args[0].type_name())),
+  });
+  let n: i32 = interpreter.call("count", (vec![1, 2, 3],))?;
+  assert_eq!(n, 3);
+  Ok(())
+}
+
+#[test]
+fn convert_i64_overflow() {
+  assert_eq!(i64::MAX.into_value(), Err(AsaErrorKind::NumberOverflow));
+  assert_eq!(i64::MIN.into_value(), Err(AsaErrorKind::NumberUnderflow));
+  assert_eq!(7i64.into_value(), Ok(Value::Number(7)));
+}
+
+#[test]
+fn convert_float() {
+  assert_eq!(1.5f64.into_value(), Ok(Value::Float(1.5)));
+  assert_eq!(f64::from_value(Value::Number(2)), Ok(2.0));
+  assert_eq!(f64::from_value(Value::Bool(true)), Err(AsaErrorKind::type_error("from_value", "num or float", "bool")));
+}
+
+#[test]
+fn convert_vec_round_trip() {
+  let value = vec!["a".to_string(), "b".to_string()].into_value().unwrap();
+  assert_eq!(value, Value::List(vec![Value::String("a".to_string()), Value::String("b".to_string())]));
+  assert_eq!(Vec::<String>::from_value(value), Ok(vec!["a".to_string(), "b".to_string()]));
+  assert_eq!(Vec::<i32>::from_value(Value::List(vec![Value::Bool(false)])), Err(AsaErrorKind::type_error("from_value", "num", "bool")));
+}
+
+#[test]
+fn convert_map_round_trip() {
+  let mut map = HashMap::new();
+  map.insert("x".to_string(), 1);
+  map.insert("y".to_string(), 2);
+  let value = map.clone().into_value().unwrap();
+  assert_eq!(HashMap::<String,i32>::from_value(value), Ok(map));
+}
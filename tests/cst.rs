@@ -0,0 +1,49 @@
+use asalang::*;
+
+// Lexes a source without losing anything and checks that printing the tree gives it back.
+fn round_trip(source: &str) -> Cst {
+  let cst = Cst::new(source);
+  assert_eq!(cst.to_string(), source);
+  cst
+}
+
+#[test]
+fn cst_round_trip_program() {
+  round_trip("// adds two numbers\nfn add(a, b) {\n\treturn a + b; // the sum\n}\r\n\nfn main() {\n  return add(1, 2);\n}\n");
+}
+
+#[test]
+fn cst_round_trip_string_escapes() {
+  round_trip(r#"let s = "say \"hi\"\n\ttwice";"#);
+}
+
+#[test]
+fn cst_round_trip_invalid_characters() {
+  let cst = round_trip("let x = 1 ? é;");
+  assert!(cst.tokens.iter().any(|token| token.token.kind == TokenKind::Other));
+}
+
+#[test]
+fn cst_attaches_trivia_to_next_token() {
+  let cst = round_trip("let x =\n\t1; // one\n");
+  let one = &cst.tokens[3];
+  assert_eq!(one.token.lexeme, b"1".to_vec());
+  assert_eq!(one.leading.iter().map(|token| token.kind).collect::<Vec<_>>(), vec![TokenKind::NewLine, TokenKind::Tab]);
+  let eof = cst.tokens.last().unwrap();
+  assert_eq!(eof.token.kind, TokenKind::EOF);
+  assert_eq!(eof.leading.iter().map(|token| token.kind).collect::<Vec<_>>(), vec![TokenKind::WhiteSpace, TokenKind::Comment, TokenKind::NewLine]);
+}
+
+#[test]
+fn cst_comments() {
+  let cst = round_trip("// first\nlet x = 1; // second\n");
+  let comments: Vec<String> = cst.comments().map(|token| String::from_utf8_lossy(&token.lexeme).into_owned()).collect();
+  assert_eq!(comments, vec!["// first", "// second"]);
+}
+
+#[test]
+fn cst_comments_are_skipped_by_the_parser() {
+  let (tokens, tree) = program(lex("// setup\nlet x = 1; // one\nx")).unwrap();
+  assert!(tokens.is_done());
+  assert_eq!(Interpreter::new().exec(&tree), Ok(Value::Number(1)));
+}
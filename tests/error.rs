@@ -0,0 +1,57 @@
+extern crate asalang;
+
+use asalang::*;
+
+fn run(source: &str) -> Result<Value,AsaErrorKind> {
+  let tree = parse(lex(source)).map_err(AsaErrorKind::SyntaxError)?;
+  let mut interpreter = Interpreter::new();
+  interpreter.exec(&tree)?;
+  interpreter.start_main(vec![])
+}
+
+#[test]
+fn error_codes_are_stable() {
+  assert_eq!(AsaErrorKind::UndefinedFunction("foo".to_string()).code(), "E0001");
+  assert_eq!(AsaErrorKind::type_error("-", "num", "bool").code(), "E0004");
+  assert_eq!(AsaErrorKind::UndefinedVariable("x".to_string()).code(), "E0016");
+  assert_eq!(AsaErrorKind::Generic(String::new()).code(), "E0019");
+}
+
+#[test]
+fn error_display() {
+  let undefined = run(r#"fn main(){return foo(1);}"#).unwrap_err();
+  assert_eq!(undefined.to_string(), "undefined function 'foo'");
+  let mismatch = run(r#"fn main(){return 5 - true;}"#).unwrap_err();
+  assert_eq!(mismatch.to_string(), "'-' expects num, found bool");
+  let variable = run(r#"fn main(){return y;}"#).unwrap_err();
+  assert_eq!(variable.to_string(), "undefined variable 'y'");
+}
+
+#[test]
+fn error_display_syntax_errors() {
+  let errors = run("fn main() {\n  let = 1;\n  return 1;\n}\n)").unwrap_err();
+  assert_eq!(errors.to_string(), "syntax error\n  expected an identifier, found '=' at 2:7\n  expected a definition or statement, found ')' at 5:1");
+}
+
+#[test]
+fn error_is_std_error() {
+  let error: Box<dyn std::error::Error> = Box::new(AsaErrorKind::DivisionByZero);
+  assert_eq!(error.to_string(), "division by zero");
+}
+
+#[test]
+fn error_kind_names_the_variant() {
+  assert_eq!(AsaErrorKind::DivisionByZero.kind(), "DivisionByZero");
+  assert_eq!(AsaErrorKind::type_error("-", "num", "bool").kind(), "TypeError");
+  let thrown = AsaErrorKind::Thrown(Value::String("oops".to_string()));
+  assert_eq!((thrown.kind(), thrown.code()), ("Thrown", "E0020"));
+  assert_eq!(thrown.to_string(), "uncaught oops");
+}
+
+#[test]
+fn error_display_arity_mismatch() {
+  let user = run(r#"fn f(a, b){return a;} fn main(){return f(1);}"#).unwrap_err();
+  assert_eq!(user.to_string(), "'f' expects 2 argument(s) but is called with 1");
+  let native = run(r#"fn main(){return format();}"#).unwrap_err();
+  assert_eq!(native.to_string(), "'format' expects at least 1 argument(s) but is called with 0");
+}
@@ -0,0 +1,67 @@
+use asalang::*;
+
+fn parse(source: &str) -> Node {
+  let (tokens, tree) = program(lex(source)).unwrap();
+  assert!(tokens.is_done());
+  tree
+}
+
+// Formats a source and checks that the result means the same and is already formatted.
+fn format(source: &str) -> String {
+  let formatted = format_source(source).unwrap();
+  assert_eq!(parse(&formatted).without_spans(), parse(source).without_spans());
+  assert_eq!(format_source(&formatted).unwrap(), formatted);
+  formatted
+}
+
+#[test]
+fn format_spacing_and_indentation() {
+  assert_eq!(format("fn add(a:num,b)->num{let x=a+b;\n\t\treturn x;}"), "fn add(a: num, b) -> num {\n  let x = a + b;\n  return x;\n}\n");
+}
+
+#[test]
+fn format_one_statement_per_line() {
+  assert_eq!(format(r#"import "util.asa"; const LIMIT=2;let x=max(1,LIMIT);x"#), "import \"util.asa\";\nconst LIMIT = 2;\nlet x = max(1, LIMIT);\nx;\n");
+}
+
+#[test]
+fn format_blank_line_around_functions() {
+  assert_eq!(format("let x = 1;\nfn f(){return 1;}\npub fn g(){return 2;}"), "let x = 1;\n\nfn f() {\n  return 1;\n}\n\npub fn g() {\n  return 2;\n}\n");
+}
+
+#[test]
+fn format_if_expressions() {
+  assert_eq!(
+    format("fn f(n){return if n>9 {return \"a\";} else if n==0 {return \"b\";} else {return n-1};}"),
+    "fn f(n) {\n  return if n > 9 {return \"a\";} else if n == 0 {return \"b\";} else {return n - 1;};\n}\n",
+  );
+}
+
+#[test]
+fn format_literals() {
+  assert_eq!(format(r#"let s = "say \"hi\"\n"; let f = 2.50; let g = 3.0;"#), "let s = \"say \\\"hi\\\"\\n\";\nlet f = 2.5;\nlet g = 3.0;\n");
+}
+
+#[test]
+fn format_keeps_comments() {
+  let source = "// header\nfn main(){ // entry\n  // before\n  let x = 1; // one\n  return x;\n  // last\n} // after\n// end\n";
+  assert_eq!(format(source), "// header\nfn main() { // entry\n  // before\n  let x = 1; // one\n  return x;\n  // last\n} // after\n// end\n");
+}
+
+#[test]
+fn format_parse_error() {
+  assert!(format_source("fn main( {").is_err());
+}
+
+#[test]
+fn format_try_catch() {
+  let source = "fn main(){try{throw \"x\";} catch e { // handle\n println(e);}finally{println(1);}\n return 0;}";
+  assert_eq!(format(source), "fn main() {\n  try {\n    throw \"x\";\n  } catch e { // handle\n    println(e);\n  } finally {\n    println(1);\n  }\n  return 0;\n}\n");
+}
+
+// A statement is printed on one line, so a comment inside it moves before it rather than after.
+#[test]
+fn format_comment_inside_statement() {
+  assert_eq!(format("let x = max(\n  // note\n  1, 2);\nlet y = 3;"), "// note\nlet x = max(1, 2);\nlet y = 3;\n");
+  assert_eq!(format("fn f() {\n  return max(1, // one\n    // two\n    2);\n}"), "fn f() {\n  // two\n  return max(1, 2); // one\n}\n");
+}
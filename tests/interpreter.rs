@@ -1,6 +1,8 @@
 extern crate asalang;
 extern crate nom;
+use std::cell::RefCell;
 use std::io::Write;
+use std::rc::Rc;
 
 use asalang::*;
 use nom::IResult;
@@ -50,10 +52,10 @@ test_fragment!(interpreter_numeric, r#"123"#, Ok(Value::Number(123)));
 test_fragment!(interpreter_string, r#""helloworld""#, Ok(Value::String("helloworld".to_string())));
 test_fragment!(interpreter_bool_true, r#"true"#, Ok(Value::Bool(true)));
 test_fragment!(interpreter_bool_false, r#"false"#, Ok(Value::Bool(false)));
-test_fragment!(interpreter_identifier, r#"x"#, Err(AsaErrorKind::UndefinedVariable));
-test_fragment!(interpreter_function_call, r#"foo()"#, Err(AsaErrorKind::UndefinedFunction));
-test_fragment!(interpreter_function_call_one_arg, r#"foo(a)"#, Err(AsaErrorKind::UndefinedFunction));
-test_fragment!(interpreter_function_call_more_args, r#"foo(a,b,c)"#, Err(AsaErrorKind::UndefinedFunction));
+test_fragment!(interpreter_identifier, r#"x"#, Err(AsaErrorKind::UndefinedVariable("x".to_string())));
+test_fragment!(interpreter_function_call, r#"foo()"#, Err(AsaErrorKind::UndefinedFunction("foo".to_string())));
+test_fragment!(interpreter_function_call_one_arg, r#"foo(a)"#, Err(AsaErrorKind::UndefinedFunction("foo".to_string())));
+test_fragment!(interpreter_function_call_more_args, r#"foo(a,b,c)"#, Err(AsaErrorKind::UndefinedFunction("foo".to_string())));
 test_fragment!(interpreter_variable_define, r#"let x = 123;"#, Ok(Value::Number(123)));
 test_fragment!(interpreter_variable_init, r#"let x = 1;"#, Ok(Value::Number(1)));
 test_fragment!(interpreter_variable_bool, r#"let bool = true;"#, Ok(Value::Bool(true)));
@@ -63,8 +65,8 @@ test_fragment!(interpreter_math, r#"1 + 1"#, Ok(Value::Number(2)));
 test_fragment!(interpreter_math_no_space, r#"1-1"#, Ok(Value::Number(0)));
 test_fragment!(interpreter_math_multiply, r#"2 + 4"#, Ok(Value::Number(6)));
 test_fragment!(interpreter_assign_math, r#"let x = 1 + 1;"#, Ok(Value::Number(2)));
-test_fragment!(interpreter_assign_function, r#"let x = foo();"#, Err(AsaErrorKind::UndefinedFunction));
-test_fragment!(interpreter_assign_function_arguments, r#"let x = foo(a,b,c);"#, Err(AsaErrorKind::UndefinedFunction));
+test_fragment!(interpreter_assign_function, r#"let x = foo();"#, Err(AsaErrorKind::UndefinedFunction("foo".to_string())));
+test_fragment!(interpreter_assign_function_arguments, r#"let x = foo(a,b,c);"#, Err(AsaErrorKind::UndefinedFunction("foo".to_string())));
 
 test_fragment!(interpreter_if_expression_true, r#"if 2 > 3 {return false;} else {return true;}"#,Ok(Value::Bool(true)));
 test_fragment!(interpreter_if_expression_false, r#"if 3 > 2 {return false;} else {return true;}"#,Ok(Value::Bool(false)));
@@ -103,3 +105,415 @@ fn main() {
   return foo(1,2,3);  
 }"#, Ok(Value::Number(10)));
 
+
+// Test native functions registered by the host
+fn run_native_program(source: &str) -> Result<Value,AsaErrorKind> {
+  let tokens = lex(source);
+  let (_, tree) = program(tokens).map_err(|e| AsaErrorKind::Generic(format!("{:?}",e)))?;
+  let mut interpreter = Interpreter::new();
+  interpreter.register_native("double", 1, |args| match args {
+    [Value::Number(n)] => Ok(Value::Number(n * 2)),
+    [other] => Err(AsaErrorKind::type_error("double", "num", other.type_name())),
+    _ => Err(AsaErrorKind::ArityMismatch{name: "double".to_string(), expected: Arity::Exact(1), found: args.len()}),
+  });
+  interpreter.register_native("answer", 0, |_| Ok(Value::Number(42)));
+  interpreter.register_native("divide", 2, |args| match args {
+    [_, Value::Number(0)] => Err(AsaErrorKind::DivisionByZero),
+    [Value::Number(a), Value::Number(b)] => Ok(Value::Number(a / b)),
+    _ => Err(AsaErrorKind::type_error("divide", "num", "other")),
+  });
+  interpreter.exec(&tree)?;
+  interpreter.start_main(vec![])
+}
+
+#[test]
+fn interpreter_native_no_args() {
+  assert_eq!(run_native_program(r#"fn main(){return answer();}"#), Ok(Value::Number(42)));
+}
+
+#[test]
+fn interpreter_native_with_args() {
+  assert_eq!(run_native_program(r#"fn main(){let x = 4; return double(x + 1);}"#), Ok(Value::Number(10)));
+}
+
+#[test]
+fn interpreter_native_from_user_function() {
+  assert_eq!(run_native_program(r#"fn main(){return foo(3);} fn foo(a){return double(a);}"#), Ok(Value::Number(6)));
+}
+
+#[test]
+fn interpreter_native_arity_mismatch() {
+  assert_eq!(run_native_program(r#"fn main(){return double(1,2);}"#), Err(AsaErrorKind::ArityMismatch{name: "double".to_string(), expected: Arity::Exact(1), found: 2}));
+}
+
+#[test]
+fn interpreter_native_error() {
+  assert_eq!(run_native_program(r#"fn main(){return double(true);}"#), Err(AsaErrorKind::type_error("double", "num", "bool")));
+}
+
+#[test]
+fn interpreter_start_main_arguments() -> Result<(),AsaErrorKind> {
+  let (_, tree) = program(lex(r#"fn main(a,b){return a-b;}"#)).map_err(|e| AsaErrorKind::Generic(format!("{:?}",e)))?;
+  let mut interpreter = Interpreter::new();
+  interpreter.exec(&tree)?;
+  let arguments = Node::FunctionArguments{children: vec![Node::Number{value: 5}, Node::Number{value: 3}]};
+  assert_eq!(interpreter.start_main(vec![arguments]), Ok(Value::Number(2)));
+  Ok(())
+}
+
+// Test output built-ins, capturing what the program prints
+#[derive(Clone, Default)]
+struct Capture(Rc<RefCell<Vec<u8>>>);
+
+impl Write for Capture {
+  fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+    self.0.borrow_mut().write(buf)
+  }
+  fn flush(&mut self) -> std::io::Result<()> {
+    Ok(())
+  }
+}
+
+fn run_captured(source: &str) -> (Result<Value,AsaErrorKind>, String) {
+  let capture = Capture::default();
+  let mut interpreter = Interpreter::new();
+  interpreter.set_output(capture.clone());
+  let result = match program(lex(source)) {
+    Ok((_, tree)) => interpreter.exec(&tree).and_then(|_| interpreter.start_main(vec![])),
+    Err(e) => Err(AsaErrorKind::Generic(format!("{:?}",e))),
+  };
+  let output = String::from_utf8(capture.0.borrow().clone()).unwrap();
+  (result, output)
+}
+
+#[test]
+fn interpreter_println() {
+  let (result, output) = run_captured(r#"fn main(){println("hello world"); println(1, true); return 0;}"#);
+  assert_eq!(result, Ok(Value::Number(0)));
+  assert_eq!(output, "hello world\n1 true\n");
+}
+
+#[test]
+fn interpreter_print() {
+  let (_, output) = run_captured(r#"fn main(){print("a"); print("b"); return 0;}"#);
+  assert_eq!(output, "ab");
+}
+
+#[test]
+fn interpreter_format() {
+  let (result, _) = run_captured(r#"fn main(){let a = 1; let b = 2; return format("{} + {} = {}", a, b, a + b);}"#);
+  assert_eq!(result, Ok(Value::String("1 + 2 = 3".to_string())));
+}
+
+#[test]
+fn interpreter_format_braces() {
+  let (result, _) = run_captured(r#"fn main(){return format("{{}} {}", 5);}"#);
+  assert_eq!(result, Ok(Value::String("{} 5".to_string())));
+}
+
+#[test]
+fn interpreter_format_missing_argument() {
+  let (result, _) = run_captured(r#"fn main(){return format("{} {}", 5);}"#);
+  assert_eq!(result, Err(AsaErrorKind::ArityMismatch{name: "format".to_string(), expected: Arity::Exact(3), found: 2}));
+}
+
+#[test]
+fn interpreter_format_not_string() {
+  let (result, _) = run_captured(r#"fn main(){return format(5);}"#);
+  assert_eq!(result, Err(AsaErrorKind::type_error("format", "str", "num")));
+}
+
+// The if_assign parser builds the IfAssign node, whose branch must only run once.
+#[test]
+fn interpreter_if_assign_prints_once() {
+  let capture = Capture::default();
+  let mut interpreter = Interpreter::new();
+  interpreter.set_output(capture.clone());
+  let (tokens, tree) = if_assign(lex(r#"let x = if true {return println("a");} else {return println("b");}"#)).unwrap();
+  assert!(tokens.is_done());
+  assert!(matches!(tree, Node::IfAssign{..}));
+  assert_eq!(interpreter.exec(&tree), Ok(Value::Bool(true)));
+  assert_eq!(String::from_utf8(capture.0.borrow().clone()).unwrap(), "a\n");
+}
+
+#[test]
+fn interpreter_print_top_level() {
+  let capture = Capture::default();
+  let mut interpreter = Interpreter::new();
+  interpreter.set_output(capture.clone());
+  let (tokens, tree) = program(lex(r#"println("top"); let x = 2; println(x);"#)).unwrap();
+  assert!(tokens.is_done());
+  interpreter.exec(&tree).unwrap();
+  assert_eq!(String::from_utf8(capture.0.borrow().clone()).unwrap(), "top\n2\n");
+}
+
+// Test string built-ins
+test_program!(interpreter_string_concat, r#"fn main(){let a = "foo"; return a + "bar";}"#, Ok(Value::String("foobar".to_string())));
+test_program!(interpreter_string_concat_type_error, r#"fn main(){return "foo" + 1;}"#, Err(AsaErrorKind::type_error("+", "str", "num")));
+test_program!(interpreter_string_len_unicode, r#"fn main(){return len("héllo wörld");}"#, Ok(Value::Number(11)));
+test_program!(interpreter_string_len_call_in_math, r#"fn main(){return len("abc") + 1;}"#, Ok(Value::Number(4)));
+test_program!(interpreter_string_substring, r#"fn main(){return substring("naïve café", 2, 5);}"#, Ok(Value::String("ïve".to_string())));
+test_program!(interpreter_string_substring_out_of_bounds, r#"fn main(){return substring("abc", 1, 4);}"#, Err(AsaErrorKind::IndexOutOfBounds));
+test_program!(interpreter_string_split_join, r#"fn main(){let parts = split("a,b,c", ","); return join(parts, " - ");}"#, Ok(Value::String("a - b - c".to_string())));
+test_program!(interpreter_string_split, r#"fn main(){return split("a b", " ");}"#, Ok(Value::List(vec![Value::String("a".to_string()), Value::String("b".to_string())])));
+test_program!(interpreter_string_trim_upper, r#"fn main(){return upper(trim("  straße "));}"#, Ok(Value::String("STRASSE".to_string())));
+test_program!(interpreter_string_lower, r#"fn main(){return lower("ÀB");}"#, Ok(Value::String("àb".to_string())));
+test_program!(interpreter_string_contains, r#"fn main(){return contains("haystack", "st");}"#, Ok(Value::Bool(true)));
+test_program!(interpreter_string_starts_with, r#"fn main(){return starts_with("haystack", "st");}"#, Ok(Value::Bool(false)));
+test_program!(interpreter_string_replace, r#"fn main(){return replace("a-b-c", "-", "+");}"#, Ok(Value::String("a+b+c".to_string())));
+test_program!(interpreter_string_parse_int, r#"fn main(){return parse_int(" 42 ") + 1;}"#, Ok(Value::Number(43)));
+test_program!(interpreter_string_parse_int_invalid, r#"fn main(){return parse_int("4x2");}"#, Err(AsaErrorKind::InvalidNumber("4x2".to_string())));
+test_program!(interpreter_string_parse_int_overflow, r#"fn main(){return parse_int("99999999999");}"#, Err(AsaErrorKind::NumberOverflow));
+test_program!(interpreter_string_to_string, r#"fn main(){return to_string(12) + "!";}"#, Ok(Value::String("12!".to_string())));
+test_program!(interpreter_string_wrong_type, r#"fn main(){return upper(5);}"#, Err(AsaErrorKind::type_error("upper", "str", "num")));
+
+// Test the run-time semantics of comparisons and if expressions. These don't depend on the type checker:
+// as the README asks, the condition is evaluated first, only the branch it selects runs, and that
+// branch's value is the value of the if expression.
+test_fragment!(interpreter_compare_greater, r#"3 > 2"#, Ok(Value::Bool(true)));
+test_fragment!(interpreter_compare_less_mixed, r#"1.5 < 2"#, Ok(Value::Bool(true)));
+test_fragment!(interpreter_compare_strings, r#""abc" < "abd""#, Ok(Value::Bool(true)));
+test_fragment!(interpreter_compare_equal_bools, r#"true == false"#, Ok(Value::Bool(false)));
+test_fragment!(interpreter_compare_num_bool, r#"1 > true"#, Err(AsaErrorKind::type_error(">", "num", "bool")));
+test_fragment!(interpreter_math_on_bool, r#"5 - false"#, Err(AsaErrorKind::type_error("-", "num", "bool")));
+test_program!(interpreter_if_expression_value, r#"fn main(){let x = if 2 > 1 {return "yes";} else {return "no";}; return x;}"#, Ok(Value::String("yes".to_string())));
+test_program!(interpreter_if_expression_short_circuit, r#"fn main(){return if true {return 1;} else {return foo();};}"#, Ok(Value::Number(1)));
+test_program!(interpreter_if_branches_of_different_types, r#"fn main(){return if false {return 1;} else {return true;};}"#, Ok(Value::Bool(true)));
+
+#[test]
+fn interpreter_if_expression_evaluation_order() {
+  let (result, output) = run_captured(r#"fn show(v){println(v); return v;} fn main(){return if show(1) > show(2) {return show("then");} else {return show("else");};}"#);
+  assert_eq!(result, Ok(Value::String("else".to_string())));
+  assert_eq!(output, "1\n2\nelse\n");
+}
+
+#[test]
+fn interpreter_else_if_runs_one_branch() {
+  let (result, output) = run_captured(r#"fn show(v){println(v); return v;} fn main(){return if show(1) == 2 {return show(1);} else if show(2) == 2 {return show(2);} else {return show(3)};}"#);
+  assert_eq!(result, Ok(Value::Number(2)));
+  assert_eq!(output, "1\n2\n2\n");
+}
+
+// Test constants
+test_program!(interpreter_const, r#"const LIMIT = 10; fn main(){return LIMIT + 1;}"#, Ok(Value::Number(11)));
+test_program!(interpreter_const_in_every_function, r#"const LIMIT = 3; fn twice(){return LIMIT + LIMIT;} fn main(){return twice() + LIMIT;}"#, Ok(Value::Number(9)));
+test_program!(interpreter_const_evaluated_once, r#"const NAME = to_string(42); fn main(){return NAME + "!";}"#, Ok(Value::String("42!".to_string())));
+test_program!(interpreter_const_parameter_shadows, r#"const A = 1; fn f(A){return A;} fn main(){return f(5);}"#, Ok(Value::Number(5)));
+test_fragment!(interpreter_const_reassign_top_level, r#"const LIMIT = 10; let LIMIT = 11;"#, Err(AsaErrorKind::ConstReassignment("LIMIT".to_string())));
+test_fragment!(interpreter_const_defined_twice, r#"const LIMIT = 10; const LIMIT = 11;"#, Err(AsaErrorKind::ConstReassignment("LIMIT".to_string())));
+
+#[test]
+fn interpreter_const_reassign_reported_before_running() {
+  let (result, output) = run_captured(r#"println("started"); const LIMIT = 10; fn main(){let LIMIT = 11; return LIMIT;}"#);
+  assert_eq!(result, Err(AsaErrorKind::ConstReassignment("LIMIT".to_string())));
+  assert_eq!(output, "");
+}
+
+// Test math built-ins
+test_fragment!(interpreter_float_literal, r#"1.5 + 2"#, Ok(Value::Float(3.5)));
+test_fragment!(interpreter_math_overflow, r#"2147483647 + 1"#, Err(AsaErrorKind::NumberOverflow));
+test_program!(interpreter_math_underflow, r#"fn main(){let min = 0 - 2147483647; return min - 2;}"#, Err(AsaErrorKind::NumberUnderflow));
+test_program!(interpreter_math_abs, r#"fn main(){return abs(0 - 5);}"#, Ok(Value::Number(5)));
+test_program!(interpreter_math_abs_overflow, r#"fn main(){let min = 0 - 2147483647; return abs(min - 1);}"#, Err(AsaErrorKind::NumberOverflow));
+test_program!(interpreter_math_min_max, r#"fn main(){return min(3, 1, 2) + max(3, 1, 2);}"#, Ok(Value::Number(4)));
+test_program!(interpreter_math_max_mixed, r#"fn main(){return max(1, 2.5);}"#, Ok(Value::Float(2.5)));
+test_program!(interpreter_math_pow, r#"fn main(){return pow(2, 10);}"#, Ok(Value::Number(1024)));
+test_program!(interpreter_math_pow_overflow, r#"fn main(){return pow(2, 31);}"#, Err(AsaErrorKind::NumberOverflow));
+test_program!(interpreter_math_pow_underflow, r#"fn main(){return pow(0 - 3, 21);}"#, Err(AsaErrorKind::NumberUnderflow));
+test_program!(interpreter_math_pow_negative_exponent, r#"fn main(){return pow(2, 0 - 1);}"#, Err(AsaErrorKind::type_error("pow", "a non-negative exponent", "-1")));
+test_program!(interpreter_math_sqrt, r#"fn main(){return sqrt(16);}"#, Ok(Value::Float(4.0)));
+test_program!(interpreter_math_sqrt_negative, r#"fn main(){return sqrt(0 - 1);}"#, Err(AsaErrorKind::type_error("sqrt", "a non-negative number", "-1")));
+test_program!(interpreter_math_floor_ceil_round, r#"fn main(){let x = floor(2.7) + ceil(2.2); return x + round(2.5);}"#, Ok(Value::Number(8)));
+test_program!(interpreter_math_round_overflow, r#"fn main(){return round(pow(10.0, 12));}"#, Err(AsaErrorKind::NumberOverflow));
+test_program!(interpreter_math_clamp, r#"fn main(){return clamp(15, 0, 10) + clamp(0 - 5, 0, 10);}"#, Ok(Value::Number(10)));
+test_program!(interpreter_math_clamp_bad_range, r#"fn main(){return clamp(5, 10, 0);}"#, Err(AsaErrorKind::type_error("clamp", "a low bound not above the high bound", "10 > 0")));
+test_program!(interpreter_math_gcd, r#"fn main(){return gcd(48, 0 - 18);}"#, Ok(Value::Number(6)));
+test_program!(interpreter_math_trig, r#"fn main(){return sin(0) + cos(0);}"#, Ok(Value::Float(1.0)));
+test_program!(interpreter_math_asin_domain, r#"fn main(){return asin(2);}"#, Err(AsaErrorKind::type_error("asin", "arguments with a defined result", "NaN")));
+
+// Test file built-ins and their sandbox
+fn scratch_dir(name: &str) -> std::path::PathBuf {
+  let dir = std::env::temp_dir().join(format!("asa-files-{}-{}", name, std::process::id()));
+  std::fs::create_dir_all(&dir).unwrap();
+  dir
+}
+
+fn run_with_files(source: &str, allowed: Option<&std::path::Path>) -> Result<Value,AsaErrorKind> {
+  let (_, tree) = program(lex(source)).map_err(|e| AsaErrorKind::Generic(format!("{:?}",e)))?;
+  let mut interpreter = Interpreter::new();
+  if let Some(dir) = allowed {
+    interpreter.allow_dir(dir)?;
+  }
+  interpreter.exec(&tree)?;
+  interpreter.start_main(vec![])
+}
+
+#[test]
+fn interpreter_files_write_and_read() {
+  let dir = scratch_dir("rw");
+  let source = format!(r#"fn main(){{let path = "{}/report.txt"; write_file(path, "a\nb"); return read_lines(path);}}"#, dir.display());
+  let result = run_with_files(&source, Some(&dir));
+  assert_eq!(result, Ok(Value::List(vec![Value::String("a".to_string()), Value::String("b".to_string())])));
+  assert_eq!(std::fs::read_to_string(dir.join("report.txt")).unwrap(), "a\nb");
+  std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn interpreter_files_exists() {
+  let dir = scratch_dir("exists");
+  std::fs::write(dir.join("config.txt"), "x").unwrap();
+  let source = format!(r#"fn main(){{let a = exists("{0}/config.txt"); let b = exists("{0}/missing.txt"); return format("{{}} {{}}", a, b);}}"#, dir.display());
+  assert_eq!(run_with_files(&source, Some(&dir)), Ok(Value::String("true false".to_string())));
+  std::fs::remove_dir_all(dir).unwrap();
+}
+
+// A path below a missing directory simply doesn't exist, but one outside the sandbox is still denied.
+#[test]
+fn interpreter_files_exists_missing_directory() {
+  let dir = scratch_dir("exists-missing");
+  let inner = dir.join("inner");
+  std::fs::create_dir_all(&inner).unwrap();
+  let source = format!(r#"fn main(){{return exists("{}/missing/file.txt");}}"#, inner.display());
+  assert_eq!(run_with_files(&source, Some(&inner)), Ok(Value::Bool(false)));
+  let path = format!("{}/missing/file.txt", dir.display());
+  let source = format!(r#"fn main(){{return exists("{}");}}"#, path);
+  assert_eq!(run_with_files(&source, Some(&inner)), Err(AsaErrorKind::PermissionDenied(path)));
+  let path = format!("{}/missing/../../file.txt", inner.display());
+  let source = format!(r#"fn main(){{return exists("{}");}}"#, path);
+  assert_eq!(run_with_files(&source, Some(&inner)), Err(AsaErrorKind::PermissionDenied(path)));
+  std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn interpreter_files_no_escape_through_dangling_symlink() {
+  let dir = scratch_dir("dangling");
+  let sandbox = dir.join("sandbox");
+  std::fs::create_dir_all(&sandbox).unwrap();
+  std::os::unix::fs::symlink(dir.join("escaped.txt"), sandbox.join("link")).unwrap();
+  let path = format!("{}/link", sandbox.display());
+  let source = format!(r#"fn main(){{return write_file("{}", "pwned");}}"#, path);
+  assert_eq!(run_with_files(&source, Some(&sandbox)), Err(AsaErrorKind::PermissionDenied(path)));
+  assert!(!dir.join("escaped.txt").exists());
+  std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn interpreter_files_denied_by_default() {
+  let dir = scratch_dir("denied");
+  std::fs::write(dir.join("config.txt"), "x").unwrap();
+  let path = format!("{}/config.txt", dir.display());
+  let source = format!(r#"fn main(){{return read_file("{}");}}"#, path);
+  assert_eq!(run_with_files(&source, None), Err(AsaErrorKind::PermissionDenied(path)));
+  std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn interpreter_files_no_escape() {
+  let dir = scratch_dir("escape");
+  let inner = dir.join("inner");
+  std::fs::create_dir_all(&inner).unwrap();
+  std::fs::write(dir.join("secret.txt"), "x").unwrap();
+  let path = format!("{}/../secret.txt", inner.display());
+  let source = format!(r#"fn main(){{return read_file("{}");}}"#, path);
+  assert_eq!(run_with_files(&source, Some(&inner)), Err(AsaErrorKind::PermissionDenied(path)));
+  std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn interpreter_files_missing_file() {
+  let dir = scratch_dir("missing");
+  let source = format!(r#"fn main(){{return read_file("{}/nope.txt");}}"#, dir.display());
+  assert!(matches!(run_with_files(&source, Some(&dir)), Err(AsaErrorKind::IoError(_))));
+  std::fs::remove_dir_all(dir).unwrap();
+}
+
+// A program with syntax errors doesn't run, not even the parts before the first error.
+test_fragment!(interpreter_syntax_error, r#"println("ran"); let = 2;"#, Err(AsaErrorKind::SyntaxError(vec![
+  SyntaxError{message: "expected an identifier, found '='".to_string(), span: Span{start_line: 1, start_col: 21, end_line: 1, end_col: 21}},
+])));
+
+// A failed call leaves none of its frames behind, and the next call starts a fresh backtrace.
+#[test]
+fn interpreter_error_unwinds_stack() {
+  let mut interpreter = Interpreter::new();
+  let (_, tree) = program(lex(r#"fn f(a){let x = a; return g(x);} fn g(b){let y = 2; return b - true;} fn h(){return 1;}"#)).unwrap();
+  interpreter.exec(&tree).unwrap();
+  assert!(interpreter.call::<_, Value>("f", (1,)).is_err());
+  let names: Vec<&str> = interpreter.backtrace().iter().map(|call| call.function.as_str()).collect();
+  assert_eq!(names, ["g", "f"]);
+  for name in ["x", "y", "a"] {
+    let (_, tree) = program(lex(name)).unwrap();
+    assert_eq!(interpreter.exec(&tree), Err(AsaErrorKind::UndefinedVariable(name.to_string())));
+  }
+  assert_eq!(interpreter.call::<_, i32>("h", ()), Ok(1));
+  assert!(interpreter.backtrace().is_empty());
+}
+
+// Test throw, try, catch and finally
+#[test]
+fn interpreter_catch_thrown_value() {
+  assert_eq!(run_native_program(r#"fn main(){try {throw 41; return 0;} catch e {return e + 1;}}"#), Ok(Value::Number(42)));
+}
+
+#[test]
+fn interpreter_catch_builtin_errors() {
+  let caught = |body: &str| run_native_program(&format!("fn main(){{try {{{}}} catch e {{return format(\"{{}}: {{}}\", e.kind, e.message);}}}}", body));
+  assert_eq!(caught("return y;"), Ok(Value::String("UndefinedVariable: undefined variable 'y'".to_string())));
+  assert_eq!(caught("return 1 - double(true);"), Ok(Value::String("TypeError: 'double' expects num, found bool".to_string())));
+  assert_eq!(caught("return divide(1, 0);"), Ok(Value::String("DivisionByZero: division by zero".to_string())));
+}
+
+#[test]
+fn interpreter_catch_from_called_function() {
+  let source = r#"fn fail(x){throw format("bad {}", x);} fn main(){try {fail(3);} catch e {return e;}}"#;
+  assert_eq!(run_native_program(source), Ok(Value::String("bad 3".to_string())));
+}
+
+#[test]
+fn interpreter_finally_runs() {
+  let (result, output) = run_captured(r#"fn main(){try {print("a");} catch e {print("b");} finally {print("c");} try {throw 1;} catch e {print("d");} finally {print("e");} return 0;}"#);
+  assert_eq!(result, Ok(Value::Number(0)));
+  assert_eq!(output, "acde");
+}
+
+#[test]
+fn interpreter_finally_runs_on_error_from_catch() {
+  let (result, output) = run_captured(r#"fn main(){try {throw 1;} catch e {throw e + 1;} finally {print("done");} return 0;}"#);
+  assert_eq!(result, Err(AsaErrorKind::Thrown(Value::Number(2))));
+  assert_eq!(output, "done");
+}
+
+// The catch variable is only bound up to the end of the try statement
+#[test]
+fn interpreter_catch_variable_restored() {
+  let (result, output) = run_captured(r#"fn main(){let e = 1; try {throw 2;} catch e {print(e);} finally {print(e);} return e;}"#);
+  assert_eq!(result, Ok(Value::Number(1)));
+  assert_eq!(output, "22");
+  assert_eq!(run_native_program(r#"fn main(){try {throw 2;} catch e {} return e;}"#), Err(AsaErrorKind::UndefinedVariable("e".to_string())));
+  let (result, output) = run_captured(r#"fn main(){let e = 1; try {throw 2;} catch e {throw e + 1;} finally {print(e);} return e;}"#);
+  assert_eq!(result, Err(AsaErrorKind::Thrown(Value::Number(3))));
+  assert_eq!(output, "2");
+}
+
+#[test]
+fn interpreter_field_access() {
+  let caught = |field: &str| run_native_program(&format!("fn main(){{try {{return y;}} catch e {{return {};}}}}", field));
+  assert_eq!(caught("e.code"), Ok(Value::String("E0016".to_string())));
+  assert_eq!(caught("e.nope"), Err(AsaErrorKind::UndefinedVariable("e.nope".to_string())));
+  assert_eq!(caught("e.kind.name"), Err(AsaErrorKind::type_error(".", "map", "str")));
+  assert_eq!(run_native_program(r#"fn main(){let x = 1; return x.kind;}"#), Err(AsaErrorKind::type_error(".", "map", "num")));
+  assert_eq!(run_native_program(r#"fn main(){return x.kind;}"#), Err(AsaErrorKind::UndefinedVariable("x.kind".to_string())));
+}
+
+#[test]
+fn interpreter_uncaught_throw() {
+  assert_eq!(run_native_program(r#"fn main(){throw "oops";}"#), Err(AsaErrorKind::Thrown(Value::String("oops".to_string()))));
+}
+
+#[test]
+fn interpreter_return_outside_function() {
+  let (_, tree) = program(lex(r#"let x = 1; return x;"#)).unwrap();
+  let mut interpreter = Interpreter::new();
+  assert_eq!(interpreter.exec(&tree), Err(AsaErrorKind::Generic("'return' outside of a function".to_string())));
+}
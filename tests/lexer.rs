@@ -1,7 +1,7 @@
 use asalang::*;
 
 fn test_lex(input: &str) -> Vec<TokenKind> {
-  lex(input).tokens.iter().map(|t| t.get_kind()).collect::<Vec<TokenKind>>()
+  lex(input).tokens().iter().map(|t| t.get_kind()).collect::<Vec<TokenKind>>()
 }
 
 #[test]
@@ -130,3 +130,116 @@ fn lexer_test_09() {
     TokenKind::EOF,
   ]);
 }
+
+#[test]
+fn lexer_test_string_text() {
+  assert_eq!(test_lex(r#""a b{}""#),vec![
+    TokenKind::Quote,
+    TokenKind::Alpha,
+    TokenKind::Text,
+    TokenKind::Alpha,
+    TokenKind::Text,
+    TokenKind::Text,
+    TokenKind::Quote,
+    TokenKind::EOF,
+  ]);
+}
+
+#[test]
+fn lexer_test_string_keywords() {
+  assert_eq!(test_lex(r#""fn true""#),vec![
+    TokenKind::Quote,
+    TokenKind::Alpha,
+    TokenKind::Alpha,
+    TokenKind::Text,
+    TokenKind::Alpha,
+    TokenKind::Alpha,
+    TokenKind::Alpha,
+    TokenKind::Alpha,
+    TokenKind::Quote,
+    TokenKind::EOF,
+  ]);
+}
+
+#[test]
+fn lexer_test_columns_after_keywords() {
+  let tokens = lex("let x = 1;\nreturn x;");
+  let tokens = tokens.tokens();
+  assert_eq!(tokens[0].span().to_string(), "1:1");
+  assert_eq!((tokens[0].start_col, tokens[0].end_col), (1, 3));
+  assert_eq!(tokens[1].span().to_string(), "1:5");
+  assert_eq!(tokens[5].span().to_string(), "2:1");
+  assert_eq!(tokens[6].span().to_string(), "2:8");
+}
+
+#[test]
+fn lexer_iterator_skips_whitespace() {
+  let kinds: Vec<TokenKind> = Lexer::new("let x\t=\r\n1;").map(|token| token.unwrap().kind).collect();
+  assert_eq!(kinds, vec![TokenKind::Let, TokenKind::Alpha, TokenKind::Equal, TokenKind::Digit, TokenKind::Semicolon, TokenKind::EOF]);
+}
+
+#[test]
+fn lexer_iterator_reports_invalid_characters() {
+  let results: Vec<Result<Token, LexError>> = Lexer::new("a ? é").collect();
+  assert_eq!(results.len(), 4);
+  assert_eq!(results[1], Err(LexError{found: '?', line: 1, col: 3}));
+  assert_eq!(results[2], Err(LexError{found: 'é', line: 1, col: 5}));
+  assert_eq!(results[2].as_ref().unwrap_err().to_string(), "unexpected character 'é' at 1:5");
+  assert_eq!(results[3].as_ref().unwrap().kind, TokenKind::EOF);
+}
+
+#[test]
+fn lexer_iterator_can_stop_early() {
+  let mut lexer = Lexer::new("fn main(){return 1;}");
+  assert_eq!(lexer.next().unwrap().unwrap().lexeme, b"fn".to_vec());
+  assert_eq!(lexer.next().unwrap().unwrap().kind, TokenKind::Alpha);
+}
+
+#[test]
+fn lexer_keeps_invalid_characters_for_the_parser() {
+  assert_eq!(test_lex("1 ? 2"), vec![TokenKind::Digit, TokenKind::Other, TokenKind::Digit, TokenKind::EOF]);
+}
+
+#[test]
+fn lexer_lossless_keeps_trivia() {
+  let kinds: Vec<TokenKind> = Lexer::lossless("x\t// note\n y").map(|token| token.unwrap().kind).collect();
+  assert_eq!(kinds, vec![TokenKind::Alpha, TokenKind::Tab, TokenKind::Comment, TokenKind::NewLine, TokenKind::WhiteSpace, TokenKind::Alpha, TokenKind::EOF]);
+}
+
+#[test]
+fn lexer_error_handling_keywords() {
+  assert_eq!(test_lex("try catch finally throw"), vec![TokenKind::Try, TokenKind::Catch, TokenKind::Finally, TokenKind::Throw, TokenKind::EOF]);
+}
+
+#[test]
+fn lexer_import_needs_word_boundary() {
+  assert_eq!(test_lex("import x")[0], TokenKind::Import);
+  assert_eq!(test_lex("important")[0], TokenKind::Alpha);
+  assert_eq!(test_lex("import_x")[0], TokenKind::Alpha);
+  assert!(!test_lex("reimport x").contains(&TokenKind::Import));
+}
+
+#[test]
+fn lexer_pub_needs_word_boundary() {
+  assert_eq!(test_lex("pub fn")[0], TokenKind::Pub);
+  assert_eq!(test_lex("public")[0], TokenKind::Alpha);
+  assert_eq!(test_lex("pub_x")[0], TokenKind::Alpha);
+  assert!(!test_lex("epub = 3").contains(&TokenKind::Pub));
+}
+
+#[test]
+fn lexer_const_needs_word_boundary() {
+  assert_eq!(test_lex("const A")[0], TokenKind::Const);
+  assert_eq!(test_lex("constant")[0], TokenKind::Alpha);
+  assert!(!test_lex("myconst = 3").contains(&TokenKind::Const));
+}
+
+#[test]
+fn lexer_error_handling_keywords_need_word_boundary() {
+  for name in ["tryCount", "catches", "finally_done", "thrower"] {
+    assert_eq!(test_lex(name)[0], TokenKind::Alpha, "{}", name);
+  }
+  for name in ["entry", "retry", "overthrow", "country", "registry"] {
+    assert!(test_lex(name).iter().all(|kind| matches!(kind, TokenKind::Alpha | TokenKind::EOF)), "{}", name);
+  }
+}
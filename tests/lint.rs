@@ -0,0 +1,82 @@
+use asalang::*;
+
+// Lints a source with the default rules, returning each lint as its location and rule.
+fn lint_source(source: &str) -> Vec<(String, Rule)> {
+  lint(source, &LintConfig::default()).unwrap().into_iter().map(|lint| (lint.span.to_string(), lint.rule)).collect()
+}
+
+#[test]
+fn lint_clean_program() {
+  assert_eq!(lint_source("const LIMIT = 2;\nfn add_one(a) {\n  let b = a + 1;\n  return b;\n}"), vec![]);
+}
+
+#[test]
+fn lint_unused_variable_and_parameter() {
+  assert_eq!(lint_source("fn f(a, b, _c) {\n  let x = 1;\n  let _y = 2;\n  return a;\n}"), vec![
+    ("1:9".to_string(), Rule::UnusedParameter),
+    ("2:7".to_string(), Rule::UnusedVariable),
+  ]);
+}
+
+#[test]
+fn lint_shadowing() {
+  assert_eq!(lint_source("fn f(a) {\n  let a = a + 1;\n  let b = a;\n  let b = b;\n  return b;\n}"), vec![
+    ("2:7".to_string(), Rule::Shadowing),
+    ("4:7".to_string(), Rule::Shadowing),
+  ]);
+}
+
+#[test]
+fn lint_constant_condition() {
+  assert_eq!(lint_source("fn f(a) {\n  return if true {return 1;} else {return a;};\n}\nfn g(a) {\n  return if 1 > 2 {return 1;} else {return a;};\n}"), vec![
+    ("2:10".to_string(), Rule::ConstantCondition),
+    ("5:15".to_string(), Rule::ConstantCondition),
+  ]);
+}
+
+#[test]
+fn lint_type_comparison() {
+  assert_eq!(lint_source("fn f(a) {\n  let s = \"a\" + \"b\";\n  let n = a - 1;\n  return n == s;\n}"), vec![
+    ("4:12".to_string(), Rule::TypeComparison),
+  ]);
+}
+
+#[test]
+fn lint_missing_return() {
+  assert_eq!(lint_source("fn f(a) {\n  return a;\n  let b = a;\n}"), vec![
+    ("1:4".to_string(), Rule::MissingReturn),
+    ("3:7".to_string(), Rule::UnusedVariable),
+  ]);
+}
+
+#[test]
+fn lint_snake_case() {
+  assert_eq!(lint_source("fn addOne(myArg) {\n  let Total = myArg + 1;\n  return Total;\n}"), vec![
+    ("1:4".to_string(), Rule::SnakeCase),
+    ("1:11".to_string(), Rule::SnakeCase),
+    ("2:7".to_string(), Rule::SnakeCase),
+  ]);
+}
+
+#[test]
+fn lint_pragma() {
+  assert_eq!(lint_source("fn f(a) {\n  // lint: allow(unused-variable)\n  let x = 1;\n  let y = 2; // lint: allow(shadowing, unused-variable)\n  return a;\n}"), vec![]);
+}
+
+#[test]
+fn lint_config() {
+  let mut config = LintConfig::default();
+  config.set(Rule::UnusedVariable, Some(Severity::Error));
+  config.set(Rule::MissingReturn, None);
+  let lints = lint("fn f() {\n  let x = 1;\n}", &config).unwrap();
+  assert_eq!(lints.len(), 1);
+  assert_eq!(lints[0].to_string(), "2:7: error[unused-variable]: unused variable 'x'");
+}
+
+#[test]
+fn lint_rule_ids() {
+  for rule in Rule::ALL {
+    assert_eq!(Rule::from_id(rule.id()), Some(rule));
+  }
+  assert_eq!(Rule::from_id("nope"), None);
+}
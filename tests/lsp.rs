@@ -0,0 +1,179 @@
+use std::io::{Cursor, Write};
+use std::process::{Command, Stdio};
+use asalang::lsp::*;
+use serde_json::{json, Value};
+
+const URI: &str = "file:///main.asa";
+
+const SOURCE: &str = "fn add(a: num, b: num) {\n  let c = a + b;\n  return c;\n}\nfn main() {\n  return add(1, 2);\n}";
+
+// A server with SOURCE open.
+fn open(source: &str) -> (Server, Vec<Value>) {
+  let mut server = Server::new();
+  let replies = server.handle(&json!({"jsonrpc": "2.0", "method": "textDocument/didOpen", "params": {
+    "textDocument": {"uri": URI, "languageId": "asa", "version": 1, "text": source},
+  }}));
+  (server, replies)
+}
+
+// Sends a request about a position in the document and returns its result.
+fn request(server: &mut Server, method: &str, line: u32, character: u32) -> Value {
+  let replies = server.handle(&json!({"jsonrpc": "2.0", "id": 1, "method": method, "params": {
+    "textDocument": {"uri": URI},
+    "position": {"line": line, "character": character},
+  }}));
+  assert_eq!(replies.len(), 1);
+  replies[0]["result"].clone()
+}
+
+fn range(line: u32, start: u32, end: u32) -> Value {
+  json!({"start": {"line": line, "character": start}, "end": {"line": line, "character": end}})
+}
+
+#[test]
+fn lsp_initialize() {
+  let mut server = Server::new();
+  let replies = server.handle(&json!({"jsonrpc": "2.0", "id": 0, "method": "initialize", "params": {}}));
+  let capabilities = &replies[0]["result"]["capabilities"];
+  assert_eq!(replies[0]["id"], 0);
+  assert_eq!(capabilities["textDocumentSync"], 1);
+  assert_eq!(capabilities["hoverProvider"], true);
+  assert_eq!(capabilities["definitionProvider"], true);
+  assert_eq!(capabilities["documentSymbolProvider"], true);
+}
+
+#[test]
+fn lsp_diagnostics_on_change() {
+  let (mut server, replies) = open(SOURCE);
+  assert_eq!(replies[0]["method"], "textDocument/publishDiagnostics");
+  assert_eq!(replies[0]["params"]["diagnostics"], json!([]));
+  let replies = server.handle(&json!({"jsonrpc": "2.0", "method": "textDocument/didChange", "params": {
+    "textDocument": {"uri": URI, "version": 2},
+    "contentChanges": [{"text": "fn main() {\n  return foo();\n}"}],
+  }}));
+  let diagnostics = &replies[0]["params"]["diagnostics"];
+  assert_eq!(diagnostics[0]["range"], range(1, 9, 12));
+  assert_eq!(diagnostics[0]["severity"], 1);
+  assert_eq!(diagnostics[0]["message"], "undefined function 'foo'");
+}
+
+#[test]
+fn lsp_syntax_error() {
+  let (_, replies) = open("fn main() {\n  let = 1;\n  return 1;\n}\n)");
+  let diagnostics = &replies[0]["params"]["diagnostics"];
+  assert_eq!(diagnostics.as_array().unwrap().len(), 2);
+  assert_eq!(diagnostics[0]["range"], range(1, 6, 7));
+  assert_eq!(diagnostics[1]["range"], range(4, 0, 1));
+}
+
+// A number too big for a literal, as typed on the way to a smaller one, is a diagnostic.
+#[test]
+fn lsp_number_out_of_range() {
+  let (_, replies) = open("fn main() {\n  let x = 99999999999;\n  return x;\n}");
+  let diagnostics = &replies[0]["params"]["diagnostics"];
+  assert_eq!(diagnostics.as_array().unwrap().len(), 1);
+  assert_eq!(diagnostics[0]["range"], range(1, 10, 11));
+  assert_eq!(diagnostics[0]["message"], "number literal out of range");
+}
+
+#[test]
+fn lsp_definition() {
+  let (mut server, _) = open(SOURCE);
+  // add in add(1, 2)
+  assert_eq!(request(&mut server, "textDocument/definition", 5, 10), json!({"uri": URI, "range": range(0, 3, 6)}));
+  // c in return c
+  assert_eq!(request(&mut server, "textDocument/definition", 2, 9), json!({"uri": URI, "range": range(1, 6, 7)}));
+  // a in a + b
+  assert_eq!(request(&mut server, "textDocument/definition", 1, 10), json!({"uri": URI, "range": range(0, 7, 8)}));
+  assert_eq!(request(&mut server, "textDocument/definition", 3, 0), Value::Null);
+}
+
+#[test]
+fn lsp_hover() {
+  let (mut server, _) = open(SOURCE);
+  assert_eq!(request(&mut server, "textDocument/hover", 2, 10)["contents"]["value"], "c: num");
+  assert_eq!(request(&mut server, "textDocument/hover", 5, 9)["contents"]["value"], "add: fn(num, num) -> num");
+}
+
+#[test]
+fn lsp_document_symbols() {
+  let (mut server, _) = open(SOURCE);
+  let symbols = request(&mut server, "textDocument/documentSymbol", 0, 0);
+  let names: Vec<&Value> = symbols.as_array().unwrap().iter().map(|symbol| &symbol["name"]).collect();
+  assert_eq!(names, vec!["add", "main"]);
+  assert_eq!(symbols[0]["detail"], "fn(num, num) -> num");
+  assert_eq!(symbols[0]["range"], json!({"start": {"line": 0, "character": 3}, "end": {"line": 3, "character": 1}}));
+  assert_eq!(symbols[1]["selectionRange"], range(4, 3, 7));
+}
+
+#[test]
+fn lsp_completion() {
+  let (mut server, _) = open(SOURCE);
+  let labels = |items: Value| -> Vec<String> {
+    items.as_array().unwrap().iter().map(|item| item["label"].as_str().unwrap().to_string()).collect()
+  };
+  let in_add = labels(request(&mut server, "textDocument/completion", 2, 2));
+  assert_eq!(in_add[..3], ["a", "b", "c"]);
+  assert!(in_add.iter().any(|label| label == "main"));
+  assert!(in_add.iter().any(|label| label == "println"));
+  assert!(in_add.iter().any(|label| label == "return"));
+  // The lets of add are not in scope in main
+  let in_main = labels(request(&mut server, "textDocument/completion", 5, 2));
+  assert!(!in_main.iter().any(|label| label == "c"));
+}
+
+#[test]
+fn lsp_unknown_request() {
+  let mut server = Server::new();
+  let replies = server.handle(&json!({"jsonrpc": "2.0", "id": 3, "method": "workspace/symbol", "params": {}}));
+  assert_eq!(replies[0]["error"]["code"], -32601);
+  assert_eq!(server.handle(&json!({"jsonrpc": "2.0", "method": "initialized", "params": {}})), Vec::<Value>::new());
+}
+
+#[test]
+fn lsp_serve_until_exit() {
+  let mut input = Vec::new();
+  for message in [
+    json!({"jsonrpc": "2.0", "id": 0, "method": "initialize", "params": {}}),
+    json!({"jsonrpc": "2.0", "id": 1, "method": "shutdown"}),
+    json!({"jsonrpc": "2.0", "method": "exit"}),
+  ] {
+    write_message(&mut input, &message).unwrap();
+  }
+  let mut output = Vec::new();
+  assert_eq!(serve(Cursor::new(input), &mut output).unwrap(), 0);
+  let mut output = Cursor::new(output);
+  assert_eq!(read_message(&mut output).unwrap().unwrap()["id"], 0);
+  assert_eq!(read_message(&mut output).unwrap().unwrap(), json!({"jsonrpc": "2.0", "id": 1, "result": null}));
+  assert_eq!(read_message(&mut output).unwrap(), None);
+}
+
+// A body that isn't JSON gets a parse error, and the server keeps serving.
+#[test]
+fn lsp_serve_bad_json() {
+  let mut input = b"Content-Length: 8\r\n\r\n{\"id\": 1".to_vec();
+  write_message(&mut input, &json!({"jsonrpc": "2.0", "id": 2, "method": "shutdown"})).unwrap();
+  write_message(&mut input, &json!({"jsonrpc": "2.0", "method": "exit"})).unwrap();
+  let mut output = Vec::new();
+  assert_eq!(serve(Cursor::new(input), &mut output).unwrap(), 0);
+  let mut output = Cursor::new(output);
+  let error = read_message(&mut output).unwrap().unwrap();
+  assert_eq!(error["id"], Value::Null);
+  assert_eq!(error["error"]["code"], -32700);
+  assert_eq!(read_message(&mut output).unwrap().unwrap(), json!({"jsonrpc": "2.0", "id": 2, "result": null}));
+  assert_eq!(read_message(&mut output).unwrap(), None);
+}
+
+#[test]
+fn lsp_binary() {
+  let mut child = Command::new(env!("CARGO_BIN_EXE_asa-lsp")).stdin(Stdio::piped()).stdout(Stdio::piped()).spawn().unwrap();
+  let mut stdin = child.stdin.take().unwrap();
+  write_message(&mut stdin, &json!({"jsonrpc": "2.0", "id": 0, "method": "initialize", "params": {}})).unwrap();
+  write_message(&mut stdin, &json!({"jsonrpc": "2.0", "method": "exit"})).unwrap();
+  stdin.flush().unwrap();
+  let output = child.wait_with_output().unwrap();
+  // exit without shutdown
+  assert_eq!(output.status.code(), Some(1));
+  let reply = read_message(&mut Cursor::new(output.stdout)).unwrap().unwrap();
+  assert_eq!(reply["result"]["serverInfo"]["name"], "asa-lsp");
+}
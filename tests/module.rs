@@ -0,0 +1,247 @@
+extern crate asalang;
+
+use std::cell::RefCell;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use asalang::*;
+
+// Creates a scratch directory holding the given files and returns its path.
+fn project(name: &str, files: &[(&str, &str)]) -> PathBuf {
+  let dir = std::env::temp_dir().join(format!("asa-module-{}-{}", name, std::process::id()));
+  for (file, source) in files {
+    let path = dir.join(file);
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(path, source).unwrap();
+  }
+  dir
+}
+
+fn run(dir: &Path) -> Result<Value,AsaErrorKind> {
+  let mut interpreter = Interpreter::new();
+  interpreter.load_file(dir.join("main.asa"))?;
+  interpreter.start_main(vec![])
+}
+
+#[derive(Clone, Default)]
+struct Capture(Rc<RefCell<Vec<u8>>>);
+
+impl Write for Capture {
+  fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+    self.0.borrow_mut().write(buf)
+  }
+  fn flush(&mut self) -> std::io::Result<()> {
+    Ok(())
+  }
+}
+
+#[test]
+fn module_namespaced_call() {
+  let dir = project("call", &[
+    ("main.asa", r#"import "util.asa"; fn main(){return util.helper(2);}"#),
+    ("util.asa", r#"pub fn helper(a){return twice(a);} fn twice(a){return a + a;}"#),
+  ]);
+  assert_eq!(run(&dir), Ok(Value::Number(4)));
+  fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn module_functions_stay_in_namespace() {
+  let dir = project("namespace", &[
+    ("main.asa", r#"import "util.asa"; fn twice(a){return 0;} fn main(){return util.helper(2) + twice(2);}"#),
+    ("util.asa", r#"pub fn helper(a){return twice(a);} fn twice(a){return a + a;}"#),
+  ]);
+  assert_eq!(run(&dir), Ok(Value::Number(4)));
+  fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn module_unqualified_call_is_undefined() {
+  let dir = project("unqualified", &[
+    ("main.asa", r#"import "util.asa"; fn main(){return helper(2);}"#),
+    ("util.asa", r#"pub fn helper(a){return a;}"#),
+  ]);
+  assert_eq!(run(&dir), Err(AsaErrorKind::UndefinedFunction("helper".to_string())));
+  fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn module_relative_to_importing_file() {
+  let dir = project("relative", &[
+    ("main.asa", r#"import "lib/util.asa"; fn main(){return util.helper();}"#),
+    ("lib/util.asa", r#"import "numbers.asa"; pub fn helper(){return numbers.seven();}"#),
+    ("lib/numbers.asa", r#"pub fn seven(){return 7;}"#),
+  ]);
+  assert_eq!(run(&dir), Ok(Value::Number(7)));
+  fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn module_loaded_once() {
+  let dir = project("once", &[
+    ("main.asa", r#"import "a.asa"; import "b.asa"; fn main(){return b.value();}"#),
+    ("a.asa", r#"import "b.asa"; fn value(){return 1;}"#),
+    ("b.asa", r#"println("loading b"); pub fn value(){return 2;}"#),
+  ]);
+  let capture = Capture::default();
+  let mut interpreter = Interpreter::new();
+  interpreter.set_output(capture.clone());
+  interpreter.load_file(dir.join("main.asa")).unwrap();
+  assert_eq!(interpreter.start_main(vec![]), Ok(Value::Number(2)));
+  assert_eq!(String::from_utf8(capture.0.borrow().clone()).unwrap(), "loading b\n");
+  fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn module_import_cycle() {
+  let dir = project("cycle", &[
+    ("main.asa", r#"import "a.asa"; fn main(){return 1;}"#),
+    ("a.asa", r#"import "b.asa"; fn f(){return 1;}"#),
+    ("b.asa", r#"import "a.asa"; fn g(){return 1;}"#),
+  ]);
+  assert_eq!(run(&dir), Err(AsaErrorKind::ModuleError{
+    chain: vec!["main.asa".to_string(), "a.asa".to_string(), "b.asa".to_string(), "a.asa".to_string()],
+    message: "import cycle".to_string(),
+  }));
+  fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn module_namespace_collision() {
+  let dir = project("collision", &[
+    ("main.asa", r#"import "a/util.asa"; import "b/util.asa"; fn main(){return util.f();}"#),
+    ("a/util.asa", "pub fn f(){return 1;}"),
+    ("b/util.asa", "pub fn f(){return 2;}"),
+  ]);
+  match run(&dir) {
+    Err(AsaErrorKind::ModuleError{chain, message}) => {
+      assert_eq!(chain, vec!["main.asa", "util.asa"]);
+      let first = dir.join("a/util.asa").canonicalize().unwrap();
+      assert_eq!(message, format!("namespace 'util' is already used by {}", first.display()));
+    },
+    other => panic!("unexpected result {:?}", other),
+  }
+  fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn module_missing_file() {
+  let dir = project("missing", &[
+    ("main.asa", r#"import "util.asa"; fn main(){return 1;}"#),
+    ("util.asa", r#"import "nope.asa"; fn f(){return 1;}"#),
+  ]);
+  match run(&dir) {
+    Err(AsaErrorKind::ModuleError{chain, ..}) => assert_eq!(chain, vec!["main.asa", "util.asa", "nope.asa"]),
+    other => panic!("unexpected result {:?}", other),
+  }
+  fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn module_parse_error() {
+  let dir = project("parse", &[
+    ("main.asa", r#"import "util.asa"; fn main(){return 1;}"#),
+    ("util.asa", "fn f(){return 1;}\n)"),
+  ]);
+  match run(&dir) {
+    Err(AsaErrorKind::ModuleError{chain, message}) => {
+      assert_eq!(chain, vec!["main.asa", "util.asa"]);
+      assert_eq!(message, "expected a definition or statement, found ')' at 2:1");
+    },
+    other => panic!("unexpected result {:?}", other),
+  }
+  fs::remove_dir_all(dir).unwrap();
+}
+
+// A run-time error in an imported file's top-level code is reported with the import chain, and the
+// module's definitions and namespace are dropped.
+#[test]
+fn module_runtime_error() {
+  let dir = project("runtime", &[
+    ("main.asa", r#"import "lib/util.asa"; fn main(){return 1;}"#),
+    ("lib/util.asa", r#"pub fn f(){return 1;} pub const A = 1; let x = zz;"#),
+    ("other.asa", r#"import "util.asa"; fn main(){return util.g();}"#),
+    ("util.asa", r#"pub fn g(){return 2;}"#),
+  ]);
+  let mut interpreter = Interpreter::new();
+  assert_eq!(interpreter.load_file(dir.join("main.asa")), Err(AsaErrorKind::ModuleError{
+    chain: vec!["main.asa".to_string(), "util.asa".to_string()],
+    message: "undefined variable 'zz'".to_string(),
+  }));
+  assert!(!interpreter.has_function("util.f"));
+  let (_, tree) = program(lex("util.A")).unwrap();
+  assert_eq!(interpreter.exec(&tree), Err(AsaErrorKind::UndefinedVariable("util.A".to_string())));
+  interpreter.load_file(dir.join("other.asa")).unwrap();
+  assert_eq!(interpreter.start_main(vec![]), Ok(Value::Number(2)));
+  fs::remove_dir_all(dir).unwrap();
+}
+
+// The file being loaded reports its syntax errors one by one, without an import chain.
+#[test]
+fn module_main_file_parse_error() {
+  let dir = project("main-parse", &[("main.asa", "fn main(){return 1;}\nlet = 2;\n)")]);
+  match run(&dir) {
+    Err(AsaErrorKind::SyntaxError(errors)) => {
+      let errors: Vec<String> = errors.iter().map(SyntaxError::to_string).collect();
+      assert_eq!(errors, vec!["expected an identifier, found '=' at 2:5", "expected a definition or statement, found ')' at 3:1"]);
+    },
+    other => panic!("unexpected result {:?}", other),
+  }
+  fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn module_call_from_host() {
+  let dir = project("host", &[
+    ("main.asa", r#"import "util.asa"; fn main(){return 0;}"#),
+    ("util.asa", r#"fn add(a,b){return a+b;}"#),
+  ]);
+  let mut interpreter = Interpreter::new();
+  interpreter.load_file(dir.join("main.asa")).unwrap();
+  let n: i32 = interpreter.call("util.add", (2, 3)).unwrap();
+  assert_eq!(n, 5);
+  fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn module_private_function() {
+  let dir = project("private", &[
+    ("main.asa", r#"import "util.asa"; fn main(){return util.twice(2);}"#),
+    ("util.asa", r#"pub fn helper(a){return twice(a);} fn twice(a){return a + a;}"#),
+  ]);
+  assert_eq!(run(&dir), Err(AsaErrorKind::PrivateAccess{module: "util".to_string(), name: "util.twice".to_string()}));
+  fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn module_private_function_not_reachable_from_other_module() {
+  let dir = project("private_other", &[
+    ("main.asa", r#"import "a.asa"; fn main(){return a.f();}"#),
+    ("a.asa", r#"import "b.asa"; pub fn f(){return b.g();}"#),
+    ("b.asa", r#"fn g(){return 1;}"#),
+  ]);
+  assert_eq!(run(&dir), Err(AsaErrorKind::PrivateAccess{module: "b".to_string(), name: "b.g".to_string()}));
+  fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn module_pub_const() {
+  let dir = project("pub_const", &[
+    ("main.asa", r#"import "util.asa"; fn main(){return util.LIMIT + util.twice();}"#),
+    ("util.asa", r#"pub const LIMIT = 5; pub fn twice(){return LIMIT + LIMIT;}"#),
+  ]);
+  assert_eq!(run(&dir), Ok(Value::Number(15)));
+  fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn module_private_const() {
+  let dir = project("private_const", &[
+    ("main.asa", r#"import "util.asa"; fn main(){return util.LIMIT;}"#),
+    ("util.asa", r#"const LIMIT = 5;"#),
+  ]);
+  assert_eq!(run(&dir), Err(AsaErrorKind::PrivateAccess{module: "util".to_string(), name: "util.LIMIT".to_string()}));
+  fs::remove_dir_all(dir).unwrap();
+}
@@ -12,7 +12,7 @@ macro_rules! test {
         Ok((tokens,tree)) => {
           println!("Test: {:?}\nExpected: {:?}\nActual: {:?}", stringify!($func), $test, tree);
           assert_eq!(tokens.is_done(),true);
-          assert_eq!(tree,$test)
+          assert_eq!(tree.without_spans(),$test)
         },
         Err(e) => {
           println!("Test: {:?}\nError: {:?}", stringify!($func), e);
@@ -24,75 +24,76 @@ macro_rules! test {
   )
 }
 // test name, test string, combinator,  expected result
-test!(parser_ident, r#"hello"#, identifier, Identifier{value: vec![104, 101, 108, 108, 111]});
+test!(parser_ident, r#"hello"#, identifier, Identifier{value: Symbol::new("hello"), span: Span::default()});
 test!(parser_number, r#"123"#, number, Number{value: 123});
 test!(parser_bool, r#"true"#, boolean, Bool{value: true});
 test!(parser_string, r#""hello""#, string, String{value: "hello".to_string()});
-test!(parser_function_call, r#"foo()"#, function_call, FunctionCall{name: vec![102, 111, 111], children: vec![
+test!(parser_function_call, r#"foo()"#, function_call, FunctionCall{name: Symbol::new("foo"), children: vec![
   FunctionArguments{ children: vec![
   ]}
-]});
-test!(parser_function_call_one_arg, r#"foo(a)"#, function_call, FunctionCall{name: vec![102, 111, 111], children: vec![
+], span: Span::default()});
+test!(parser_function_call_one_arg, r#"foo(a)"#, function_call, FunctionCall{name: Symbol::new("foo"), children: vec![
   FunctionArguments{ children: vec![
-    Expression { children: vec![Identifier { value: vec![97] }]}
+    Expression { children: vec![Identifier { value: Symbol::new("a"), span: Span::default() }]}
   ]}
-]});
+], span: Span::default()});
 test!(parser_variable_define_number, r#"let a = 123"#, variable_define, VariableDefine{children: vec![
-  Identifier { value: vec![97] },
+  Identifier { value: Symbol::new("a"), span: Span::default() },
   Expression { children: vec![Number{value: 123 }]}
 ]});
 test!(parser_variable_define_bool, r#"let a = true"#, variable_define, VariableDefine{children: vec![
-  Identifier { value: vec![97] },
+  Identifier { value: Symbol::new("a"), span: Span::default() },
   Expression { children: vec![Bool{value: true}]}
 ]});
 test!(parser_math_expr, r#"1+1"#, math_expression, MathExpression {name: vec![97, 100, 100], children: vec![
   Number{value: 1},
   Number{value: 1}
-]});
+], span: Span::default()});
 test!(parser_variable_define_math_expr, r#"let a = 1 + 1"#, variable_define, VariableDefine{children: vec![
-  Identifier { value: vec![97] },
+  Identifier { value: Symbol::new("a"), span: Span::default() },
   Expression { children: vec![
     MathExpression {name: vec![97, 100, 100], children: vec![
       Number{value: 1},
       Number{value: 1}
-    ]}
+    ], span: Span::default()}
   ]}
 ]});
 test!(parser_variable_function_call, r#"let a = foo()"#, variable_define, VariableDefine{children: vec![
-  Identifier { value: vec![97] },
+  Identifier { value: Symbol::new("a"), span: Span::default() },
   Expression { children: vec![
-    FunctionCall{name: vec![102, 111, 111], children: vec![
+    FunctionCall{name: Symbol::new("foo"), children: vec![
       FunctionArguments{ children: vec![
       ]}
-    ]}
+    ], span: Span::default()}
   ]}
 ]});
 test!(parser_function_define, r#"fn a(){return 1;}"#, function_define, FunctionDefine{
-  name: vec![97],
+  name: Symbol::new("a"),
   children: vec![
     FunctionArguments{ children: vec![] },
     FunctionStatements{ children: vec![
       FunctionReturn{ children: vec![ 
         Expression { children: vec![Number{value: 1 }]}
-      ]}
+      ], span: Span::default()}
     ]}
-  ]
+  ],
+  span: Span::default()
 });
 
 test!(parser_if_else, r#"if x > y {return 1;} else if x == y {return 2;} else {return 3}"#, if_else, IfElse{
   children: vec![
     Condition{ children: vec![
-      Identifier{value: vec![120]},  
-      Operator{value: vec![62]},  
-      Identifier{value: vec![121]}
+      Identifier{value: Symbol::new("x"), span: Span::default()},  
+      Operator{value: vec![62], span: Span::default()},  
+      Identifier{value: Symbol::new("y"), span: Span::default()}
     ]},
     ReturnIfNum{ children: vec![
       Number{value: 1}  
     ]},
     Condition{ children: vec![
-      Identifier{value: vec![120]},  
-      Operator{value: vec![b'=', b'=']},  
-      Identifier{value: vec![121]}
+      Identifier{value: Symbol::new("x"), span: Span::default()},  
+      Operator{value: vec![b'=', b'='], span: Span::default()},  
+      Identifier{value: Symbol::new("y"), span: Span::default()}
     ]},
     ReturnIfNum{ children: vec![
       Number{value: 2} 
@@ -107,9 +108,9 @@ test!(parser_if_else, r#"if x > y {return 1;} else if x == y {return 2;} else {r
 test!(parser_if_expression, r#"if x > y {return false;} else {return true;}"#, if_expression, IfExpression{
   children: vec![
     Condition{ children: vec![
-      Identifier{value: vec![120]}, 
-      Operator{value: vec![62]},     
-      Identifier{value: vec![121]}
+      Identifier{value: Symbol::new("x"), span: Span::default()}, 
+      Operator{value: vec![62], span: Span::default()},     
+      Identifier{value: Symbol::new("y"), span: Span::default()}
     ]},
     ReturnIf{ children: vec![
       Bool{value: false}
@@ -117,7 +118,8 @@ test!(parser_if_expression, r#"if x > y {return false;} else {return true;}"#, i
     ReturnIf{ children: vec![
       Bool{value: true}
     ]}
-  ]
+  ],
+  span: Span::default()
 });
 
 test!(parser_if_multi_line, r#"if true {
@@ -139,7 +141,7 @@ test!(parser_if_multi_line, r#"if true {
 
 test!(parser_if_assign, r#"let x = if true {return false;} else {return true;}"#, if_assign, IfAssign{
  children: vec![
-  Identifier{value: vec![120]},
+  Identifier{value: Symbol::new("x"), span: Span::default()},
   Bool{value: true},
       ReturnIf{children: vec![Bool{value: false}]},
       ReturnIf{children: vec![Bool{value: true}]}
@@ -147,25 +149,225 @@ test!(parser_if_assign, r#"let x = if true {return false;} else {return true;}"#
 });
 
 test!(parser_function_define_multi_statements, r#"fn add(a,b){let x=a+b;return x;}"#, function_define, FunctionDefine{
-  name: vec![97, 100, 100],
+  name: Symbol::new("add"),
   children: vec![
     FunctionArguments{ children: vec![
-      Expression { children: vec![Identifier { value: vec![97] }] },
-      Expression { children: vec![Identifier { value: vec![98] }] },
+      Expression { children: vec![Identifier { value: Symbol::new("a"), span: Span::default() }] },
+      Expression { children: vec![Identifier { value: Symbol::new("b"), span: Span::default() }] },
     ] },
     FunctionStatements{ children: vec![
       VariableDefine{children: vec![
-        Identifier { value: vec![120] },
+        Identifier { value: Symbol::new("x"), span: Span::default() },
         Expression { children: vec![
           MathExpression {name: vec![97, 100, 100], children: vec![
-            Identifier{value: vec![97]},
-            Identifier{value: vec![98]}
-          ]}
+            Identifier{value: Symbol::new("a"), span: Span::default()},
+            Identifier{value: Symbol::new("b"), span: Span::default()}
+          ], span: Span::default()}
         ]}
       ]},
       FunctionReturn{ children: vec![ 
-        Expression { children: vec![Identifier{value: vec![120] }]}
-      ]}
+        Expression { children: vec![Identifier{value: Symbol::new("x"), span: Span::default() }]}
+      ], span: Span::default()}
     ]}
-  ]
+  ],
+  span: Span::default()
 });
+test!(parser_string_text, r#""1 + 2 = {}!""#, string, String{value: "1 + 2 = {}!".to_string()});
+test!(parser_string_escapes, r#""say \"hi\"\n""#, string, String{value: "say \"hi\"\n".to_string()});
+test!(parser_ident_underscore, r#"starts_with"#, identifier, Identifier{value: Symbol::new("starts_with"), span: Span::default()});
+test!(parser_math_expr_call, r#"len(a)+1"#, math_expression, MathExpression {name: vec![97, 100, 100], children: vec![
+  FunctionCall{name: Symbol::new("len"), children: vec![
+    FunctionArguments{ children: vec![
+      Expression { children: vec![Identifier { value: Symbol::new("a"), span: Span::default() }]}
+    ]}
+  ], span: Span::default()},
+  Number{value: 1}
+], span: Span::default()});
+test!(parser_import, r#"import "util.asa";"#, import, Import{path: "util.asa".to_string(), span: Span::default()});
+test!(parser_qualified_call, r#"util.helper()"#, function_call, FunctionCall{name: Symbol::new("util.helper"), children: vec![
+  FunctionArguments{ children: vec![
+  ]}
+], span: Span::default()});
+test!(parser_export, r#"pub fn a(){return 1;}"#, export, Export{children: vec![
+  FunctionDefine{
+    name: Symbol::new("a"),
+    children: vec![
+      FunctionArguments{ children: vec![] },
+      FunctionStatements{ children: vec![
+        FunctionReturn{ children: vec![
+          Expression { children: vec![Number{value: 1 }]}
+        ], span: Span::default()}
+      ]}
+    ],
+    span: Span::default()
+  }
+]});
+test!(parser_const_define, r#"const A = 1;"#, const_define, ConstDefine{children: vec![
+  Identifier { value: Symbol::new("A"), span: Span::default() },
+  Expression { children: vec![Number{value: 1}]}
+]});
+test!(parser_function_define_annotated, r#"fn f(a: num, b) -> list<str> {return b;}"#, function_define, FunctionDefine{
+  name: Symbol::new("f"),
+  children: vec![
+    FunctionArguments{ children: vec![
+      Expression { children: vec![Identifier { value: Symbol::new("a"), span: Span::default() }, TypeAnnotation{ value: Type::Num }] },
+      Expression { children: vec![Identifier { value: Symbol::new("b"), span: Span::default() }] },
+    ] },
+    FunctionStatements{ children: vec![
+      FunctionReturn{ children: vec![
+        Expression { children: vec![Identifier{value: Symbol::new("b"), span: Span::default() }]}
+      ], span: Span::default()}
+    ]},
+    TypeAnnotation{ value: Type::List(Box::new(Type::Str)) },
+  ],
+  span: Span::default()
+});
+test!(parser_throw, r#"throw "bad""#, throw, Throw{children: vec![
+  Expression { children: vec![String{value: "bad".to_string()}]}
+], span: Span::default()});
+test!(parser_try_catch, r#"try {throw 1;} catch e {} finally {return e;}"#, try_catch, Try{children: vec![
+  FunctionStatements{ children: vec![
+    Throw{ children: vec![Expression { children: vec![Number{value: 1}]}], span: Span::default()}
+  ]},
+  Identifier { value: Symbol::new("e"), span: Span::default() },
+  FunctionStatements{ children: vec![] },
+  FunctionStatements{ children: vec![
+    FunctionReturn{ children: vec![
+      Expression { children: vec![Identifier{value: Symbol::new("e"), span: Span::default() }]}
+    ], span: Span::default()}
+  ]},
+], span: Span::default()});
+test!(parser_field_access, r#"e.kind.name"#, value, FieldAccess{children: vec![
+  FieldAccess{children: vec![Identifier{value: Symbol::new("e"), span: Span::default()}], name: Symbol::new("kind"), span: Span::default()},
+], name: Symbol::new("name"), span: Span::default()});
+test!(parser_condition_values, r#"1 > true"#, condition, Condition{ children: vec![
+  Number{value: 1},
+  Operator{value: vec![62], span: Span::default()},
+  Bool{value: true}
+]});
+
+// Tokens are shared between the views the parser takes of them, so a long program parses in linear time.
+#[test]
+fn parser_large_program() {
+  let source = (0..3000).map(|i| format!("fn f{}(a, b) {{\n  let x = a + b;\n  return x - {};\n}}\n", i, i)).collect::<Vec<_>>().concat();
+  let (tokens, tree) = program(lex(&source)).unwrap();
+  assert!(tokens.is_done());
+  assert_eq!(tree.children().len(), 3000);
+}
+
+// Each part that can't be parsed becomes an error node, and parsing carries on after the next ; or }.
+#[test]
+fn parser_recovers_at_statement_boundaries() {
+  let source = "fn f(a) {\n  let = 1;\n  return a;\n}\nlet x = ;\nfn g() {\n  if {return 1;}\n  return 2;\n}\nconst C = 1;";
+  let (tokens, tree) = program(lex(source)).unwrap();
+  assert!(tokens.is_done());
+  let kinds: Vec<&str> = tree.children().iter().map(|node| match node {
+    FunctionDefine{..} => "fn",
+    ConstDefine{..} => "const",
+    Error{..} => "error",
+    _ => "other",
+  }).collect();
+  assert_eq!(kinds, vec!["fn", "error", "fn", "const"]);
+  let errors: Vec<_> = syntax_errors(&tree).iter().map(|error| error.to_string()).collect();
+  assert_eq!(errors, vec![
+    "expected an identifier, found '=' at 2:7",
+    "expected an expression or 'let', found ';' at 5:9",
+    "expected a value, found '{' at 7:6",
+  ]);
+  assert_eq!(tree.children()[0].children()[1].children().len(), 2);
+}
+
+#[test]
+fn parser_parse_fails_on_unconsumed_input() {
+  match parse(lex("fn main() {return 1;}\n}\n)")) {
+    Err(errors) => {
+      let errors: Vec<_> = errors.iter().map(|error| error.to_string()).collect();
+      assert_eq!(errors, vec![
+        "expected a definition or statement, found '}' at 2:1",
+        "expected a definition or statement, found ')' at 3:1",
+      ]);
+    },
+    Ok(tree) => panic!("parsed {:?}", tree),
+  }
+  assert!(parse(lex("fn main() {return 1;}")).is_ok());
+}
+
+// Errors are reported where the parser got furthest, with what every alternative expected there.
+#[test]
+fn parser_expected_tokens() {
+  let messages = |source: &str| -> Vec<_> {
+    syntax_errors(&program(lex(source)).unwrap().1).iter().map(|error| error.to_string()).collect()
+  };
+  assert_eq!(messages("fn f() {\n  let a = 1;\n  return a let b = 2;\n}"), vec!["expected ';' after statement, found 'let' at 3:12"]);
+  assert_eq!(messages("fn f() {\n  return 1;\n  )\n}"), vec!["expected a statement or '}', found ')' at 3:3"]);
+  assert_eq!(messages("fn f() {return 1;"), vec!["expected '}' to end the function body, found end of input at 1:18"]);
+  assert_eq!(messages("fn f(a: thing) {return a;}"), vec!["expected a type, found 't' at 1:9"]);
+  assert_eq!(messages("const C = 1"), vec!["expected ';' after constant, found end of input at 1:12"]);
+  assert_eq!(messages("let x = 1 > ;"), vec!["expected a value, found ';' at 1:13"]);
+  assert_eq!(messages("fn f() {\n  let x = 99999999999;\n  return 2147483647;\n}"), vec!["number literal out of range at 2:11"]);
+  assert_eq!(messages(&format!("let x = 1{}.5;\nlet y = 1{}.5;", "0".repeat(309), "0".repeat(308))), vec!["number literal out of range at 1:9"]);
+  assert_eq!(messages("fn f() {\n  try {return 1;}\n  return 2;\n}"), vec!["expected 'catch' after the try block, found 'return' at 3:3"]);
+}
+
+#[test]
+fn parser_error_merges_alternatives() {
+  let error = match boolean(lex("5")) {
+    Err(nom::Err::Error(error)) => error,
+    other => panic!("parsed {:?}", other),
+  };
+  assert_eq!(error.expected, vec!["'true'", "'false'"]);
+  assert_eq!(error.to_string(), "expected 'true' or 'false', found '5' at 1:1");
+}
+
+#[test]
+fn parser_identifiers_starting_with_keywords() {
+  assert!(parse(lex("let tryCount = 3; let catches = 3; let thrower = 2; let finally_done = true;")).is_ok());
+}
+
+// Names are interned as they are parsed: symbols are equal only if they are the same copy, so each
+// name in the tree is the one symbol for it. A qualified name already knows its namespace.
+#[test]
+fn parser_interned_names() {
+  let tree = parse(lex("fn main() {\n  let a = util.helper(a);\n  return a;\n}")).unwrap().without_spans();
+  let statement = &tree.children()[0].children()[1].children()[0];
+  assert_eq!(*statement, VariableDefine{children: vec![
+    Identifier{value: Symbol::new("a"), span: Span::default()},
+    Expression{children: vec![FunctionCall{name: Symbol::new("util.helper"), children: vec![FunctionArguments{children: vec![
+      Expression{children: vec![Identifier{value: Symbol::new("a"), span: Span::default()}]},
+    ]}], span: Span::default()}]},
+  ]});
+  assert_eq!(Symbol::new("util.helper").parts(), Some((&Symbol::new("util"), &Symbol::new("helper"))));
+  assert_eq!(Symbol::new("a").parts(), None);
+}
+
+// Spans take part in the equality of trees, so two layouts of one program only compare equal without them.
+#[test]
+fn parser_spans() {
+  let tree = parse(lex("fn main() {\n  let abc = f(1);\n}")).unwrap();
+  let statement = &tree.children()[0].children()[1].children()[0];
+  assert_eq!(statement.children()[0], Identifier{value: Symbol::new("abc"), span: Span{start_line: 2, start_col: 7, end_line: 2, end_col: 9}});
+  assert_eq!(statement.children()[1].span(), Some(Span{start_line: 2, start_col: 13, end_line: 2, end_col: 13}));
+  let other = parse(lex("fn main() {let abc = f(1);}")).unwrap();
+  assert_ne!(tree, other);
+  assert_eq!(tree.without_spans(), other.without_spans());
+}
+
+#[test]
+fn parser_identifiers_ending_with_import() {
+  assert!(parse(lex("fn main(){let reimport = 1; return reimport;}")).is_ok());
+}
+
+#[test]
+fn parser_identifiers_ending_with_pub() {
+  assert!(parse(lex("fn main(){let epub = 3; return epub;}")).is_ok());
+}
+
+#[test]
+fn parser_identifiers_ending_with_const() {
+  assert!(parse(lex("fn main(){let myconst = 3; return myconst;}")).is_ok());
+}
+
+#[test]
+fn parser_identifiers_ending_with_keywords() {
+  assert!(parse(lex("fn main(){let entry = 1; let retry = 2; let overthrow = 3; return entry;}")).is_ok());
+}
@@ -0,0 +1,122 @@
+use asalang::*;
+
+// Parses a program and type checks it, returning each finding as its location and kind.
+fn typecheck_source(source: &str) -> Vec<(String, FindingKind)> {
+  let (tokens, tree) = program(lex(source)).unwrap();
+  assert!(tokens.is_done());
+  typecheck(&tree).into_iter().map(|finding| (finding.span.to_string(), finding.kind)).collect()
+}
+
+fn mismatch(expected: Type, found: Type) -> FindingKind {
+  FindingKind::TypeMismatch{expected, found}
+}
+
+#[test]
+fn typecheck_compare_num_and_bool() {
+  assert_eq!(typecheck_source("1 > true"), vec![("1:3".to_string(), mismatch(Type::Num, Type::Bool))]);
+}
+
+#[test]
+fn typecheck_math_on_bool() {
+  assert_eq!(typecheck_source("5 - false"), vec![("1:3".to_string(), mismatch(Type::Num, Type::Bool))]);
+}
+
+#[test]
+fn typecheck_inconsistent_branches() {
+  assert_eq!(typecheck_source("if true {return 1;} else {return true;}"), vec![
+    ("1:1".to_string(), FindingKind::BranchMismatch{then: Type::Num, otherwise: Type::Bool}),
+  ]);
+}
+
+#[test]
+fn typecheck_if_test_must_be_bool() {
+  assert_eq!(typecheck_source("let x = 1;\nif x {return 1;} else {return 2;}"), vec![("2:4".to_string(), mismatch(Type::Bool, Type::Num))]);
+}
+
+#[test]
+fn typecheck_valid_program() {
+  assert_eq!(typecheck_source(r#"
+fn add(a: num, b: num) -> num {return a + b;}
+fn greet(name) {return "hi " + name;}
+fn main() {
+  let x = add(1, 2.5);
+  let big = if x > 3 {return "big";} else {return greet("small");};
+  return big;
+}"#), vec![]);
+}
+
+#[test]
+fn typecheck_annotated_return() {
+  assert_eq!(typecheck_source("fn f(a: str) -> num {\n  return a;\n}"), vec![("2:10".to_string(), mismatch(Type::Num, Type::Str))]);
+}
+
+#[test]
+fn typecheck_annotated_argument() {
+  assert_eq!(typecheck_source(r#"fn f(a: num){return a;} fn main(){return f("x");}"#), vec![("1:42".to_string(), mismatch(Type::Num, Type::Str))]);
+}
+
+#[test]
+fn typecheck_infers_parameters() {
+  assert_eq!(typecheck_source("fn dec(a){return a - 1;}\nfn main(){let t = true; return dec(t);}"), vec![("2:36".to_string(), mismatch(Type::Num, Type::Bool))]);
+}
+
+#[test]
+fn typecheck_infers_locals() {
+  assert_eq!(typecheck_source(r#"fn main(){let s = "a"; return s - 1;}"#), vec![("1:31".to_string(), mismatch(Type::Num, Type::Str))]);
+}
+
+#[test]
+fn typecheck_generic_function() {
+  assert_eq!(typecheck_source("fn id(a){return a;} fn main(){let n = id(1); let b = id(true); return n;}"), vec![]);
+}
+
+// A function defined below its caller is checked before the call, so it is just as generic.
+#[test]
+fn typecheck_generic_function_defined_after_caller() {
+  assert_eq!(typecheck_source(r#"fn main(){let a = id(1); let b = id("x"); return a;} fn id(x){return x;}"#), vec![]);
+  assert_eq!(typecheck_source("fn main(){return dec(true);}\nfn dec(a){return a - 1;}"), vec![("1:18".to_string(), mismatch(Type::Num, Type::Bool))]);
+}
+
+#[test]
+fn typecheck_recursive_functions() {
+  assert_eq!(typecheck_source("fn even(n){return if n == 0 {return true;} else {return odd(n - 1);};} fn odd(n){return if n == 0 {return false;} else {return even(n - 1);};} fn main(){return even(4);}"), vec![]);
+}
+
+#[test]
+fn typecheck_invalid_operand() {
+  assert_eq!(typecheck_source("fn f(a: bool, b: bool){return a + b;}"), vec![
+    ("1:33".to_string(), FindingKind::InvalidOperand{operator: "+".to_string(), found: Type::Bool}),
+  ]);
+}
+
+#[test]
+fn typecheck_compound_annotations() {
+  assert_eq!(typecheck_source(r#"
+fn first(xs: list<str>, counts: map<num>, keep: fn(str) -> bool) -> list<str> {return xs;}
+fn f(xs: list<num>) -> list<str> {return first(xs, 1, 2);}"#), vec![
+    ("3:42".to_string(), mismatch(Type::Map(Box::new(Type::Num)), Type::Num)),
+    ("3:42".to_string(), mismatch(Type::Function(vec![Type::Str], Box::new(Type::Bool)), Type::Num)),
+    ("3:48".to_string(), mismatch(Type::List(Box::new(Type::Str)), Type::List(Box::new(Type::Num)))),
+  ]);
+}
+
+#[test]
+fn typecheck_type_display() {
+  let ty = Type::Function(vec![Type::List(Box::new(Type::Num)), Type::Map(Box::new(Type::Str))], Box::new(Type::Bool));
+  assert_eq!(ty.to_string(), "fn(list<num>, map<str>) -> bool");
+}
+
+#[test]
+fn typecheck_types_of_names() {
+  let (_, tree) = program(lex("fn f(a) {let b = a - 1; return b;} const C = f(2);")).unwrap();
+  let types: Vec<(String, String)> = types(&tree).into_iter().map(|(span, ty)| (span.to_string(), ty.to_string())).collect();
+  assert_eq!(types, vec![
+    ("1:4".to_string(), "fn(num) -> num".to_string()),
+    ("1:6".to_string(), "num".to_string()),
+    ("1:14".to_string(), "num".to_string()),
+    ("1:18".to_string(), "num".to_string()),
+    ("1:32".to_string(), "num".to_string()),
+    ("1:42".to_string(), "num".to_string()),
+    ("1:46".to_string(), "fn(num) -> num".to_string()),
+  ]);
+}
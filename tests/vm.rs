@@ -0,0 +1,107 @@
+extern crate asalang;
+
+use asalang::*;
+
+// Runs a program's main function on the given back end.
+fn run_on(backend: Backend, source: &str) -> Result<Value,AsaErrorKind> {
+  let (tokens, tree) = program(lex(source)).unwrap();
+  assert!(tokens.is_done());
+  let mut interpreter = Interpreter::new();
+  interpreter.set_backend(backend);
+  interpreter.exec(&tree)?;
+  interpreter.start_main(vec![])
+}
+
+// Checks that the tree-walker and the bytecode VM both produce the expected result.
+macro_rules! test_backends {
+  ($func:ident, $test:tt, $expected:expr) => (
+    #[test]
+    fn $func() {
+      assert_eq!(run_on(Backend::TreeWalker, $test), $expected);
+      assert_eq!(run_on(Backend::Bytecode, $test), $expected);
+    }
+  )
+}
+
+test_backends!(vm_return_literal, r#"fn main(){return 5;}"#, Ok(Value::Number(5)));
+test_backends!(vm_locals, r#"fn main(){let x = 1; let y = x + 2; let x = y + x; return x;}"#, Ok(Value::Number(4)));
+test_backends!(vm_last_statement_is_result, r#"fn main(){return 1; let x = 2;}"#, Ok(Value::Number(2)));
+test_backends!(vm_arguments, r#"fn minus(a,b){return a - b;} fn main(){return minus(10, 3);}"#, Ok(Value::Number(7)));
+test_backends!(vm_duplicate_parameters, r#"fn f(a,a){return a;} fn main(){return f(1, 2);}"#, Ok(Value::Number(2)));
+test_backends!(vm_strings, r#"fn main(){let s = "a" + "b"; return s + "c";}"#, Ok(Value::String("abc".to_string())));
+test_backends!(vm_floats, r#"fn main(){return 1.5 + 2;}"#, Ok(Value::Float(3.5)));
+test_backends!(vm_constants, r#"const LIMIT = 10; fn main(){let x = LIMIT - 1; return x;}"#, Ok(Value::Number(9)));
+test_backends!(vm_if_expression, r#"fn main(){let x = 3; return if x > 2 {return "big";} else {return "small";};}"#, Ok(Value::String("big".to_string())));
+test_backends!(vm_if_else, r#"fn size(n){return if n > 9 {return "large";} else if n > 0 {return "small";} else {return "none";};} fn main(){let a = size(0); let b = size(5); let c = a + b; return c + size(12);}"#, Ok(Value::String("nonesmalllarge".to_string())));
+test_backends!(vm_recursion, r#"fn sum(n){return if n == 0 {return 0;} else {return n + sum(n - 1);};} fn main(){return sum(20);}"#, Ok(Value::Number(210)));
+test_backends!(vm_natives, r#"fn main(){return len("abc") + max(1, 4);}"#, Ok(Value::Number(7)));
+test_backends!(vm_undefined_variable, r#"fn main(){return y;}"#, Err(AsaErrorKind::UndefinedVariable("y".to_string())));
+test_backends!(vm_undefined_function, r#"fn main(){return foo(1);}"#, Err(AsaErrorKind::UndefinedFunction("foo".to_string())));
+test_backends!(vm_arity_mismatch, r#"fn f(a){return a;} fn main(){return f(1, 2);}"#, Err(AsaErrorKind::ArityMismatch{name: "f".to_string(), expected: Arity::Exact(1), found: 2}));
+test_backends!(vm_type_error, r#"fn main(){return 1 > "a";}"#, Err(AsaErrorKind::type_error(">", "num", "str")));
+test_backends!(vm_if_test_not_bool, r#"fn main(){let x = 1; return if x {return 1;} else {return 2;};}"#, Err(AsaErrorKind::type_error("if", "bool", "num")));
+test_backends!(vm_overflow, r#"fn main(){let max = 2147483647; return max + 1;}"#, Err(AsaErrorKind::NumberOverflow));
+test_backends!(vm_falls_back_to_tree_walker, r#"fn main(){let x = if true {return 1;} else {return 2;}; return x;}"#, Ok(Value::Number(1)));
+test_backends!(vm_catch_error_from_compiled_function, r#"fn f(a){return a - true;} fn main(){try {f(1);} catch e {return e.kind;}}"#, Ok(Value::String("TypeError".to_string())));
+test_backends!(vm_field_of_local_map, r#"fn kind(e){return e.kind;} fn main(){try {return y;} catch e {return kind(e);}}"#, Ok(Value::String("UndefinedVariable".to_string())));
+test_backends!(vm_missing_field, r#"fn kind(e){return e.nope;} fn main(){try {return y;} catch e {return kind(e);}}"#, Err(AsaErrorKind::UndefinedVariable("e.nope".to_string())));
+test_backends!(vm_field_of_number, r#"fn kind(e){return e.kind;} fn main(){return kind(1);}"#, Err(AsaErrorKind::type_error(".", "map", "num")));
+test_backends!(vm_uncaught_throw, r#"fn f(){throw "oops";} fn main(){return f();}"#, Err(AsaErrorKind::Thrown(Value::String("oops".to_string()))));
+
+#[test]
+fn vm_backend_defaults_to_tree_walker() {
+  let mut interpreter = Interpreter::new();
+  assert_eq!(interpreter.backend(), Backend::TreeWalker);
+  interpreter.set_backend(Backend::Bytecode);
+  assert_eq!(interpreter.backend(), Backend::Bytecode);
+}
+
+// Both back ends record the same backtrace, innermost call first.
+#[test]
+fn vm_backtrace() {
+  let source = "fn inner(a){\n  return a - true;\n}\nfn outer(a){\n  return inner(a + 1);\n}\nfn main(){return outer(1);}";
+  for backend in [Backend::TreeWalker, Backend::Bytecode] {
+    let (_, tree) = program(lex(source)).unwrap();
+    let mut interpreter = Interpreter::new();
+    interpreter.set_backend(backend);
+    interpreter.exec(&tree).unwrap();
+    assert!(interpreter.start_main(vec![]).is_err());
+    let backtrace: Vec<String> = interpreter.backtrace().iter().map(CallSite::to_string).collect();
+    assert_eq!(backtrace, ["in inner, called at 5:10", "in outer, called at 7:18", "in main"]);
+  }
+}
+
+// Runaway recursion stops at the call depth limit with a StackError and a backtrace, instead of
+// overflowing the native stack. The default limit fits the stack of a test thread.
+#[test]
+fn vm_runaway_recursion() {
+  let sources = ["fn down(n){return down(n + 1);}\nfn main(){return down(0);}", "fn down(n){return if n == 0 {return 0;} else {return 1 + down(n + 1);};}\nfn main(){return down(1);}"];
+  for (source, backend) in sources.iter().flat_map(|source| [(source, Backend::TreeWalker), (source, Backend::Bytecode)]) {
+    let (_, tree) = program(lex(source)).unwrap();
+    let mut interpreter = Interpreter::new();
+    interpreter.set_backend(backend);
+    interpreter.exec(&tree).unwrap();
+    assert_eq!(interpreter.start_main(vec![]), Err(AsaErrorKind::StackError));
+    let backtrace = interpreter.backtrace();
+    // Every call in progress, and the one that was refused.
+    assert_eq!(backtrace.len(), MAX_CALL_DEPTH + 1);
+    assert_eq!(backtrace[0].function, "down");
+    assert_eq!(backtrace[MAX_CALL_DEPTH].to_string(), "in main");
+    // The interpreter is usable again afterwards.
+    assert_eq!(interpreter.start_main(vec![]), Err(AsaErrorKind::StackError));
+  }
+}
+
+#[test]
+fn vm_call_depth_limit() {
+  for backend in [Backend::TreeWalker, Backend::Bytecode] {
+    let (_, tree) = program(lex("fn sum(n){return if n == 0 {return 0;} else {return n + sum(n - 1);};} fn main(){return sum(10);}")).unwrap();
+    let mut interpreter = Interpreter::new();
+    interpreter.set_backend(backend);
+    interpreter.exec(&tree).unwrap();
+    interpreter.set_max_call_depth(11);
+    assert_eq!(interpreter.start_main(vec![]), Err(AsaErrorKind::StackError));
+    interpreter.set_max_call_depth(12);
+    assert_eq!(interpreter.start_main(vec![]), Ok(Value::Number(55)));
+  }
+}